@@ -8,11 +8,15 @@ NC='\033[0m' # No Color
 export PORT=12389
 export DIR=$(mktemp -d)
 export BOUNDARY="aaaaaaaaaaaaaaaaaaaa" # 20 a's
+export VIRTUAL_FILE=$(mktemp)
+
+echo "hello from memory" > $VIRTUAL_FILE
 
 echo "Starting hypershare"
 
 cargo build
-cargo run -- -d $DIR -p $PORT -m "127.0.0.1" -u --headless | sed -e 's/^/ >>> hypershare: /g' &
+cargo run -- -d $DIR -p $PORT -m "127.0.0.1" -u --headless \
+    --virtual-file /hello.txt=$VIRTUAL_FILE --deny-dotdot-encoded --show-hits --zip | sed -e 's/^/ >>> hypershare: /g' &
 
 sleep 1
 
@@ -69,6 +73,12 @@ templates/wellformed_post_request.sh test_small.img || errored
 echo "TEST: Small file with expectation... "
 templates/wellformed_post_request_with_continue.sh test_small.img || errored
 
+echo "TEST: 0B file with split delimeter... "
+templates/split_boundary_post_request.sh test_0b.img || errored
+
+echo "TEST: Small file with split delimeter... "
+templates/split_boundary_post_request.sh test_small.img || errored
+
 echo -e "\n.... GET + POST Requests (curl/wget) ...."
 
 echo "TEST: 1M file... "
@@ -83,6 +93,1162 @@ templates/curl_wget_twoway.sh test_small.img || errored
 echo "TEST: File with spaces... "
 templates/curl_wget_twoway.sh "file with spaces and %s" || errored
 
+echo -e "\n.......... In-Memory Files .........."
+
+echo "TEST: Virtual file... "
+templates/raw_request.sh "GET /hello.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^hello from memory$" || errored
+
+echo "TEST: Virtual file with range... "
+templates/raw_request.sh "GET /hello.txt HTTP/1.1\r\nHost: localhost\r\nRange: bytes=6-9\r\n\r\n" "^from$" || errored
+
+echo -e "\n......... OPTIONS Requests ........."
+
+echo "TEST: OPTIONS *... "
+templates/raw_request.sh "OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Allow: GET, HEAD, POST, PUT, OPTIONS, PROPFIND$" || errored
+
+echo "TEST: OPTIONS with Max-Forwards: 0... "
+templates/raw_request.sh "OPTIONS /missing HTTP/1.1\r\nHost: localhost\r\nMax-Forwards: 0\r\n\r\n" "^HTTP/1.1 200 OK$" || errored
+
+echo -e "\n........ Traversal Attempts ........"
+
+echo "TEST: Literal traversal... "
+templates/raw_request.sh "GET /../etc/passwd HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: Encoded traversal... "
+templates/raw_request.sh "GET /%2e%2e/etc/passwd HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: Double-encoded traversal... "
+templates/raw_request.sh "GET /%252e%252e/etc/passwd HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo -e "\n....... Directory Listings ........."
+
+mkdir -p $DIR/sort_test/zz_dir
+touch $DIR/sort_test/aa_file
+
+echo "TEST: Directories listed first... "
+templates/raw_request.sh "GET /sort_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "zz_dir.*aa_file" || errored
+
+echo -e "\n.......... PUT Requests ..........."
+
+echo "TEST: Resumable PUT, first range... "
+templates/raw_request.sh "PUT /put_test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nContent-Range: bytes 0-4/15\r\n\r\nhello" "^Range: bytes=0-4$" || errored
+
+echo "TEST: Resumable PUT, non-contiguous range... "
+templates/raw_request.sh "PUT /put_test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nContent-Range: bytes 10-14/15\r\n\r\nworld" "^Range: bytes=0-4,10-14$" || errored
+
+echo "TEST: Resumable PUT, overlapping range... "
+templates/raw_request.sh "PUT /put_test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\nContent-Range: bytes 3-6/15\r\n\r\nxxxx" "^HTTP/1.1 416" || errored
+
+echo "TEST: Resumable PUT, final range... "
+templates/raw_request.sh "PUT /put_test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nContent-Range: bytes 5-9/15\r\n\r\n, big" "^HTTP/1.1 201" || errored
+
+echo "TEST: Resumable PUT, assembled file... "
+templates/raw_request.sh "GET /put_test.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^hello, bigworld$" || errored
+
+echo "TEST: Resumable PUT pieces can't overlap ones still being received... "
+templates/put_in_flight.sh || errored
+
+(echo -en "PUT /put_aborted.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello"; sleep 1) | nc -t localhost $PORT > /dev/null
+
+echo "TEST: PUT can be sent again after an aborted one... "
+templates/raw_request.sh "PUT /put_aborted.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhelloworld" "^HTTP/1.1 201" || errored
+
+(echo -en "PUT /put_aborted_piece.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\nContent-Range: bytes 0-9/20\r\n\r\nhello"; sleep 1) | nc -t localhost $PORT > /dev/null
+
+echo "TEST: Aborted first piece of a resumable PUT leaves no file behind... "
+templates/raw_request.sh "GET /put_aborted_piece.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+rm -f $DIR/put_aborted.txt
+
+echo "TEST: Request pipelined after a PUT body is served... "
+templates/raw_request.sh "PUT /put_pipelined.txt HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 5\r\n\r\nhelloGET /put_pipelined.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" "HTTP/1.1 200 OK" || errored
+
+big_body=$(head -c 10000 /dev/zero | tr '\0' 'x')
+
+echo "TEST: Request after a PUT body larger than the request buffer is served... "
+templates/raw_request.sh "PUT /put_pipelined_big.txt HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 10000\r\n\r\n${big_body}GET /put_pipelined.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" "HTTP/1.1 200 OK" || errored
+
+rm -f $DIR/put_pipelined.txt $DIR/put_pipelined_big.txt
+
+echo -e "\n.......... Bind Retrying ..........."
+
+echo "TEST: Bind after port is released... "
+templates/bind_retry.sh test_small.img || errored
+
+echo -e "\n........... Hit Counting ..........."
+
+mkdir -p $DIR/hits_test
+echo ":)" > $DIR/hits_test/popular.txt
+echo ":(" > $DIR/hits_test/unpopular.txt
+templates/raw_request.sh "GET /hits_test/popular.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^:\)$" > /dev/null
+templates/raw_request.sh "GET /hits_test/popular.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^:\)$" > /dev/null
+
+echo "TEST: File served twice... "
+templates/raw_request.sh "GET /hits_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "popular.txt<.*Hits: 2<.*unpopular.txt<.*Hits: 0<" || errored
+
+echo -e "\n........... Expectations ..........."
+
+echo "TEST: Unknown expectation... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nExpect: 200-ok\r\n\r\n" "^HTTP/1.1 417" || errored
+
+echo "TEST: 100-continue expectation... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo "TEST: PUT gets 100 Continue... "
+templates/put_continue.sh 100-continue || errored
+
+echo "TEST: 100-continue expectation is case-insensitive for PUT... "
+templates/put_continue.sh 100-Continue || errored
+
+echo "TEST: 100-continue expectation is case-insensitive for POST... "
+templates/wellformed_post_request_with_continue.sh test_small.img 100-Continue || errored
+
+echo -e "\n........... ZIP Downloads ..........."
+
+mkdir -p $DIR/zip_test/sub/empty_dir
+head -c 300000 /dev/urandom > $DIR/zip_test/random.bin
+echo "nested" > $DIR/zip_test/sub/nested.txt
+touch $DIR/zip_test/sub/empty.txt
+
+echo "TEST: Directory as ZIP... "
+templates/zip_download.sh zip_test || errored
+
+echo "TEST: Range on a ZIP download gets the whole archive... "
+templates/zip_download.sh zip_test "bytes=0-99" || errored
+
+echo "TEST: ZIP link in listing... "
+templates/raw_request.sh "GET /zip_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "href=.\?zip.*Download as .zip" || errored
+
+echo "TEST: ZIP headers... "
+templates/raw_request.sh "HEAD /zip_test/?zip HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Disposition: attachment; filename=\"zip_test.zip\"$" || errored
+
+mkdir -p $DIR/zip_deep/1/2/3/4/5/6/7/8/9/10/11/12/13/14/15/16
+echo "TEST: ZIP of a deeply nested directory is refused... "
+templates/raw_request.sh "GET /zip_deep/?zip HTTP/1.1\r\nHost: localhost\r\n\r\n" "nested too deeply" "^HTTP/1.1 200" || errored
+
+mkdir -p $DIR/zip_many
+(cd $DIR/zip_many && seq 1 10001 | xargs touch)
+echo "TEST: ZIP of a directory with too many entries is refused... "
+templates/raw_request.sh "GET /zip_many/?zip HTTP/1.1\r\nHost: localhost\r\n\r\n" "too large to archive" "^HTTP/1.1 200" || errored
+
+echo -e "\n........... Upload Tokens ..........."
+
+echo "TEST: Form hidden without token... "
+templates/raw_request_with_opts.sh "-u --upload-token s3cret" "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "<form" || errored
+
+echo "TEST: Form shown with token... "
+templates/raw_request_with_opts.sh "-u --upload-token s3cret" "GET /?token=s3cret HTTP/1.1\r\nHost: localhost\r\n\r\n" "<form[^>]*action='\?token=s3cret'" || errored
+
+echo "TEST: POST rejected without token... "
+templates/raw_request_with_opts.sh "-u --upload-token s3cret" "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: 0\r\n\r\n" "^HTTP/1.1 403" || errored
+
+echo "TEST: POST accepted with token header... "
+templates/raw_request_with_opts.sh "-u --upload-token s3cret" "POST / HTTP/1.1\r\nHost: localhost\r\nX-Upload-Token: s3cret\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: 102\r\n\r\n--xyz\r\nContent-Disposition: form-data; filename=\"token_upload.txt\"\r\n\r\nuploaded with a token\r\n--xyz--\r\n" "^HTTP/1.1 201" || errored
+rm $DIR/token_upload.txt
+
+echo -e "\n........... Trailing Slashes ..........."
+
+echo "TEST: File with trailing slash... "
+templates/raw_request.sh "GET /test_small.img/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+echo "TEST: File with trailing slash, redirecting... "
+templates/raw_request_with_opts.sh "--redirect-file-slash" "GET /test_small.img/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Location: /test_small.img$" || errored
+
+echo -e "\n........... Missing Root ..........."
+
+echo "TEST: Root deleted while running... "
+templates/root_deleted.sh || errored
+
+echo -e "\n........... Request Bodies ..........."
+
+echo "TEST: GET with a body, then another request... "
+templates/two_requests.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 11\r\n\r\nhello " "worldGET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" 1 "^HTTP/1.1 200" || errored
+
+echo "TEST: Conflicting Content-Lengths are refused... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nhelloGET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" "^HTTP/1.1 400" "^HTTP/1.1 (200|501)" || errored
+
+echo "TEST: Repeated identical Content-Lengths are accepted... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello" "^HTTP/1.1 200" || errored
+
+echo "TEST: Content-Length with Transfer-Encoding is refused... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\nhello" "^HTTP/1.1 400" || errored
+
+echo "TEST: Body longer than --max-discard-bytes closes the connection... "
+templates/raw_request_with_opts.sh "--max-discard-bytes 1000" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 99999999999\r\n\r\nhello" "^Connection: close$" "^Connection: keep-alive$" || errored
+
+echo -e "\n........... Pipelining ..........."
+
+echo "TEST: Two pipelined requests... "
+templates/two_requests.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n" "GET /hello.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" 0 "^HTTP/1.1 200" || errored
+
+echo "TEST: Pipelined request after a GET body... "
+templates/two_requests.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 5\r\n\r\nhello" "GET /hello.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" 0 "^(:\)|hello from memory)$" || errored
+
+echo -e "\n........... Slow Requests ..........."
+
+echo "TEST: Trickled request is dropped... "
+templates/slow_request.sh "--min-request-rate 10/3" || errored
+
+echo "TEST: Fast request with a minimum rate... "
+templates/raw_request_with_opts.sh "--min-request-rate 10/3" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo -e "\n........... Dropping Privileges ..........."
+
+echo "TEST: Switching to the current user... "
+templates/raw_request_with_opts.sh "--user $(id -un) --group $(id -g)" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo "TEST: Unknown user... "
+templates/startup_error.sh "--user no-such-hypershare-user" "unknown user \`no-such-hypershare-user\`" || errored
+
+echo -e "\n........... Ranges ..........."
+
+echo "TEST: Range on a directory... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-10\r\n\r\n" "^HTTP/1.1 200" "^Content-Range" || errored
+
+echo "TEST: Full listing despite Range... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-10\r\n\r\n" "</html>$" || errored
+
+echo -e "\n........... Non-UTF-8 Paths ..........."
+
+echo "latin-1" > "$DIR/$(printf 'caf\xe9.txt')"
+echo "utf-8" > "$DIR/café.txt"
+
+echo "TEST: Non-UTF-8 filename... "
+templates/raw_request.sh "GET /caf%E9.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^latin-1$" || errored
+
+echo "TEST: Percent-encoded UTF-8 filename... "
+templates/raw_request.sh "GET /caf%C3%A9.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^utf-8$" || errored
+
+echo "TEST: Non-UTF-8 filename in a listing... "
+mkdir -p $DIR/nonutf8_test
+echo "latin-1" > "$DIR/nonutf8_test/$(printf 'caf\xe9.txt')"
+templates/raw_request.sh "GET /nonutf8_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "href='/nonutf8_test/caf%E9.txt'>caf" || errored
+
+echo -e "\n........... Staged Uploads ..........."
+
+echo "TEST: Upload staged on the same filesystem... "
+templates/staged_upload.sh test_1m.img $DIR/staging || errored
+
+if [[ -d /dev/shm ]]
+then
+    echo "TEST: Upload staged on another filesystem... "
+    templates/staged_upload.sh test_1m.img /dev/shm/hypershare-staging-$$ || errored
+fi
+
+echo -e "\n........... Upload Events ..........."
+
+echo "TEST: Completed upload is reported... "
+templates/upload_event.sh test_1m.img || errored
+
+echo -e "\n........... JSON Log ..........."
+
+echo "TEST: JSON log lines... "
+templates/json_log.sh test_1m.img || errored
+
+echo -e "\n........... HEAD with Range ..........."
+
+echo "TEST: HEAD with a Range matches GET... "
+templates/head_matches_get.sh /test_1m.img "bytes=100-199" || errored
+
+echo "TEST: HEAD with an open-ended Range matches GET... "
+templates/head_matches_get.sh /test_1m.img "bytes=1000-" || errored
+
+echo "TEST: HEAD on a file advertises ranges and its length... "
+templates/head_file.sh test_1m.img || errored
+
+echo -e "\n........... MD5 Sidecars ..........."
+
+echo "TEST: Sidecar written for 1M file... "
+templates/generate_md5.sh test_1m.img || errored
+
+echo "TEST: Sidecar written for 0B file... "
+templates/generate_md5.sh test_0b.img || errored
+
+echo "TEST: Sidecar written for small file... "
+templates/generate_md5.sh test_small.img || errored
+
+echo -e "\n........... Nested Index Files ..........."
+
+mkdir -p $DIR/docs/api $DIR/docs/noindex
+echo "<p>docs index</p>" > $DIR/docs/index.html
+echo "<p>api index</p>" > $DIR/docs/api/index.html
+echo ":)" > $DIR/docs/noindex/file.txt
+
+echo "TEST: Index file one level down... "
+templates/raw_request.sh "GET /docs/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^<p>docs index</p>$" || errored
+
+echo "TEST: Index file two levels down... "
+templates/raw_request.sh "GET /docs/api/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^<p>api index</p>$" || errored
+
+echo "TEST: Nested index file is served as HTML... "
+templates/raw_request.sh "GET /docs/api/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/html" || errored
+
+echo "TEST: Directory without an index is listed... "
+templates/raw_request.sh "GET /docs/noindex/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "file.txt</a>" || errored
+
+echo -e "\n........... Content-Type Overrides ..........."
+
+mkdir -p $DIR/ct_test
+echo ":)" > $DIR/ct_test/module.wasm
+echo ":)" > $DIR/ct_test/README
+echo ":)" > $DIR/ct_test/other.txt
+ct_opts="--content-type /ct_test/README:text/markdown --content-type *.wasm:application/wasm --content-type /ct_test/*:text/plain"
+
+echo "TEST: Glob forces application/wasm... "
+templates/raw_request_with_opts.sh "$ct_opts" "GET /ct_test/module.wasm HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: application/wasm$" || errored
+
+echo "TEST: Path-specific override... "
+templates/raw_request_with_opts.sh "$ct_opts" "GET /ct_test/README HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/markdown$" || errored
+
+echo "TEST: Later rule applies when earlier ones don't match... "
+templates/raw_request_with_opts.sh "$ct_opts" "GET /ct_test/other.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/plain$" || errored
+
+echo "TEST: No override without a matching rule... "
+templates/raw_request.sh "GET /ct_test/module.wasm HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "^Content-Type:" || errored
+
+echo -e "\n........... Request IDs ..........."
+
+echo "TEST: Request id in response and log, inbound id echoed... "
+templates/request_id.sh test_small.img || errored
+
+echo "TEST: Error responses carry a request id... "
+templates/raw_request.sh "GET /does-not-exist HTTP/1.1\r\nHost: localhost\r\n\r\n" "^X-Request-Id: [0-9a-f]+-[0-9a-f]+$" || errored
+
+echo -e "\n........... Symlinked Root ..........."
+
+echo "TEST: Symlinked root is warned about or refused... "
+templates/symlink_root.sh || errored
+
+echo -e "\n........... WebDAV PROPFIND ..........."
+
+mkdir -p $DIR/propfind_test/sub
+echo ":)" > $DIR/propfind_test/a.txt
+echo ":)" > "$DIR/propfind_test/b & c.txt"
+
+echo "TEST: PROPFIND on a directory lists its entries... "
+templates/propfind.sh /propfind_test/ 1 /propfind_test/ /propfind_test/a.txt "/propfind_test/b%20%26%20c.txt" /propfind_test/sub/ || errored
+
+echo "TEST: PROPFIND with Depth 0 describes only the directory... "
+templates/propfind.sh /propfind_test/ 0 /propfind_test/ || errored
+
+echo "TEST: PROPFIND on a file... "
+templates/propfind.sh /propfind_test/a.txt 1 /propfind_test/a.txt || errored
+
+echo "TEST: PROPFIND on a missing path... "
+templates/raw_request.sh "PROPFIND /propfind_test/missing HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+echo -e "\n........... Virtual Hosts ..........."
+
+mkdir -p $DIR/vhost_a $DIR/vhost_b
+echo "from a" > $DIR/vhost_a/same.txt
+echo "from b" > $DIR/vhost_b/same.txt
+echo "from default" > $DIR/same.txt
+vhost_opts="--vhost a.local:$DIR/vhost_a --vhost B.Local:$DIR/vhost_b"
+
+echo "TEST: First vhost serves its own file... "
+templates/raw_request_with_opts.sh "$vhost_opts" "GET /same.txt HTTP/1.1\r\nHost: a.local\r\n\r\n" "^from a$" || errored
+
+echo "TEST: Second vhost serves its own file, ignoring case and port... "
+templates/raw_request_with_opts.sh "$vhost_opts" "GET /same.txt HTTP/1.1\r\nHost: b.LOCAL:8080\r\n\r\n" "^from b$" || errored
+
+echo "TEST: Unknown host falls back to the default root... "
+templates/raw_request_with_opts.sh "$vhost_opts" "GET /same.txt HTTP/1.1\r\nHost: c.local\r\n\r\n" "^from default$" || errored
+
+echo "TEST: Vhost root can't be escaped... "
+templates/raw_request_with_opts.sh "$vhost_opts" "GET /../same.txt HTTP/1.1\r\nHost: a.local\r\n\r\n" "^HTTP/1.1 404" "^from" || errored
+
+echo -e "\n........... Empty Directories ..........."
+
+mkdir -p $DIR/empty_test
+
+echo "TEST: Empty directory shows a message instead of a table... "
+templates/raw_request.sh "GET /empty_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "This folder is empty\." "<table" || errored
+
+echo "TEST: Empty directory still shows the upload form... "
+templates/raw_request.sh "GET /empty_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "This folder is empty\..*<form" || errored
+
+echo -e "\n........... CORS ..........."
+
+cors_opts="--cors-origin * --cors-credentials --cors-expose-headers X-Request-Id,X-Custom --cors-methods GET,POST --cors-headers Content-Type"
+
+echo "TEST: Credentialed wildcard echoes the origin... "
+templates/raw_request_with_opts.sh "$cors_opts" "GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n" "^Access-Control-Allow-Origin: https://app.example$" "^Access-Control-Allow-Origin: \*" || errored
+
+echo "TEST: Credentials are allowed... "
+templates/raw_request_with_opts.sh "$cors_opts" "GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n" "^Access-Control-Allow-Credentials: true$" || errored
+
+echo "TEST: Custom exposed headers... "
+templates/raw_request_with_opts.sh "$cors_opts" "GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n" "^Access-Control-Expose-Headers: X-Request-Id,X-Custom$" || errored
+
+echo "TEST: CORS headers on error responses... "
+templates/raw_request_with_opts.sh "$cors_opts" "GET /does-not-exist HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n" "^Access-Control-Allow-Origin: https://app.example$" || errored
+
+echo "TEST: Preflight gets the configured methods and headers... "
+templates/raw_request_with_opts.sh "$cors_opts" "OPTIONS / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\nAccess-Control-Request-Method: POST\r\n\r\n" "^Access-Control-Allow-Methods: GET,POST$" || errored
+
+echo "TEST: Wildcard without credentials... "
+templates/raw_request_with_opts.sh "--cors-origin *" "GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n" "^Access-Control-Allow-Origin: \*$" || errored
+
+echo "TEST: Unlisted origin gets no CORS headers... "
+templates/raw_request_with_opts.sh "--cors-origin https://app.example" "GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://evil.example\r\n\r\n" "^HTTP/1.1 200" "^Access-Control-" || errored
+
+echo -e "\n........... Overlays ..........."
+
+mkdir -p $DIR/overlay_base/shared $DIR/overlay_top/shared
+echo "base version" > $DIR/overlay_base/shadowed.txt
+echo "overlay version" > $DIR/overlay_top/shadowed.txt
+echo "only in base" > $DIR/overlay_base/base_only.txt
+echo ":)" > $DIR/overlay_base/shared/from_base.txt
+echo ":)" > $DIR/overlay_top/shared/from_overlay.txt
+echo "secret" > $DIR/overlay_secret.txt
+overlay_opts="--overlay $DIR/overlay_top"
+
+echo "TEST: Overlay file shadows the base file... "
+PORT=$PORT DIR=$DIR/overlay_base templates/raw_request_with_opts.sh "$overlay_opts" "GET /shadowed.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^overlay version$" "^base version$" || errored
+
+echo "TEST: Base-only file is still served... "
+PORT=$PORT DIR=$DIR/overlay_base templates/raw_request_with_opts.sh "$overlay_opts" "GET /base_only.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^only in base$" || errored
+
+echo "TEST: Listings are merged... "
+PORT=$PORT DIR=$DIR/overlay_base templates/raw_request_with_opts.sh "$overlay_opts" "GET /shared/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "from_base.txt<.*from_overlay.txt<" || errored
+
+echo "TEST: Shadowed entries are listed once... "
+PORT=$PORT DIR=$DIR/overlay_base templates/raw_request_with_opts.sh "$overlay_opts" "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "base_only.txt<.*shadowed.txt<" "shadowed.txt<.*shadowed.txt<" || errored
+
+echo "TEST: Overlay can't be escaped... "
+PORT=$PORT DIR=$DIR/overlay_base templates/raw_request_with_opts.sh "$overlay_opts" "GET /../overlay_secret.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" "^secret$" || errored
+
+echo -e "\n........... Byte Ranges ..........."
+
+echo "TEST: Single-byte range... "
+templates/raw_request.sh "GET /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-0\r\nConnection: close\r\n\r\n" "^Content-Range: bytes 0-0/1048576$" || errored
+
+echo "TEST: Single-byte range length... "
+templates/raw_request.sh "GET /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-0\r\nConnection: close\r\n\r\n" "^Content-Length: 1$" || errored
+
+echo "TEST: Open-ended range... "
+templates/raw_request.sh "HEAD /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-\r\nConnection: close\r\n\r\n" "^Content-Range: bytes 0-1048575/1048576$" || errored
+
+echo "TEST: Suffix range... "
+templates/raw_request.sh "HEAD /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=-3\r\nConnection: close\r\n\r\n" "^Content-Range: bytes 1048573-1048575/1048576$" || errored
+
+echo "TEST: Suffix range gets the end of the body... "
+templates/raw_request.sh "GET /hello.txt HTTP/1.1\r\nHost: localhost\r\nRange: bytes=-7\r\nConnection: close\r\n\r\n" "^memory$" "hello" || errored
+
+echo "TEST: Suffix range longer than the file... "
+templates/raw_request.sh "HEAD /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=-2000000\r\nConnection: close\r\n\r\n" "^Content-Range: bytes 0-1048575/1048576$" || errored
+
+echo "TEST: Empty suffix range... "
+templates/raw_request.sh "GET /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=-0\r\nConnection: close\r\n\r\n" "^HTTP/1.1 416" || errored
+
+echo "TEST: Full-file range... "
+templates/raw_request.sh "HEAD /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-1048575\r\nConnection: close\r\n\r\n" "^Content-Range: bytes 0-1048575/1048576$" || errored
+
+echo "TEST: Range past the end of the file... "
+templates/raw_request.sh "GET /test_1m.img HTTP/1.1\r\nHost: localhost\r\nRange: bytes=1048576-\r\n\r\n" "^Content-Range: bytes \*/1048576$" || errored
+
+echo -e "\n........... Compressed Uploads ..........."
+
+echo "TEST: gzip-encoded uploads... "
+templates/gzip_upload.sh test_1m.img || errored
+
+echo "TEST: Corrupt gzip-encoded PUT... "
+templates/raw_request.sh "PUT /corrupt_gzip.txt HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: gzip\r\nContent-Length: 5\r\n\r\nhello" "^HTTP/1.1 400" || errored
+
+echo "TEST: Unsupported Content-Encoding... "
+templates/raw_request.sh "PUT /deflated.txt HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: deflate\r\nContent-Length: 5\r\n\r\nhello" "^HTTP/1.1 415" || errored
+
+echo -e "\n........... Localized Index Files ..........."
+
+mkdir -p $DIR/i18n_test
+echo "<p>english index</p>" > $DIR/i18n_test/index.html
+echo "<p>index francais</p>" > $DIR/i18n_test/index.fr.html
+
+echo "TEST: Localized index for a preferred language... "
+templates/raw_request.sh "GET /i18n_test/ HTTP/1.1\r\nHost: localhost\r\nAccept-Language: fr-CH, fr;q=0.9, en;q=0.8\r\n\r\n" "^<p>index francais</p>$" || errored
+
+echo "TEST: Localized index chosen by quality... "
+templates/raw_request.sh "GET /i18n_test/ HTTP/1.1\r\nHost: localhost\r\nAccept-Language: de;q=0.5, fr;q=0.7\r\n\r\n" "^Content-Language: fr$" || errored
+
+echo "TEST: Fallback index for other languages... "
+templates/raw_request.sh "GET /i18n_test/ HTTP/1.1\r\nHost: localhost\r\nAccept-Language: de, en;q=0.5\r\n\r\n" "^<p>english index</p>$" || errored
+
+echo "TEST: Fallback index without Accept-Language... "
+templates/raw_request.sh "GET /i18n_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^<p>english index</p>$" "^Content-Language" || errored
+
+echo "TEST: Index responses vary by language... "
+templates/raw_request.sh "GET /i18n_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Vary: Accept-Language$" || errored
+
+echo -e "\n........... Concurrent Uploads ..........."
+
+echo "TEST: Uploads over the limit are turned away... "
+templates/concurrent_uploads.sh 2 || errored
+
+echo -e "\n........... Upload Buffer Refills ..........."
+
+echo "TEST: Upload the size of the buffer, delimiter split after its CRLF... "
+templates/split_refill_post_request.sh 262144 4 || errored
+
+echo "TEST: Upload the size of the buffer, split inside the CRLF... "
+templates/split_refill_post_request.sh 262144 1 || errored
+
+echo "TEST: Upload larger than the buffer, split just before its end... "
+templates/split_refill_post_request.sh 300000 -1 || errored
+
+echo "TEST: Upload several times the buffer, split inside the delimiter... "
+templates/split_refill_post_request.sh 1000000 10 || errored
+
+echo -e "\n........... Content-MD5 ..........."
+
+mkdir -p $DIR/content_md5_test
+dd if=/dev/zero of=$DIR/content_md5_test/large.img bs=1K count=2K 2> /dev/null
+sleep 0.1
+echo "00112233445566778899aabbccddeeff" > $DIR/content_md5_test/large.img.md5sum
+echo "hello" > $DIR/content_md5_test/small.txt
+
+echo "TEST: Content-MD5 from a sidecar... "
+templates/raw_request_with_opts.sh "--content-md5" "HEAD /content_md5_test/large.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-MD5: ABEiM0RVZneImaq7zN3u/w==$" || errored
+
+echo "TEST: Content-MD5 computed for a small file... "
+templates/raw_request_with_opts.sh "--content-md5" "GET /content_md5_test/small.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-MD5: sZRqySSS0jR8YjW00mERhA==$" || errored
+
+echo "TEST: No Content-MD5 for a partial response... "
+templates/raw_request_with_opts.sh "--content-md5" "GET /content_md5_test/small.txt HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-1\r\n\r\n" "^HTTP/1.1 206" "^Content-MD5" || errored
+
+echo "TEST: No Content-MD5 without --content-md5... "
+templates/raw_request.sh "GET /content_md5_test/small.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "^Content-MD5" || errored
+
+echo -e "\n........... Closing Idle Connections ..........."
+
+echo "TEST: Only idle keep-alive connections are closed... "
+templates/close_idle.sh || errored
+
+echo -e "\n........... Listen Backlog ..........."
+
+echo "TEST: A burst of connections waits in the backlog... "
+templates/connection_burst.sh 64 || errored
+
+echo -e "\n........... Automatic Port ..........."
+
+echo "TEST: Port 0 picks a free port and reports it... "
+templates/auto_port.sh || errored
+
+echo -e "\n........... Shutting Down ..........."
+
+echo "TEST: SIGTERM finishes downloads in progress and exits cleanly... "
+templates/sigterm_shutdown.sh test_1m.img || errored
+
+echo -e "\n........... Error Templates ..........."
+
+echo '<p class="error">{{code}} {{message}}: {{detail}}</p>' > $DIR/error_template.html
+
+echo "TEST: Error page uses the template and escapes details... "
+templates/raw_request_with_opts.sh "-u --error-template $DIR/error_template.html" "PUT /error_template_test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: <b>\r\nContent-Length: 0\r\n\r\n" "^<p class=\"error\">415 Unsupported media type: Unsupported Content-Encoding: &lt;b&gt;</p>$" || errored
+
+echo "TEST: Built-in error page escapes details... "
+templates/raw_request_with_opts.sh "-u" "PUT /error_template_test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: <b>\r\nContent-Length: 0\r\n\r\n" "Unsupported Content-Encoding: &lt;b&gt;" "<b>" || errored
+
+rm $DIR/error_template.html
+
+echo -e "\n........... Search ..........."
+
+mkdir -p $DIR/search_test/a/b
+echo "found" > $DIR/search_test/a/b/needle-report.txt
+echo "not found" > $DIR/search_test/haystack.txt
+
+echo "TEST: Search finds a nested file, ignoring case... "
+templates/raw_request.sh "GET /_search?q=NEEDLE HTTP/1.1\r\nHost: localhost\r\n\r\n" "href='/search_test/a/b/needle-report.txt'" "haystack.txt" || errored
+
+echo "TEST: Search without a term is rejected... "
+templates/raw_request.sh "GET /_search HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+rm -r $DIR/search_test
+
+echo -e "\n........... Compressed Responses ..........."
+
+echo "TEST: Response just below --gzip-min-size is sent as is... "
+templates/gzip_response.sh 999 1000 identity || errored
+
+echo "TEST: Response at --gzip-min-size is compressed... "
+templates/gzip_response.sh 1000 1000 gzip || errored
+
+echo "TEST: Responses are only compressed for clients that accept it... "
+templates/raw_request_with_opts.sh "--gzip-level 1 --gzip-min-size 0" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: br, gzip;q=0\r\n\r\n" "^Vary: Accept-Encoding$" "^Content-Encoding: " || errored
+
+echo "TEST: Responses over the in-memory limit are sent as is... "
+templates/gzip_response.sh 1100000 1000 identity || errored
+
+echo ":)" > $DIR/already_compressed.gz
+
+echo "TEST: Files that are compressed already aren't compressed again... "
+templates/raw_request_with_opts.sh "--gzip-level 1 --gzip-min-size 0" "GET /already_compressed.gz HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n" "^HTTP/1.1 200" "^Content-Encoding: " || errored
+
+rm $DIR/already_compressed.gz
+
+echo "TEST: HEAD of a gzip-compressed response matches GET... "
+templates/compressed_head_matches_get.sh /test_small.img "--gzip-level 1 --gzip-min-size 0" || errored
+
+echo "TEST: HEAD of a Brotli-compressed response matches GET... "
+templates/compressed_head_matches_get.sh /test_small.img "--brotli-level 11 --gzip-min-size 0" || errored
+
+echo -e "\n........... Disabling Keep-Alive ..........."
+
+echo "TEST: Pipelined keep-alive requests are both answered... "
+templates/pipelined_requests.sh "" 2 || errored
+
+echo "TEST: --no-keepalive answers one request and closes... "
+templates/pipelined_requests.sh "--no-keepalive" 1 || errored
+
+echo -e "\n........... Host Header ..........."
+
+echo "TEST: HTTP/1.1 request without Host is rejected... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: HTTP/1.1 request with Host is served... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo "TEST: HTTP/1.0 request without Host is served... "
+templates/raw_request.sh "GET /test_small.img HTTP/1.0\r\n\r\n" "^HTTP/1.0 200" || errored
+
+echo -e "\n........... Plain Text Listings ..........."
+
+echo "TEST: Listing as plain text for Accept: text/plain... "
+templates/text_listing.sh "" -H "Accept: text/plain" || errored
+
+echo "TEST: Listing as plain text for ?format=txt... "
+templates/text_listing.sh "?format=txt" || errored
+
+echo "TEST: Browsers still get HTML listings... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\nAccept: text/html,text/plain;q=0.9,*/*;q=0.8\r\n\r\n" "^Content-Type: text/html" || errored
+
+echo -e "\n........... Permissions in Listings ..........."
+
+echo "TEST: Modes are shown symbolically with the owner... "
+templates/perms_listing.sh 644=-rw-r--r-- 600=-rw------- 755=-rwxr-xr-x 4755=-rwsr-xr-x 2644=-rw-r-Sr-- 1777=drwxrwxrwt 1770=drwxrwx--T 750=drwxr-x--- || errored
+
+echo -e "\n........... Long URIs in the Interface ..........."
+
+echo "TEST: Long URIs are shortened to fit the terminal... "
+templates/long_uri_display.sh || errored
+
+echo -e "\n........... Strict Boundaries ..........."
+
+echo "TEST: Quoted boundary with a space is accepted under --strict-boundary... "
+templates/boundary_upload.sh "--strict-boundary" '"strict boundary"' "strict boundary" 201 || errored
+
+echo "TEST: Boundary with an invalid character is rejected under --strict-boundary... "
+templates/boundary_upload.sh "--strict-boundary" "bad<boundary>" "bad<boundary>" 400 || errored
+
+echo "TEST: Boundary with an invalid character is accepted by default... "
+templates/boundary_upload.sh "" "bad<boundary>" "bad<boundary>" 201 || errored
+
+echo -e "\n........... Upload Preflights ..........."
+
+preflight="OPTIONS / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\nAccess-Control-Request-Method: POST\r\nAccess-Control-Request-Headers: content-type\r\n\r\n"
+
+echo "TEST: Upload preflight allows POST... "
+templates/raw_request_with_opts.sh "-u --cors-origin https://app.example" "$preflight" "^Access-Control-Allow-Methods: .*POST" || errored
+
+echo "TEST: Upload preflight allows Content-Type... "
+templates/raw_request_with_opts.sh "-u --cors-origin https://app.example" "$preflight" "^Access-Control-Allow-Headers: Content-Type$" || errored
+
+echo "TEST: Preflight without uploading allows no extra headers... "
+templates/raw_request_with_opts.sh "--cors-origin https://app.example" "$preflight" "^HTTP/1.1 200" "^Access-Control-Allow-Headers: " || errored
+
+echo -e "\n........... Malformed Requests ..........."
+
+echo "TEST: Empty request... "
+templates/raw_request.sh "\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: Request line with only a method... "
+templates/raw_request.sh "GET\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: Missing version... "
+templates/raw_request.sh "GET /test_small.img\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: Empty line before the request line is ignored... "
+templates/raw_request.sh "\r\nGET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo -e "\n........... Spaces and Pluses in Paths ..........."
+
+echo "spaced out" > "$DIR/plus+and space.txt"
+echo "not a plus" > "$DIR/plus and space.txt"
+
+echo "TEST: Percent-encoded space... "
+templates/raw_request.sh "GET /plus+and%20space.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^spaced out$" || errored
+
+echo "TEST: Literal space... "
+templates/raw_request.sh "GET /plus+and space.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^spaced out$" || errored
+
+echo "TEST: Percent-encoded plus... "
+templates/raw_request.sh "GET /plus%2Band%20space.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^spaced out$" || errored
+
+echo "TEST: Plus isn't a space in a path... "
+templates/raw_request.sh "GET /plus+and+space.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+rm "$DIR/plus+and space.txt" "$DIR/plus and space.txt"
+
+echo -e "\n........... Absolute-Form Targets ..........."
+
+echo "TEST: Absolute-form target is served from its path... "
+templates/raw_request.sh "GET http://localhost:$PORT/test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^:\)$" || errored
+
+echo "TEST: Absolute-form target stands in for the Host header... "
+templates/raw_request.sh "GET HTTP://localhost/test_small.img HTTP/1.1\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo "TEST: Absolute-form target without a host is rejected... "
+templates/raw_request.sh "GET http:///test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo -e "\n........... Metrics ..........."
+
+echo "TEST: /_metrics is valid Prometheus text... "
+templates/metrics.sh || errored
+
+echo "TEST: /_metrics is not served without --enable-metrics... "
+templates/raw_request.sh "GET /_metrics HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+
+echo -e "\n........... Conditional listings ..........."
+
+echo "TEST: An unchanged directory listing gets 304... "
+templates/conditional_listing.sh || errored
+
+
+echo -e "\n........... Memory-Mapped Files ..........."
+
+echo "TEST: 1M file... "
+templates/mmap_download.sh test_1m.img || errored
+
+echo "TEST: 0B file... "
+templates/mmap_download.sh test_0b.img || errored
+
+echo "TEST: Range of 1M file... "
+templates/mmap_download.sh test_1m.img 1000-600000 || errored
+
+
+echo -e "\n........... Startup Banner ..........."
+
+echo "TEST: Banner in text and JSON... "
+templates/startup_banner.sh || errored
+
+
+echo -e "\n........... Default Charset ..........."
+
+echo "<p>caf&eacute;</p>" > $DIR/charset_test.html
+
+echo "TEST: HTML files are UTF-8 by default... "
+templates/raw_request.sh "GET /charset_test.html HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/html; charset=utf-8$" || errored
+
+echo "TEST: Configured charset... "
+templates/raw_request_with_opts.sh "--default-charset iso-8859-1" "GET /charset_test.html HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/html; charset=iso-8859-1$" || errored
+
+echo "TEST: Charset left out with none... "
+templates/raw_request_with_opts.sh "--default-charset none" "GET /charset_test.html HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/html$" || errored
+
+echo "TEST: Generated pages stay UTF-8... "
+templates/raw_request_with_opts.sh "--default-charset iso-8859-1" "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Type: text/html; charset=utf-8$" || errored
+
+rm $DIR/charset_test.html
+
+
+echo -e "\n........... Listing MD5 Limit ..........."
+
+echo "TEST: Only the first sidecars are read... "
+templates/md5_limit.sh 2 5 || errored
+
+echo "TEST: No sidecars are read with a limit of 0... "
+templates/md5_limit.sh 0 3 || errored
+
+
+echo -e "\n........... Hidden Upload Form ..........."
+
+echo "TEST: Listing without the upload form... "
+templates/raw_request_with_opts.sh "-u --no-upload-form" "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "<form" || errored
+
+echo "TEST: POST still accepted without the form... "
+templates/raw_request_with_opts.sh "-u --no-upload-form" "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: 103\r\n\r\n--xyz\r\nContent-Disposition: form-data; filename=\"formless_upload\"\r\n\r\nuploaded without a form\r\n--xyz--\r\n" "^HTTP/1.1 201" || errored
+rm $DIR/formless_upload
+
+
+echo -e "\n........... Inherited Sockets ..........."
+
+echo "TEST: Serving from an inherited socket... "
+templates/inherit_fd.sh test_1m.img || errored
+
+echo "TEST: Inherited descriptor that isn't open... "
+templates/startup_error.sh "--inherit-fd 999" "Failed to listen on inherited descriptor 999" || errored
+
+
+echo -e "\n........... Oversized Part Headers ..........."
+
+long_name=$(head -c 20000 /dev/zero | tr '\0' 'a')
+meta_body="--xyz\r\nContent-Disposition: form-data; name=\"f\"; filename=\"$long_name\"\r\n\r\nhello\r\n--xyz--\r\n"
+meta_length=$(echo -en "$meta_body" | wc -c)
+
+echo "TEST: Part headers over the limit are rejected... "
+templates/raw_request.sh "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: $meta_length\r\n\r\n$meta_body" "^HTTP/1.1 400" || errored
+
+padding=$(head -c 6000 /dev/zero | tr '\0' 'a')
+meta_body="--xyz\r\nContent-Disposition: form-data; name=\"f\"; filename=\"long_headers.txt\"\r\nX-Padding: $padding\r\n\r\nhello\r\n--xyz--\r\n"
+meta_length=$(echo -en "$meta_body" | wc -c)
+
+echo "TEST: Long part headers under the limit are accepted... "
+templates/raw_request.sh "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: $meta_length\r\n\r\n$meta_body" "^HTTP/1.1 201" || errored
+rm $DIR/long_headers.txt
+
+
+echo -e "\n........... Listing Stylesheets ..........."
+
+echo "td > a { color: teal; }" > $DIR/listing.css
+
+echo "TEST: Custom CSS is included verbatim... "
+templates/raw_request_with_opts.sh "--listing-css $DIR/listing.css" "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "td > a \{ color: teal; \}" || errored
+
+echo "TEST: Dark mode... "
+templates/raw_request_with_opts.sh "--dark-mode" "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "background-color: #1e1e1e" || errored
+
+echo "TEST: Default style is unchanged... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "background-color: #1e1e1e" || errored
+
+echo "TEST: Unreadable stylesheet... "
+templates/startup_error.sh "--listing-css $DIR/does-not-exist.css" "could not read listing stylesheet" || errored
+
+rm $DIR/listing.css
+
+
+echo -e "\n........... Slow Request Log ..........."
+
+echo "TEST: Slow request flagged in text log... "
+templates/slow_request_log.sh text || errored
+
+echo "TEST: Slow request flagged in JSON log... "
+templates/slow_request_log.sh json || errored
+
+echo -e "\n........... Upload Filenames ..........."
+
+echo "TEST: Quoted filename... "
+templates/upload_filename.sh 'filename="plain name.txt"' 201 "plain name.txt" || errored
+
+echo "TEST: RFC 5987 filename* in UTF-8... "
+templates/upload_filename.sh "filename*=UTF-8''caf%C3%A9%20menu.txt" 201 "café menu.txt" || errored
+
+echo "TEST: filename* preferred over filename... "
+templates/upload_filename.sh "filename=\"fallback.txt\"; filename*=utf-8''%E2%82%AC.txt" 201 "€.txt" || errored
+
+echo "TEST: filename* in ISO-8859-1... "
+templates/upload_filename.sh "filename*=ISO-8859-1''na%EFve.txt" 201 "naïve.txt" || errored
+
+echo "TEST: filename* that decodes to a path... "
+templates/upload_filename.sh "filename*=UTF-8''..%2Fescape.txt" 422 || errored
+
+echo "TEST: filename* with an unknown charset... "
+templates/upload_filename.sh "filename*=KOI8-R''test.txt" 422 || errored
+
+echo -e "\n........... Oversized Request Headers ..........."
+
+long_header=$(head -c 5000 /dev/zero | tr '\0' 'a')
+
+echo "TEST: Oversized headers are rejected... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\nX-Padding: $long_header\r\n\r\n" "^HTTP/1.1 431" || errored
+
+echo "TEST: Rejection gives the header size limit... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\nX-Padding: $long_header\r\n\r\n" "less than 4096 bytes" || errored
+
+echo "TEST: Oversized headers close the connection... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\nX-Padding: $long_header\r\n\r\nGET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Connection: close" "^HTTP/1.1 200" || errored
+
+echo -e "\n........... Growing Files ..........."
+
+echo "TEST: File that grows while being sent keeps its Content-Length... "
+templates/growing_file.sh || errored
+
+echo -e "\n........... Directory Counts ..........."
+
+echo "TEST: Subdirectories are shown with their entry counts... "
+templates/dir_counts.sh 0 || errored
+
+echo "TEST: Counts are left out of long listings... "
+templates/dir_counts.sh 1000 || errored
+
+echo -e "\n........... HTTP/2 Prefaces ..........."
+
+h2_preface="PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\x00\x00\x00\x04\x00\x00\x00\x00\x00"
+
+echo "TEST: HTTP/2 preface gets a 505... "
+templates/raw_request.sh "$h2_preface" "^HTTP/1.1 505" || errored
+
+echo "TEST: HTTP/2 preface is told why... "
+templates/raw_request.sh "$h2_preface" "HTTP/2 is not supported" || errored
+
+echo "TEST: HTTP/2 preface closes the connection... "
+templates/raw_request.sh "$h2_preface" "^Connection: close" || errored
+
+echo -e "\n........... READMEs in Listings ..........."
+
+mkdir -p $DIR/readme_md $DIR/readme_txt
+echo "Hello <b>readers</b>" > $DIR/readme_md/README.md
+echo "Plain text readme" > $DIR/readme_txt/README.txt
+
+echo "TEST: README.md shown escaped below the listing... "
+templates/raw_request_with_opts.sh "--show-readme" "GET /readme_md/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "<pre class=' readme'>Hello &lt;b&gt;readers&lt;/b&gt;" || errored
+
+echo "TEST: README.txt shown when there is no README.md... "
+templates/raw_request_with_opts.sh "--show-readme" "GET /readme_txt/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "<pre class=' readme'>Plain text readme" || errored
+
+echo "TEST: READMEs aren't shown by default... "
+templates/raw_request.sh "GET /readme_md/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "Hello &lt;b&gt;" || errored
+
+mkdir -p $DIR/readme_link
+ln -sf /etc/passwd $DIR/readme_link/README.md
+
+echo "TEST: Symlinked READMEs aren't shown... "
+templates/raw_request_with_opts.sh "--show-readme" "GET /readme_link/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "root:" || errored
+
+rm -r $DIR/readme_md $DIR/readme_txt $DIR/readme_link
+
+echo -e "\n........... Uploads to the Same Name ..........."
+
+echo "TEST: Second upload to a name being written is a conflict... "
+templates/same_name_uploads.sh "" 201 409 "first" || errored
+
+echo "TEST: Staged upload that finishes second is a conflict... "
+templates/same_name_uploads.sh "--upload-temp-dir $DIR/same-name-staging" 409 201 "2nd!" || errored
+
+echo "TEST: Aborted staged upload is removed from the staging directory... "
+templates/aborted_staged_upload.sh $DIR/abort-staging || errored
+
+echo -e "\n........... Upload Directory Creation ..........."
+
+echo "TEST: Form upload creates missing directories... "
+templates/upload_mkdir.sh "--upload-mkdir" POST /upload-mkdir/new/deeper 201 upload-mkdir/new/deeper/test_small.img || errored
+
+echo "TEST: PUT creates missing directories... "
+templates/upload_mkdir.sh "--upload-mkdir" PUT /upload-mkdir/put/file.img 201 upload-mkdir/put/file.img || errored
+
+echo "TEST: Directories outside the root aren't created... "
+templates/upload_mkdir.sh "--upload-mkdir" POST /%2e%2e/upload-mkdir-escape 404 ../upload-mkdir-escape || errored
+
+echo "TEST: Missing directories are left alone by default... "
+templates/upload_mkdir.sh "" POST /upload-mkdir/new 404 upload-mkdir || errored
+
+echo -e "\n........... Custom Response Headers ..........."
+
+echo "TEST: Custom headers on a successful response... "
+templates/raw_request_with_opts.sh "--header X-Frame-Options:DENY --header Content-Security-Policy:default-src" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Content-Security-Policy: default-src" || errored
+
+echo "TEST: Custom headers on an error response... "
+templates/raw_request_with_opts.sh "--header X-Frame-Options:DENY" "GET /does-not-exist HTTP/1.1\r\nHost: localhost\r\n\r\n" "^X-Frame-Options: DENY" || errored
+
+echo "TEST: Malformed header... "
+templates/startup_error.sh "--header X-Frame-Options" "invalid header \`X-Frame-Options\`" || errored
+
+echo "TEST: Header that frames the response... "
+templates/startup_error.sh "--header Content-Length:0" "the Content-Length header can't be set" || errored
+
+echo -e "\n........... OPTIONS Hints ..........."
+
+echo "TEST: OPTIONS reports range support... "
+templates/raw_request.sh "OPTIONS /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Accept-Ranges: bytes$" || errored
+
+echo "TEST: OPTIONS reports the upload size limit... "
+templates/raw_request_with_opts.sh "-u --upload-size-limit 1048576" "OPTIONS / HTTP/1.1\r\nHost: localhost\r\n\r\n" "^X-Upload-Size-Limit: 1048576$" || errored
+
+echo "TEST: No upload size limit without uploading... "
+templates/raw_request_with_opts.sh "--upload-size-limit 1048576" "OPTIONS / HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "^X-Upload-Size-Limit" || errored
+
+echo -e "\n........... NUL Bytes in Paths ..........."
+
+echo "TEST: Percent-encoded NUL... "
+templates/raw_request.sh "GET /%00 HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: NUL that would cut a path short... "
+templates/raw_request.sh "GET /test_small.img%00.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 400" || errored
+
+echo "TEST: NUL in an upload path... "
+templates/raw_request.sh "PUT /nul%00.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\nok" "^HTTP/1.1 400" || errored
+
+echo -e "\n........... Audit Log ..........."
+
+echo "TEST: Downloads and uploads are audited... "
+templates/audit_log.sh 0 || errored
+
+echo "TEST: Audit log is rotated once it would grow too large... "
+templates/audit_log.sh 250 || errored
+
+echo "TEST: Audit log that can't be opened... "
+templates/startup_error.sh "--audit-log $DIR/does-not-exist/audit.log" "could not open audit log" || errored
+
+echo "TEST: Serving downloads and uploads... "
+templates/serving_mode.sh "" 200 201 201 || errored
+
+echo "TEST: Downloads paused, uploads still accepted... "
+templates/serving_mode.sh "--start-reads-paused" 503 201 201 || errored
+
+echo "TEST: Uploads paused, downloads still served... "
+templates/serving_mode.sh "--start-uploads-paused" 200 503 503 || errored
+
+echo "TEST: Downloads and uploads both paused... "
+templates/serving_mode.sh "--start-reads-paused --start-uploads-paused" 503 503 503 || errored
+
+echo "TEST: Everything paused when started disabled... "
+templates/serving_mode.sh "--start-disabled" 503 503 503 || errored
+
+echo "TEST: FIFO streamed to an HTTP/1.1 client in chunks... "
+templates/fifo_stream.sh "--allow-fifo" "--http1.1" "^Transfer-Encoding: chunked$" || errored
+
+echo "TEST: FIFO streamed to an HTTP/1.0 client until close... "
+templates/fifo_stream.sh "--allow-fifo" "--http1.0" "^Connection: close$" || errored
+
+mkfifo $DIR/idle.fifo
+
+echo "TEST: FIFOs are refused without --allow-fifo... "
+templates/raw_request.sh "GET /idle.fifo HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 403" || errored
+
+echo "TEST: HEAD of a FIFO doesn't wait for a writer... "
+templates/raw_request_with_opts.sh "--allow-fifo" "HEAD /idle.fifo HTTP/1.1\r\nHost: localhost\r\n\r\n" "^Transfer-Encoding: chunked$" || errored
+
+rm $DIR/idle.fifo
+
+echo "TEST: Failed upload is read to the end without a discard limit... "
+templates/discard_limit.sh "" 16777216 1 || errored
+
+echo "TEST: Failed upload past the discard limit closes the connection... "
+templates/discard_limit.sh "--max-discard-bytes 1048576" 268435456 0 || errored
+
+echo "TEST: Connection from a denied address is closed... "
+templates/deny_ip.sh "--deny-ip 127.0.0.2" 127.0.0.2 000 || errored
+
+echo "TEST: Connection from another address is served... "
+templates/deny_ip.sh "--deny-ip 127.0.0.2" 127.0.0.1 200 || errored
+
+echo "TEST: Invalid address given to --deny-ip... "
+templates/startup_error.sh "--deny-ip 127.0.0" "invalid IP address" || errored
+
+echo "TEST: Completed and aborted downloads are told apart in text logs... "
+templates/transfer_end_log.sh text || errored
+
+echo "TEST: Completed and aborted downloads are told apart in JSON logs... "
+templates/transfer_end_log.sh json || errored
+
+mkdir -p $DIR/thumbs
+touch $DIR/thumbs/photo.JPG $DIR/thumbs/notes.txt
+
+echo "TEST: Images in listings get a thumbnail with --thumbnails... "
+templates/raw_request_with_opts.sh "--thumbnails" "GET /thumbs/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "<a href='/thumbs/photo.JPG'><img src='/thumbs/photo.JPG'" || errored
+
+echo "TEST: Other files in listings don't get a thumbnail... "
+templates/raw_request_with_opts.sh "--thumbnails" "GET /thumbs/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "notes.txt" "<img src='/thumbs/notes.txt'" || errored
+
+echo "TEST: No thumbnails without --thumbnails... "
+templates/raw_request.sh "GET /thumbs/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "photo.JPG" "<img" || errored
+
+rm -r $DIR/thumbs
+
+echo "TEST: Concurrent downloads stay under --global-rate-limit together... "
+templates/global_rate_limit.sh 4194304 || errored
+
+echo "TEST: A single write doesn't burst past --global-rate-limit... "
+templates/global_rate_limit_burst.sh 65536 || errored
+
+echo "TEST: gzip-negotiated response has Vary: Accept-Encoding... "
+templates/raw_request_with_opts.sh "--gzip-level 1 --gzip-min-size 0" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n" "^Vary: Accept-Encoding$" || errored
+
+echo "TEST: Every negotiated header is listed in one Vary header... "
+templates/raw_request_with_opts.sh "--gzip-level 1 --gzip-min-size 0" "GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n" "^Vary: Accept-Encoding, Accept$" "^Vary: Accept$" || errored
+
+mkdir -p $DIR/hide_test
+touch $DIR/hide_test/keep.txt $DIR/hide_test/scratch.tmp $DIR/hide_test/backup~
+
+echo "TEST: Entries matching --hide are left out of listings... "
+templates/raw_request_with_opts.sh "--hide *.tmp --hide *~" "GET /hide_test/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "keep.txt" "scratch.tmp|backup~" || errored
+
+echo "TEST: Entries matching --hide are left out of text listings... "
+templates/raw_request_with_opts.sh "--hide *.tmp" "GET /hide_test/ HTTP/1.1\r\nHost: localhost\r\nAccept: text/plain\r\n\r\n" "^keep.txt" "scratch.tmp" || errored
+
+echo "TEST: Entries matching --hide can still be requested directly... "
+templates/raw_request_with_opts.sh "--hide *.tmp" "GET /hide_test/scratch.tmp HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo "TEST: Entries matching --hide are not found with --hide-hard... "
+templates/raw_request_with_opts.sh "--hide *.tmp --hide-hard" "GET /hide_test/scratch.tmp HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+echo "TEST: Entries inside a hidden directory are not found with --hide-hard... "
+templates/raw_request_with_opts.sh "--hide hide_* --hide-hard" "GET /hide_test/keep.txt HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" || errored
+
+echo "TEST: Hidden entries are left out of search results... "
+templates/raw_request_with_opts.sh "--hide *.tmp --hide-hard" "GET /_search?q=tmp HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" "scratch.tmp" || errored
+
+echo "TEST: Search is turned away under a hidden path... "
+templates/raw_request_with_opts.sh "--hide _search --hide-hard" "GET /_search?q=keep HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 404" "keep.txt" || errored
+
+echo "TEST: Hidden entries are left out of PROPFIND listings... "
+templates/raw_request_with_opts.sh "--hide *.tmp --hide-hard" "PROPFIND /hide_test/ HTTP/1.1\r\nHost: localhost\r\nDepth: 1\r\n\r\n" "keep.txt" "scratch.tmp" || errored
+
+echo "TEST: PROPFIND of a hidden path with --hide-hard is not found... "
+templates/raw_request_with_opts.sh "--hide *.tmp --hide-hard" "PROPFIND /hide_test/scratch.tmp HTTP/1.1\r\nHost: localhost\r\nDepth: 0\r\n\r\n" "^HTTP/1.1 404" || errored
+
+mkdir -p $DIR/hide_test/skipped_dir
+touch $DIR/hide_test/skipped_dir/inner.txt
+echo "TEST: Entries matching --hide are left out of ZIP downloads... "
+templates/raw_request_with_opts.sh "--zip --hide *.tmp --hide-hard" "GET /hide_test/?zip HTTP/1.1\r\nHost: localhost\r\n\r\n" "keep.txt" "scratch.tmp" 2> /dev/null || errored
+
+echo "TEST: Everything under a hidden directory is left out of ZIP downloads... "
+templates/raw_request_with_opts.sh "--zip --hide skipped_*" "GET /hide_test/?zip HTTP/1.1\r\nHost: localhost\r\n\r\n" "keep.txt" "skipped_dir|inner.txt" 2> /dev/null || errored
+
+rm -r $DIR/hide_test
+
+echo -e "\n........... Long Request Targets ..........."
+
+long_path=$(head -c 200 /dev/zero | tr '\0' 'a')
+huge_path=$(head -c 5000 /dev/zero | tr '\0' 'a')
+
+echo "TEST: Targets over --max-uri-length get 414... "
+templates/raw_request_with_opts.sh "--max-uri-length 100" "GET /$long_path HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 414" || errored
+
+echo "TEST: Targets under --max-uri-length are served... "
+templates/raw_request_with_opts.sh "--max-uri-length 100" "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 200" || errored
+
+echo "TEST: Targets that don't fit in the request buffer get 414, not 431... "
+templates/raw_request.sh "GET /$huge_path HTTP/1.1\r\nHost: localhost\r\n\r\n" "^HTTP/1.1 414" "^HTTP/1.1 431" || errored
+
+echo -e "\n........... Brotli Responses ..........."
+
+echo "TEST: Client preferring Brotli gets a Brotli response... "
+templates/negotiated_encoding.sh "gzip;q=0.5, br" br || errored
+
+echo "TEST: Client that only accepts gzip gets gzip... "
+templates/negotiated_encoding.sh "gzip" gzip || errored
+
+echo "TEST: Between equally preferred encodings, the first listed is used... "
+templates/negotiated_encoding.sh "br, gzip" br || errored
+
+echo "TEST: Brotli refused with q=0 is not used... "
+templates/negotiated_encoding.sh "br;q=0, *" gzip || errored
+
+echo "TEST: Large responses at the slowest Brotli level arrive intact... "
+templates/negotiated_encoding.sh "br" br 900000 11 || errored
+
+echo "TEST: Brotli level is checked... "
+templates/startup_error.sh "--brotli-level 12" "between 0 and 11" || errored
+
+echo -e "\n........... Listing Links ..........."
+
+mkdir -p "$DIR/Bob's" "$DIR/<b>bold"
+echo ":)" > "$DIR/Bob's/f.txt"
+
+echo "TEST: Listing links are encoded from the path, not copied from the request... "
+templates/raw_request.sh "GET /Bob's/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "href='/Bob%27s/f.txt'" "href='/Bob's" || errored
+
+echo "TEST: Listing links don't depend on how the path was encoded... "
+templates/raw_request.sh "GET /Bob%27s/ HTTP/1.1\r\nHost: localhost\r\n\r\n" "href='/Bob%27s/f.txt'" || errored
+
+echo "TEST: Names in listings are escaped... "
+templates/raw_request.sh "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" "&lt;b&gt;bold" "<b>bold" || errored
+
+echo "TEST: PROPFIND hrefs are encoded from the path... "
+templates/raw_request.sh "PROPFIND /Bob's/ HTTP/1.1\r\nHost: localhost\r\nDepth: 1\r\n\r\n" "<D:href>/Bob%27s/f.txt</D:href>" "/Bob's/" || errored
+
+rm -r "$DIR/Bob's" "$DIR/<b>bold"
+
 echo -e "...................................\n"
 echo "Killing hypershare and cleaning up"
 
@@ -93,6 +1259,8 @@ rm $DIR/file\ with\ spaces\ and\ %s
 
 kill -2 %1
 
+rm $VIRTUAL_FILE
+
 rm -r $DIR
 
 popd > /dev/null
@@ -0,0 +1,41 @@
+#!/bin/bash -ue
+
+# Usage: aborted_staged_upload.sh <temp-dir>
+# Starts a second server that stages uploads in <temp-dir>, starts an upload
+# and drops the connection partway through its body, and checks that nothing
+# is left in <temp-dir> or in place of the upload.
+
+temp_dir="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+mkdir -p "$temp_dir" "$DIR/aborted-upload"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --upload-temp-dir "$temp_dir" > /dev/null &
+server=$!
+sleep 1
+
+part="--xyz\r\nContent-Disposition: form-data; name=\"f\"; filename=\"aborted.txt\"\r\n\r\n"
+head="POST /aborted-upload HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\n"
+
+(echo -en "${head}Content-Length: 1000\r\n\r\n${part}partial"; sleep 1) |
+    timeout 1 nc -t localhost $port > /dev/null || true
+sleep 1
+
+leftover="$(ls -A "$temp_dir") $(ls -A "$DIR/aborted-upload")"
+
+kill $server
+wait $server 2> /dev/null || true
+
+rm -rf "$temp_dir" "$DIR/aborted-upload"
+
+if [[ "$leftover" == " " ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Left behind: $leftover"
+fi
@@ -0,0 +1,64 @@
+#!/bin/bash -ue
+
+# Usage: audit_log.sh <max-size>
+# Starts a second server with an --audit-log of at most <max-size> bytes,
+# downloads a file, uploads one, asks for a missing file and makes a HEAD
+# request. With a <max-size> of 0, checks that the transfers are logged and
+# the HEAD isn't. Otherwise, checks that the log was rotated, keeping only the
+# last line in the log and the one before it in <log>.1.
+
+max_size="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log_dir=$(mktemp -d)
+log="$log_dir/audit.log"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --audit-log "$log" \
+    --audit-log-max-size $max_size > /dev/null &
+server=$!
+sleep 1
+
+curl -s -o /dev/null "http://localhost:$port/test_small.img"
+curl -s -o /dev/null -T "$DIR/test_small.img" "http://localhost:$port/audited.img"
+curl -s -o /dev/null "http://localhost:$port/not-there.img"
+curl -s -o /dev/null -I "http://localhost:$port/test_small.img"
+
+kill $server
+wait $server 2> /dev/null || true
+rm -f "$DIR/audited.img"
+
+small_size=$(stat -c %s "$DIR/test_small.img")
+line='^\{"ts":"[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9:.]+Z","ip":"127.0.0.1",'
+download="$line\"method\":\"GET\",\"path\":\"/test_small.img\",\"status\":200,\"bytes_sent\":$small_size,\"bytes_received\":0,\"files\":\[\]\}$"
+upload="$line\"method\":\"PUT\",\"path\":\"/audited.img\",\"status\":201,\"bytes_sent\":[0-9]+,\"bytes_received\":$small_size,\"files\":\[\]\}$"
+missing="$line\"method\":\"GET\",\"path\":\"/not-there.img\",\"status\":404,"
+
+if [[ "$max_size" == "0" ]]
+then
+    [[ $(wc -l < "$log") == 3 ]] &&
+        sed -n 1p "$log" | grep -q -E "$download" &&
+        sed -n 2p "$log" | grep -q -E "$upload" &&
+        sed -n 3p "$log" | grep -q -E "$missing" &&
+        [[ ! -e "$log.1" ]]
+else
+    [[ $(wc -l < "$log") == 1 ]] && grep -q -E "$missing" "$log" &&
+        [[ $(wc -l < "$log.1") == 1 ]] && grep -q -E "$upload" "$log.1" &&
+        [[ $(stat -c %s "$log.1") -le $max_size ]]
+fi && passed=1 || passed=0
+
+if [[ $passed == 1 ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    for f in "$log" "$log.1"; do
+        echo "$f:"
+        sed -e 's/^/ >>> output: /' "$f" 2> /dev/null || true
+    done
+fi
+
+rm -rf "$log_dir"
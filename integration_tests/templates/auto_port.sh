@@ -0,0 +1,36 @@
+#!/bin/bash -ue
+
+# Starts a second server with --port 0, reads the port it picked from the
+# "Listening on" line, and checks that a file can be downloaded from it.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+log=$(mktemp)
+
+cargo run -q -- -d $DIR -p 0 -m "127.0.0.1" --headless > "$log" &
+server=$!
+sleep 1
+
+port=$(grep -o "^Listening on 127.0.0.1:[0-9]*" "$log" | awk -F: '{ print $2 }' || true)
+output=""
+if [[ -n "$port" ]] && [[ "$port" != "0" ]]
+then
+    output=$(curl -s "http://localhost:$port/test_small.img" | md5sum | awk '{ print $1 }')
+fi
+
+kill $server
+wait $server 2> /dev/null || true
+rm -f "$log"
+
+expected=$(md5sum < "$DIR/test_small.img" | awk '{ print $1 }')
+
+if [[ "$output" == "$expected" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Port: ${port:-none}"
+    echo "Download: $output"
+fi
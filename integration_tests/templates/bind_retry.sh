@@ -0,0 +1,37 @@
+#!/bin/bash -ue
+
+# Starts a second server on a port that is still in use, then frees the port
+# and checks that the second server picked it up.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless > /dev/null &
+first=$!
+sleep 1
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --bind-retry 10 > /dev/null &
+second=$!
+sleep 1
+
+kill $first
+wait $first 2> /dev/null || true
+sleep 2
+
+resp=$(echo -en "GET /$file HTTP/1.1\r\nHost: localhost\r\n\r\n" | nc -t localhost $port | head -n1)
+
+kill $second
+wait $second 2> /dev/null || true
+
+if echo "$resp" | grep -q "200 OK"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Response: $resp"
+fi
@@ -0,0 +1,53 @@
+#!/bin/bash -ue
+
+# Usage: boundary_upload.sh <options> <boundary-param> <boundary> <expected-status>
+# Starts a second server with uploading enabled and the extra command line
+# <options>, uploads a small file with a Content-Type boundary parameter of
+# <boundary-param> and a body delimited by <boundary>, and checks the status.
+
+options="$1"
+param="$2"
+boundary="$3"
+expected="$4"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+output_file="boundary-upload.txt"
+
+body=$(printf -- "--%s\r\nContent-Disposition: form-data; name=\"f\"; filename=\"%s\"\r\n\r\nhello\r\n--%s--\r\n" \
+    "$boundary" "$output_file" "$boundary")
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u $options > /dev/null &
+server=$!
+sleep 1
+
+status=$(
+    {
+        printf "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n"
+        printf "Content-Type: multipart/form-data; boundary=%s\r\n" "$param"
+        printf "Content-Length: %s\r\n\r\n" "${#body}"
+        printf "%s" "$body"
+    } | timeout 3 nc -t localhost $port | head -n1 | tr -d '\r'
+)
+
+kill $server
+wait $server 2> /dev/null || true
+
+contents=$(cat "$DIR/$output_file" 2> /dev/null || true)
+rm -f "$DIR/$output_file"
+
+if [[ "$status" == "HTTP/1.1 $expected"* ]] &&
+    { [[ "$expected" != "201" ]] || [[ "$contents" == "hello" ]]; }
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected $expected, got: $status"
+    echo "Uploaded contents: $contents"
+fi
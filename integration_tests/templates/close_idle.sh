@@ -0,0 +1,54 @@
+#!/bin/bash -ue
+
+# Starts a second server with its interface running in a pseudo-terminal,
+# opens a keep-alive connection that has finished a request and one that is
+# part way through its first, presses I, and checks that only the idle one is
+# closed.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+keys=$(mktemp -u)
+mkfifo "$keys"
+
+script -qfc "stty cols 120 rows 40; cargo run -q -- -d $DIR -p $port -m 127.0.0.1" /dev/null \
+    < "$keys" > /dev/null 2>&1 &
+server=$!
+exec 7> "$keys"
+sleep 1
+
+exec 3<> /dev/tcp/127.0.0.1/$port
+echo -en "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n" >&3
+exec 4<> /dev/tcp/127.0.0.1/$port
+echo -en "GET /test_small.img HTTP/1.1\r\nHost: localhost\r\n" >&4
+sleep 0.5
+
+# `timeout` exits with 124 if the connection is still open.
+idle_before=0
+timeout 1 cat <&3 > /dev/null || idle_before=$?
+echo -n i >&7
+sleep 1
+idle_after=0
+timeout 1 cat <&3 > /dev/null || idle_after=$?
+busy_after=0
+timeout 1 cat <&4 > /dev/null || busy_after=$?
+
+exec 3>&- 4>&-
+echo -n q >&7
+exec 7>&-
+sleep 1
+kill $server 2> /dev/null || true
+wait $server 2> /dev/null || true
+rm -f "$keys"
+
+if [[ $idle_before == 124 ]] && [[ $idle_after == 0 ]] && [[ $busy_after == 124 ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Idle connection open before pressing I: $idle_before (124 means open)"
+    echo "Idle connection open after pressing I: $idle_after"
+    echo "Busy connection open after pressing I: $busy_after"
+fi
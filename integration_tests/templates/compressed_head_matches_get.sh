@@ -0,0 +1,46 @@
+#!/bin/bash -ue
+
+# Usage: compressed_head_matches_get.sh <path> <options>
+# Starts a second server with the extra command line <options>, requests
+# <path> with Accept-Encoding: gzip, br using both GET and HEAD, and checks
+# that the HEAD response has the same headers as the GET, including
+# Content-Encoding and Content-Length, and no body. X-Request-Id differs per
+# request, so it isn't compared.
+
+path="$1"
+options="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options > /dev/null &
+server=$!
+sleep 1
+
+request="$path HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip, br\r\nConnection: close\r\n\r\n"
+
+get_headers=$(echo -en "GET $request" | nc -t localhost $port | tr -d '\r' |
+    sed -e '/^$/q' | grep -v "^X-Request-Id: ")
+head_resp=$(echo -en "HEAD $request" | nc -t localhost $port | tr -d '\r')
+head_headers=$(echo "$head_resp" | sed -e '/^$/q' | grep -v "^X-Request-Id: ")
+head_body=$(echo "$head_resp" | sed -e '1,/^$/d')
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ "$get_headers" == "$head_headers" ]] &&
+    [[ -z "$head_body" ]] &&
+    echo "$head_headers" | grep -q "^Content-Encoding: " &&
+    echo "$head_headers" | grep -q "^Content-Length: "
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "GET headers:"
+    echo "$get_headers" | sed -e 's/^/ >>> response: /'
+    echo "HEAD response:"
+    echo "$head_resp" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
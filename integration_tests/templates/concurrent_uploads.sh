@@ -0,0 +1,53 @@
+#!/bin/bash -ue
+
+# Usage: concurrent_uploads.sh <n>
+# Starts a second server allowing <n> concurrent uploads, holds <n> form
+# uploads open, and checks that one more is turned away with a 429 and a
+# Retry-After while a PUT still gets through.
+
+n="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+mkdir -p "$DIR/concurrent-upload"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --max-concurrent-uploads $n \
+    > /dev/null &
+server=$!
+sleep 1
+
+head="POST /concurrent-upload HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: 1000\r\n\r\n"
+
+# Each upload sends the start of its body and then stalls.
+uploaders=()
+for i in $(seq 1 $n); do
+    (echo -en "$head--xyz\r\nContent-Disposition: form-data; name=\"f\"; filename=\"held-$i.txt\"\r\n\r\nsome data"; sleep 3) |
+        nc -t localhost $port > /dev/null &
+    uploaders+=($!)
+done
+sleep 1
+
+rejected=$(echo -en "$head" | timeout 2 nc -t localhost $port | tr -d '\r' || true)
+put=$(echo -en "PUT /concurrent-upload/put.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\nok" |
+    timeout 2 nc -t localhost $port | tr -d '\r' || true)
+
+kill ${uploaders[@]} 2> /dev/null || true
+kill $server
+wait $server 2> /dev/null || true
+rm -rf "$DIR/concurrent-upload"
+
+if echo "$rejected" | grep -q "^HTTP/1.1 429" &&
+    echo "$rejected" | grep -q "^Retry-After: [0-9]*$" &&
+    echo "$put" | grep -q "^HTTP/1.1 201"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Upload over the limit:"
+    echo "$rejected" | head -n 20 | sed -e 's/^/ >>> response: /'
+    echo "PUT:"
+    echo "$put" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
@@ -0,0 +1,49 @@
+#!/bin/bash -ue
+
+# Starts a second server, lists a directory, then checks that conditional requests with its ETag and
+# Last-Modified get 304 Not Modified, and that the same requests get the full
+# listing again once a file has been added to the directory.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+rm -rf "$DIR/conditional_listing"
+mkdir "$DIR/conditional_listing"
+touch -d "2020-01-01 00:00:00" "$DIR/conditional_listing/a.txt" "$DIR/conditional_listing"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless > /dev/null &
+server=$!
+sleep 1
+
+url="http://localhost:$port/conditional_listing/"
+headers=$(curl -s -D - -o /dev/null "$url" | tr -d '\r')
+etag=$(echo "$headers" | sed -n -e 's/^ETag: //p')
+modified=$(echo "$headers" | sed -n -e 's/^Last-Modified: //p')
+
+status() {
+    curl -s -o /dev/null -w "%{http_code}" "$@" "$url"
+}
+
+etag_unchanged=$(status -H "If-None-Match: $etag")
+date_unchanged=$(status -H "If-Modified-Since: $modified")
+touch "$DIR/conditional_listing/b.txt"
+etag_changed=$(status -H "If-None-Match: $etag")
+date_changed=$(status -H "If-Modified-Since: $modified")
+
+kill $server
+wait $server 2> /dev/null || true
+
+rm -rf "$DIR/conditional_listing"
+
+if [[ "$etag" == W/\"*\" ]] && [[ "$modified" == "Wed, 01 Jan 2020 00:00:00 GMT" ]] &&
+    [[ "$etag_unchanged" == "304" ]] && [[ "$date_unchanged" == "304" ]] &&
+    [[ "$etag_changed" == "200" ]] && [[ "$date_changed" == "200" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "ETag: $etag, Last-Modified: $modified"
+    echo "Unchanged: $etag_unchanged $date_unchanged, changed: $etag_changed $date_changed"
+fi
@@ -0,0 +1,44 @@
+#!/bin/bash -ue
+
+# Usage: connection_burst.sh <backlog>
+# Starts a second server with --backlog <backlog> and pauses it, so that
+# nothing is accepted while <backlog> clients connect at once. Once it is
+# resumed, every client should get its response.
+
+backlog="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+results=$(mktemp -d)
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --backlog $backlog > /dev/null &
+server=$!
+sleep 1
+
+kill -STOP $server
+clients=()
+for i in $(seq 1 $backlog); do
+    curl -s -o /dev/null -w "%{http_code}" --connect-timeout 1 --max-time 10 \
+        "http://localhost:$port/test_small.img" > "$results/$i" &
+    clients+=($!)
+done
+sleep 2
+kill -CONT $server
+wait ${clients[@]} || true
+
+kill $server
+wait $server 2> /dev/null || true
+
+ok=$(cat "$results"/* | grep -o "200" | wc -l)
+rm -rf "$results"
+
+if [[ "$ok" == "$backlog" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Only $ok of $backlog connections were served"
+fi
@@ -0,0 +1,38 @@
+#!/bin/bash -ue
+
+# Usage: deny_ip.sh <options> <source-address> <expected-status>
+# Starts a second server with the extra command line <options>, then requests
+# test_small.img from <source-address>, which must be a loopback address, and
+# checks the status. A connection that is closed without a response has status
+# 000.
+
+options="$1"
+source_address="$2"
+expected="$3"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options > /dev/null &
+server=$!
+sleep 1
+
+status=$(curl -s -o /dev/null -w "%{http_code}" --interface "$source_address" \
+    "http://127.0.0.1:$port/test_small.img" || true)
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ "$status" == "$expected" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected $expected, got: $status"
+fi
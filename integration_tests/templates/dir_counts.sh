@@ -0,0 +1,48 @@
+#!/bin/bash -ue
+
+# Usage: dir_counts.sh <padding-files>
+# Starts a second server with --show-dir-counts, lists a directory holding an
+# empty subdirectory, one with three entries, a file, and <padding-files> more
+# files, and checks that subdirectories are shown with their counts, or, if
+# the listing is over the limit for counting, without them.
+
+padding="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+listed="$DIR/dir_counts"
+mkdir -p "$listed/three" "$listed/empty"
+touch "$listed/three/a" "$listed/three/b" "$listed/three/c" "$listed/file"
+for i in $(seq 1 "$padding"); do
+    touch "$listed/padding-$i"
+done
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --show-dir-counts > /dev/null &
+server=$!
+sleep 1
+
+rows=$(curl -s "http://localhost:$port/dir_counts/" | sed -e 's/<tr>/\n/g')
+
+kill $server
+wait $server 2> /dev/null || true
+rm -rf "$listed"
+
+if [[ $((padding + 3)) -le 1000 ]]
+then
+    echo "$rows" | grep -q ">three</a><span> (3)</span>" &&
+        echo "$rows" | grep -q ">empty</a><span> (0)</span>" &&
+        ! echo "$rows" | grep ">file</a>" | grep -q "<span>"
+else
+    echo "$rows" | grep -q ">three</a>" && ! echo "$rows" | grep -q "<span>"
+fi && passed=1 || passed=0
+
+if [[ $passed == 1 ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "$rows" | grep ">three<\|>empty<\|>file<" | sed -e 's/^/ >>> response: /' || true
+fi
@@ -0,0 +1,78 @@
+#!/bin/bash -ue
+
+# Usage: discard_limit.sh <options> <body-size> <read-fully>
+# Starts a second server with uploading enabled, a 1 KiB upload size limit and
+# the extra command line <options>, then sends a form upload with a body of
+# <body-size> bytes. <read-fully> is 1 if the server should read the whole body
+# before answering with 413, or 0 if it should close the connection before the
+# body has been sent.
+
+options="$1"
+body_size="$2"
+read_fully="$3"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --upload-size-limit 1024 $options > /dev/null &
+server=$!
+sleep 1
+
+result=$(python3 - "$port" "$body_size" <<'PYTHON'
+import socket, sys
+
+port, body_size = int(sys.argv[1]), int(sys.argv[2])
+part = b'--xyz\r\nContent-Disposition: form-data; name="f"; filename="discard-limit.bin"\r\n\r\n'
+end = b"\r\n--xyz--\r\n"
+s = socket.create_connection(("127.0.0.1", port))
+s.settimeout(10)
+s.sendall(b"POST / HTTP/1.1\r\nHost: localhost\r\n"
+          b"Content-Type: multipart/form-data; boundary=xyz\r\n"
+          b"Content-Length: %d\r\n\r\n" % body_size + part)
+sent = len(part)
+chunk = b"a" * 65536
+try:
+    while sent < body_size - len(end):
+        s.sendall(chunk[:body_size - len(end) - sent])
+        sent += min(len(chunk), body_size - len(end) - sent)
+    s.sendall(end)
+    sent += len(end)
+except (BrokenPipeError, ConnectionResetError):
+    pass
+response = b""
+try:
+    while True:
+        data = s.recv(65536)
+        if not data:
+            break
+        response += data
+except (ConnectionResetError, socket.timeout):
+    pass
+# The response may be lost to a reset if the connection was closed early.
+status = response.split(b"\r\n")[0].decode() or "none"
+print(int(sent >= body_size), status)
+PYTHON
+)
+
+kill $server
+wait $server 2> /dev/null || true
+
+rm -f "$DIR/discard-limit.bin"
+
+fully=${result%% *}
+status=${result#* }
+if [[ "$fully" == "$read_fully" ]] &&
+    { [[ "$status" == "HTTP/1.1 413 Payload too large" ]] ||
+        { [[ $read_fully == 0 ]] && [[ "$status" == "none" ]]; }; }
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected the body to be read fully: $read_fully, got: $fully ($status)"
+fi
@@ -0,0 +1,59 @@
+#!/bin/bash -ue
+
+# Usage: fifo_stream.sh <options> <curl-options> <expected-header>
+# Starts a second server with the extra command line <options> and downloads a
+# FIFO while something writes to it in two parts, a second apart. Between the
+# parts, test_small.img is downloaded to check that other connections are still
+# served. Checks that the FIFO's contents arrive in full and that the response
+# has a line matching <expected-header>.
+
+options="$1"
+curl_options="$2"
+expected_header="$3"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+fifo="$DIR/stream.fifo"
+headers=$(mktemp)
+body=$(mktemp)
+
+# The option lists are split into words, but shouldn't be glob-expanded.
+set -f
+
+mkfifo "$fifo"
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options > /dev/null &
+server=$!
+sleep 1
+
+# Opening the FIFO blocks until the server opens it for the download.
+{ echo "first part"; sleep 1; echo "second part"; } > "$fifo" &
+writer=$!
+curl -s $curl_options -D "$headers" -o "$body" "http://localhost:$port/stream.fifo" &
+download=$!
+
+sleep 0.5
+other=$(curl -s -m 1 "http://localhost:$port/test_small.img" | wc -c)
+wait $download || true
+wait $writer || true
+
+kill $server
+wait $server 2> /dev/null || true
+
+received=$(cat "$body")
+header_found=$(tr -d '\r' < "$headers" | grep -c -- "$expected_header" || true)
+rm -f "$fifo" "$headers" "$body"
+
+if [[ "$received" == $'first part\nsecond part' ]] &&
+    [[ $header_found -ge 1 ]] &&
+    [[ $other == $(wc -c < "$DIR/test_small.img") ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "$received" | sed -e 's/^/ >>> response: /'
+    echo "Header '$expected_header' found: $header_found"
+    echo "Bytes of test_small.img received while streaming: $other"
+fi
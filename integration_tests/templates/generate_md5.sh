@@ -0,0 +1,40 @@
+#!/bin/bash -ue
+
+# Usage: generate_md5.sh <file>
+# Starts a second server with --generate-md5, downloads <file> and checks
+# that a .md5sum sidecar with the right digest is written next to it.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+sidecar="$DIR/$file.md5sum"
+
+rm -f "$sidecar"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --generate-md5 > /dev/null &
+server=$!
+sleep 1
+
+curl -s http://localhost:$port/$file > /dev/null
+sleep 1
+
+kill $server
+wait $server 2> /dev/null || true
+
+expected=$(md5sum "$DIR/$file" | awk '{ print $1 }')
+actual=$(cat "$sidecar" 2> /dev/null || true)
+
+if [[ "$expected" == "$actual" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected: $expected"
+    echo "Sidecar: $actual"
+fi
+
+rm -f "$sidecar"
@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Usage: global_rate_limit.sh <bytes-per-second>
+# Starts a second server with --global-rate-limit <bytes-per-second>, downloads
+# two 8M files at the same time, and checks that both arrive whole and that
+# together they came no more than 10% faster than the limit.
+
+limit="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+size=$((8 * 1024 * 1024))
+truncate -s $size "$DIR/rate-limit-a.img" "$DIR/rate-limit-b.img"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --global-rate-limit $limit > /dev/null &
+server=$!
+sleep 1
+
+start=$(date +%s.%N)
+curl -s "http://localhost:$port/rate-limit-a.img" | wc -c > "$DIR/rate-limit-a.len" &
+first=$!
+curl -s "http://localhost:$port/rate-limit-b.img" | wc -c > "$DIR/rate-limit-b.len" &
+second=$!
+wait $first $second
+end=$(date +%s.%N)
+
+kill $server
+wait $server 2> /dev/null || true
+
+received="$(cat "$DIR/rate-limit-a.len") $(cat "$DIR/rate-limit-b.len")"
+rm -f "$DIR"/rate-limit-*
+
+rate=$(python3 -c "print(int(2 * $size / ($end - $start)))")
+if [[ "$received" == "$size $size" ]] && [[ $rate -le $((limit * 11 / 10)) ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Received $received bytes at $rate bytes per second with a limit of $limit"
+fi
@@ -0,0 +1,34 @@
+#!/bin/bash -ue
+
+# Usage: global_rate_limit_burst.sh <bytes-per-second>
+# Starts a second server with --global-rate-limit <bytes-per-second>, starts a
+# download of a file much larger than that, and checks that no more than twice
+# the limit arrived in the first second.
+
+limit="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+truncate -s $((limit * 16)) "$DIR/rate-limit-burst.img"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --global-rate-limit $limit > /dev/null &
+server=$!
+sleep 1
+
+received=$(curl -s --max-time 1 "http://localhost:$port/rate-limit-burst.img" | wc -c)
+
+kill $server
+wait $server 2> /dev/null || true
+
+rm -f "$DIR/rate-limit-burst.img"
+
+if [[ $received -gt 0 ]] && [[ $received -le $((limit * 2)) ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Received $received bytes in the first second with a limit of $limit"
+fi
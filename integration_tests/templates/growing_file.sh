@@ -0,0 +1,64 @@
+#!/bin/bash -ue
+
+# Starts a second server, requests a large file and then a small one on the
+# same connection, appends to the large file once the first response's headers
+# have arrived, and checks that exactly Content-Length bytes of the large file
+# are sent before the second response.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+file="$DIR/growing_file.img"
+# Not a multiple of the server's buffer size, so the last read could run past the end.
+head -c $((32 * 1024 * 1024 + 100)) /dev/zero > "$file"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless > /dev/null &
+server=$!
+sleep 1
+
+result=$(python3 - "$port" "$file" <<'PYTHON'
+import socket, sys, time
+
+port, path = int(sys.argv[1]), sys.argv[2]
+request = "GET /{} HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n"
+sock = socket.create_connection(("localhost", port))
+sock.settimeout(10)
+sock.sendall((request.format("growing_file.img") + request.format("test_small.img")).encode())
+
+data = b""
+while b"\r\n\r\n" not in data:
+    data += sock.recv(4096)
+with open(path, "ab") as f:
+    f.write(b"x" * 1024 * 1024)
+
+head, data = data.split(b"\r\n\r\n", 1)
+length = int([line.split(b": ")[1] for line in head.split(b"\r\n")
+              if line.lower().startswith(b"content-length: ")][0])
+# Read slowly to start with, so the server is still sending when the file grows.
+time.sleep(0.5)
+try:
+    while len(data) < length + 4096:
+        chunk = sock.recv(65536)
+        if not chunk:
+            break
+        data += chunk
+except socket.timeout:
+    pass
+body, rest = data[:length], data[length:]
+print(length, body.count(b"x"), rest.split(b"\r\n")[0].decode(errors="replace"))
+PYTHON
+)
+
+kill $server
+wait $server 2> /dev/null || true
+rm -f "$file"
+
+if [[ "$result" == "33554532 0 HTTP/1.1 200 OK" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Content-Length, appended bytes sent, and the next line: $result"
+fi
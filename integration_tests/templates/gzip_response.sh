@@ -0,0 +1,45 @@
+#!/bin/bash -ue
+
+# Usage: gzip_response.sh <size> <min-size> <expected-encoding>
+# Starts a second server with --gzip-min-size <min-size>, requests a file of
+# <size> bytes accepting gzip, and checks that it is sent with
+# <expected-encoding> (gzip or identity) and arrives intact.
+
+size="$1"
+min_size="$2"
+expected="$3"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+file="gzip-response-$size.txt"
+headers=$(mktemp)
+
+head -c "$size" /dev/zero | tr '\0' 'a' > "$DIR/$file"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --gzip-level 6 \
+    --gzip-min-size $min_size > /dev/null &
+server=$!
+sleep 1
+
+sum=$(curl -s --compressed -D "$headers" "http://localhost:$port/$file" | md5sum |
+    awk '{ print $1 }')
+
+kill $server
+wait $server 2> /dev/null || true
+
+encoding=$(tr -d '\r' < "$headers" | grep "^Content-Encoding: " | awk '{ print $2 }' || true)
+expected_sum=$(md5sum < "$DIR/$file" | awk '{ print $1 }')
+rm -f "$headers" "$DIR/$file"
+
+if [[ "${encoding:-identity}" == "$expected" ]] && [[ "$sum" == "$expected_sum" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Content-Encoding: ${encoding:-none}, expected $expected"
+    echo "Source: $expected_sum"
+    echo "Download: $sum"
+fi
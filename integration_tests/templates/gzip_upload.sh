@@ -0,0 +1,52 @@
+#!/bin/bash -ue
+
+# Usage: gzip_upload.sh <file>
+# Uploads <file> with a gzip-compressed body using both PUT and a multipart
+# POST, and checks that what ends up on disk is the original content.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+mkdir -p "$DIR/gzip-upload"
+boundary="hypershare-gzip-test"
+
+pushd $DIR > /dev/null
+
+put_output=$(gzip -c "$file" | curl -s -X PUT -H "Content-Encoding: gzip" --data-binary @- \
+    "http://localhost:$PORT/gzip-upload/put-$file")
+
+post_body=$(mktemp)
+{
+    printf -- "--%s\r\n" "$boundary"
+    printf "Content-Disposition: form-data; name=\"fileupload\"; filename=\"%s\"\r\n" "$file"
+    printf "Content-Type: application/octet-stream\r\n\r\n"
+    cat "$file"
+    printf "\r\n--%s--\r\n" "$boundary"
+} | gzip -c > "$post_body"
+post_output=$(curl -s -H "Content-Encoding: gzip" \
+    -H "Content-Type: multipart/form-data; boundary=$boundary" --data-binary @"$post_body" \
+    "http://localhost:$PORT/gzip-upload")
+rm -f "$post_body"
+
+popd > /dev/null
+
+source_sum=$(md5sum < "$DIR/$file" | awk '{ print $1 }')
+put_sum=$(md5sum < "$DIR/gzip-upload/put-$file" 2> /dev/null | awk '{ print $1 }' || true)
+post_sum=$(md5sum < "$DIR/gzip-upload/$file" 2> /dev/null | awk '{ print $1 }' || true)
+
+if [[ "$source_sum" == "$put_sum" ]] && [[ "$source_sum" == "$post_sum" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Source: $source_sum"
+    echo "PUT: $put_sum"
+    echo "POST: $post_sum"
+    echo "PUT output: $put_output"
+    echo "POST output: $post_output"
+fi
+
+rm -rf "$DIR/gzip-upload"
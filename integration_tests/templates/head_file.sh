@@ -0,0 +1,30 @@
+#!/bin/bash -ue
+
+# Usage: head_file.sh <file>
+# Sends a HEAD request for <file> and checks that the response advertises
+# byte ranges and the file's full length, without sending a body.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+size=$(stat -c %s "$DIR/$file")
+
+resp=$(echo -en "HEAD /$file HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" |
+    nc -t localhost $PORT | tr -d '\r')
+headers=$(echo "$resp" | sed -e '/^$/q')
+body=$(echo "$resp" | sed -e '1,/^$/d')
+
+if echo "$headers" | grep -q "^HTTP/1.1 200" &&
+    echo "$headers" | grep -q "^Accept-Ranges: bytes$" &&
+    echo "$headers" | grep -q "^Content-Length: $size$" &&
+    [[ -z "$body" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected Content-Length: $size"
+    echo "$resp" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
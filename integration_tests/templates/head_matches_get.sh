@@ -0,0 +1,35 @@
+#!/bin/bash -ue
+
+# Usage: head_matches_get.sh <path> <range>
+# Requests <path> with a Range header of <range> using both GET and HEAD, and
+# checks that the HEAD response has the same headers as the GET and no body.
+# X-Request-Id differs per request, so it isn't compared.
+
+path="$1"
+range="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+request="$path HTTP/1.1\r\nHost: localhost\r\nRange: $range\r\nConnection: close\r\n\r\n"
+
+get_headers=$(echo -en "GET $request" | nc -t localhost $PORT | tr -d '\r' | sed -e '/^$/q' |
+    grep -v "^X-Request-Id: ")
+head_resp=$(echo -en "HEAD $request" | nc -t localhost $PORT | tr -d '\r')
+head_headers=$(echo "$head_resp" | sed -e '/^$/q' | grep -v "^X-Request-Id: ")
+head_body=$(echo "$head_resp" | sed -e '1,/^$/d')
+
+if [[ "$get_headers" == "$head_headers" ]] &&
+    [[ -z "$head_body" ]] &&
+    echo "$head_headers" | grep -q "^HTTP/1.1 206" &&
+    echo "$head_headers" | grep -q "^Content-Range: "
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "GET headers:"
+    echo "$get_headers" | sed -e 's/^/ >>> response: /'
+    echo "HEAD response:"
+    echo "$head_resp" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Creates a listening socket by hand, starts a second server with
+# --inherit-fd to serve from it instead of binding its own, and checks that a
+# file can be downloaded through it.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log=$(mktemp)
+
+python3 -c '
+import os, socket, sys
+listener = socket.socket()
+listener.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+listener.bind(("127.0.0.1", int(sys.argv[1])))
+listener.listen()
+fd = listener.fileno()
+os.set_inheritable(fd, True)
+os.execvp("cargo", ["cargo", "run", "-q", "--", "-d", sys.argv[2], "--headless",
+                    "--inherit-fd", str(fd)])
+' "$port" "$DIR" > "$log" &
+server=$!
+sleep 1
+
+curl -s -o "$log.out" "http://127.0.0.1:$port/$file" || true
+
+kill $server
+wait $server 2> /dev/null || true
+
+if cmp -s "$log.out" "$DIR/$file" && grep -q -x "Listening on 127.0.0.1:$port" "$log"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    sed -e 's/^/ >>> output: /' "$log"
+fi
+
+rm -f "$log" "$log.out"
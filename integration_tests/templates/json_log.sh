@@ -0,0 +1,68 @@
+#!/bin/bash -ue
+
+# Usage: json_log.sh <file>
+# Starts a second server with --log-format json, makes a few requests and
+# checks that every line it writes is a JSON object: the startup event, one
+# entry per request with the expected keys, one upload event, and messages.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log=$(mktemp)
+
+mkdir -p $DIR/json-upload
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --log-format json > "$log" &
+server=$!
+sleep 1
+
+curl -s http://localhost:$port/$file > /dev/null
+curl -s http://localhost:$port/ > /dev/null
+curl -s http://localhost:$port/does-not-exist > /dev/null
+pushd $DIR > /dev/null
+curl -s --form "fileupload=@$file" http://localhost:$port/json-upload > /dev/null
+popd > /dev/null
+sleep 1
+
+kill $server
+wait $server 2> /dev/null || true
+
+size=$(stat -c %s "$DIR/$file")
+
+if python3 -c '
+import json, sys
+keys = {"ts", "ip", "method", "path", "status", "bytes_sent", "duration_ms", "request_id",
+        "completed"}
+lines = [json.loads(line) for line in sys.stdin]
+assert lines[0]["event"] == "startup", lines[0]
+entries = [line for line in lines if "event" not in line]
+uploads = [line for line in lines if line.get("event") == "upload"]
+messages = [line for line in lines if line.get("event") == "message"]
+assert len(lines) == 1 + len(entries) + len(uploads) + len(messages), lines
+assert all(set(message) == {"event", "ts", "message"} for message in messages), messages
+assert len(uploads) == 1, uploads
+assert uploads[0]["path"] == "/json-upload/'"$file"'" and uploads[0]["ip"] == "127.0.0.1", uploads
+assert len(entries) == 4, entries
+for entry in entries:
+    assert set(entry) == keys, entry
+    assert entry["ip"] == "127.0.0.1", entry
+    assert entry["completed"] == True, entry
+    assert entry["method"] == ("POST" if entry is entries[3] else "GET"), entry
+assert entries[0]["path"] == "/'"$file"'" and entries[0]["status"] == 200, entries[0]
+assert entries[0]["bytes_sent"] == '"$size"', entries[0]
+assert entries[2]["status"] == 404, entries[2]
+assert entries[3]["path"] == "/json-upload" and entries[3]["status"] == 201, entries[3]
+assert uploads[0]["size"] == '"$size"', uploads
+' < "$log" 2> /dev/null
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    sed -e 's/^/ >>> output: /' "$log"
+fi
+
+rm -f "$log" "$DIR/json-upload/$file"
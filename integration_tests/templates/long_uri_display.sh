@@ -0,0 +1,47 @@
+#!/bin/bash -ue
+
+# Starts a second server with its interface running in an 80 column
+# pseudo-terminal, keeps a connection open after requesting a very long URI,
+# and checks that the URI is shown shortened with an ellipsis, with the
+# client's address and the end of the URI still visible.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+keys=$(mktemp -u)
+screen=$(mktemp)
+mkfifo "$keys"
+
+long_dir=$(head -c 150 /dev/zero | tr '\0' 'a')
+
+script -qfc "stty cols 80 rows 40; cargo run -q -- -d $DIR -p $port -m 127.0.0.1" /dev/null \
+    < "$keys" > "$screen" 2>&1 &
+server=$!
+exec 7> "$keys"
+sleep 1
+
+exec 3<> /dev/tcp/127.0.0.1/$port
+echo -en "GET /$long_dir/needle-end.txt HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n" >&3
+sleep 1.5
+
+exec 3>&-
+echo -n q >&7
+exec 7>&-
+sleep 1
+kill $server 2> /dev/null || true
+wait $server 2> /dev/null || true
+
+# The interface moves the cursor over blank cells instead of printing spaces.
+shown=$(sed -e 's/\x1b\[[0-9;]*H/ /g' "$screen" |
+    grep -a -o "127.0.0.1:[0-9]* *#1 *=> /a*…a*/needle-end.txt" | tail -n1 || true)
+rm -f "$keys" "$screen"
+
+if [[ -n "$shown" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Connection line: ${shown:-not found}"
+fi
@@ -0,0 +1,44 @@
+#!/bin/bash -ue
+
+# Usage: md5_limit.sh <limit> <files>
+# Starts a second server with --listing-md5-limit <limit>, lists a directory
+# of <files> files that each have a .md5sum sidecar, and checks that the first
+# <limit> digests are shown, that no others are, and that no sidecar is
+# listed as an entry of its own.
+
+limit="$1"
+files="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+rm -rf "$DIR/md5_limit"
+mkdir "$DIR/md5_limit"
+for i in $(seq 1 "$files"); do
+    echo "$i" > "$DIR/md5_limit/file$i"
+    printf "%032d" "$i" > "$DIR/md5_limit/file$i.md5sum"
+done
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --listing-md5-limit "$limit" > /dev/null &
+server=$!
+sleep 1
+
+listing=$(curl -s "http://localhost:$port/md5_limit/" | sed -e 's/<tr>/\n/g')
+
+kill $server
+wait $server 2> /dev/null || true
+rm -rf "$DIR/md5_limit"
+
+shown=$(echo "$listing" | grep -o "MD5: [0-9]*" || true)
+expected=$(for i in $(seq 1 "$limit"); do printf "MD5: %032d\n" "$i"; done)
+
+if [[ "$shown" == "$expected" ]] && ! echo "$listing" | grep -q "\.md5sum<"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected the first $limit digests, got:"
+    echo "$shown" | sed -e 's/^/ >>> response: /'
+fi
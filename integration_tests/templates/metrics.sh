@@ -0,0 +1,52 @@
+#!/bin/bash -ue
+
+# Starts a second server with --enable-metrics, downloads a file, and checks
+# that /_metrics is served as valid Prometheus text with every counter, and
+# that the download was counted.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+headers=$(mktemp)
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --enable-metrics > /dev/null &
+server=$!
+sleep 1
+
+curl -s "http://localhost:$port/test_1m.img" > /dev/null
+metrics=$(curl -s -D "$headers" "http://localhost:$port/_metrics")
+content_type=$(tr -d '\r' < "$headers" | grep "^Content-Type: " || true)
+rm -f "$headers"
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ "$content_type" == "Content-Type: text/plain; version=0.0.4" ]] &&
+    echo "$metrics" | python3 -c '
+import re, sys
+sample = re.compile(r"^([a-zA-Z_:][a-zA-Z0-9_:]*)(\{[^}]*\})? (-?[0-9.eE+-]+|NaN|[+-]Inf)$")
+comment = re.compile(r"^# (HELP|TYPE) [a-zA-Z_:][a-zA-Z0-9_:]* .*$")
+values = {}
+for line in sys.stdin.read().splitlines():
+    if line.startswith("#"):
+        assert comment.match(line), line
+        continue
+    match = sample.match(line)
+    assert match, line
+    values[match.group(1)] = float(match.group(3))
+for name in ["hypershare_requests_total", "hypershare_bytes_sent_total",
+             "hypershare_active_connections", "hypershare_uploads_total"]:
+    assert name in values, name
+assert values["hypershare_requests_total"] >= 2, values
+assert values["hypershare_bytes_sent_total"] >= 1048576, values
+assert values["hypershare_active_connections"] >= 1, values
+'
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "$content_type"
+    echo "$metrics" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Usage: mmap_download.sh <file> [<range>]
+# Starts a second server with --mmap, downloads <file>, or just <range> of it
+# (as first-last), and checks that the bytes match the file on disk.
+
+file="$1"
+range="${2:-}"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+output=$(mktemp)
+expected=$(mktemp)
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --mmap > /dev/null &
+server=$!
+sleep 1
+
+if [[ -n "$range" ]]
+then
+    first="${range%-*}"
+    last="${range#*-}"
+    curl -s -r "$range" -o "$output" "http://localhost:$port/$file"
+    tail -c +$((first + 1)) "$DIR/$file" | head -c $((last - first + 1)) > "$expected"
+else
+    curl -s -o "$output" "http://localhost:$port/$file"
+    cp "$DIR/$file" "$expected"
+fi
+
+kill $server
+wait $server 2> /dev/null || true
+
+if cmp -s "$output" "$expected"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Got $(wc -c < "$output") bytes, expected $(wc -c < "$expected")"
+fi
+rm -f "$output" "$expected"
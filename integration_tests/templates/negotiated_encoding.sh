@@ -0,0 +1,47 @@
+#!/bin/bash -ue
+
+# Usage: negotiated_encoding.sh <accept-encoding> <expected-encoding> [size] [brotli-level]
+# Starts a second server with both gzip and Brotli enabled, requests a text
+# file of [size] bytes (4096 by default) with the given Accept-Encoding header,
+# and checks that it is sent with <expected-encoding> (gzip, br or identity) and
+# arrives intact.
+
+accept="$1"
+expected="$2"
+size="${3:-4096}"
+level="${4:-5}"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+file="negotiated-encoding.txt"
+headers=$(mktemp)
+
+base64 /dev/urandom | head -c "$size" > "$DIR/$file"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --gzip-level 6 \
+    --brotli-level $level > /dev/null &
+server=$!
+sleep 1
+
+sum=$(curl -s --compressed -H "Accept-Encoding: $accept" -D "$headers" \
+    "http://localhost:$port/$file" | md5sum | awk '{ print $1 }')
+
+kill $server
+wait $server 2> /dev/null || true
+
+encoding=$(tr -d '\r' < "$headers" | grep "^Content-Encoding: " | awk '{ print $2 }' || true)
+expected_sum=$(md5sum < "$DIR/$file" | awk '{ print $1 }')
+rm -f "$headers" "$DIR/$file"
+
+if [[ "${encoding:-identity}" == "$expected" ]] && [[ "$sum" == "$expected_sum" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Content-Encoding: ${encoding:-none}, expected $expected"
+    echo "Source: $expected_sum"
+    echo "Download: $sum"
+fi
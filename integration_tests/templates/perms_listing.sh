@@ -0,0 +1,55 @@
+#!/bin/bash -ue
+
+# Usage: perms_listing.sh <mode>=<expected>...
+# Starts a second server with --show-perms, lists a directory holding a file
+# for each <mode> (or a directory, if <expected> starts with d), and checks
+# that each entry is shown with <expected> and its owner and group.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+owner="$(id -un) $(id -gn)"
+mkdir -p "$DIR/perms_listing"
+
+for spec in "$@"; do
+    mode="${spec%%=*}"
+    expected="${spec#*=}"
+    if [[ "$expected" == d* ]]
+    then
+        mkdir "$DIR/perms_listing/entry-$mode"
+    else
+        touch "$DIR/perms_listing/entry-$mode"
+    fi
+    chmod "$mode" "$DIR/perms_listing/entry-$mode"
+done
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --show-perms > /dev/null &
+server=$!
+sleep 1
+
+rows=$(curl -s "http://localhost:$port/perms_listing/" | sed -e 's/<tr>/\n/g')
+
+kill $server
+wait $server 2> /dev/null || true
+rm -rf "$DIR/perms_listing"
+
+failed=0
+for spec in "$@"; do
+    mode="${spec%%=*}"
+    expected="${spec#*=}"
+    if ! echo "$rows" | grep ">entry-$mode<" | grep -q "<pre>$expected $owner</pre>"
+    then
+        failed=1
+        echo "Mode $mode should be shown as $expected $owner:"
+        echo "$rows" | grep ">entry-$mode<" | sed -e 's/^/ >>> response: /' || true
+    fi
+done
+
+if [[ $failed == 0 ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+fi
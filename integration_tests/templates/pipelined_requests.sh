@@ -0,0 +1,42 @@
+#!/bin/bash -ue
+
+# Usage: pipelined_requests.sh <options> <expected-responses>
+# Starts a second server with the extra command line <options>, sends two
+# keep-alive requests in one go, and checks how many of them are answered
+# before the connection is closed.
+
+options="$1"
+expected="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options > /dev/null &
+server=$!
+sleep 1
+
+request="GET /test_small.img HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n"
+resp=$(echo -en "$request$request" | timeout 3 nc -t localhost $port | tr -d '\r' |
+    grep -a "^HTTP/1.1 \|^Connection: " || true)
+
+kill $server
+wait $server 2> /dev/null || true
+
+responses=$(echo "$resp" | grep -c "^HTTP/1.1 200" || true)
+last_connection=$(echo "$resp" | grep "^Connection: " | tail -n1)
+
+if [[ "$responses" == "$expected" ]] &&
+    { [[ "$expected" != "1" ]] || [[ "$last_connection" == "Connection: close" ]]; }
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected $expected responses, got $responses"
+    echo "$resp" | sed -e 's/^/ >>> response: /'
+fi
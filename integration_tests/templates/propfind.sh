@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Usage: propfind.sh <path> <depth> <expected-hrefs...>
+# Sends a PROPFIND for <path> with the given Depth and checks that the
+# response is a 207 with well-formed multistatus XML describing exactly the
+# expected hrefs.
+
+path="$1"
+depth="$2"
+shift 2
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+body=$(mktemp)
+status=$(curl -s -o "$body" -w "%{http_code}" -X PROPFIND -H "Depth: $depth" \
+    "http://localhost:$PORT$path")
+
+if [[ "$status" == "207" ]] && python3 - "$body" "$@" <<'EOF'
+import sys
+import xml.etree.ElementTree as ET
+
+root = ET.parse(sys.argv[1]).getroot()
+assert root.tag == "{DAV:}multistatus", root.tag
+hrefs = sorted(r.findtext("{DAV:}href") for r in root.findall("{DAV:}response"))
+assert hrefs == sorted(sys.argv[2:]), hrefs
+for r in root.findall("{DAV:}response"):
+    prop = r.find("{DAV:}propstat/{DAV:}prop")
+    is_dir = prop.find("{DAV:}resourcetype/{DAV:}collection") is not None
+    assert is_dir == r.findtext("{DAV:}href").endswith("/"), ET.tostring(r)
+    assert is_dir or prop.findtext("{DAV:}getcontentlength") is not None, ET.tostring(r)
+EOF
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Status: $status"
+    head -c 2000 "$body" | sed -e 's/^/ >>> response: /'
+    echo
+fi
+
+rm -f "$body"
@@ -0,0 +1,26 @@
+#!/bin/bash -ue
+
+# Usage: put_continue.sh <expect>
+# Sends the headers of a PUT with the given Expect header, then its body a
+# second later, and checks that 100 Continue came before 201 Created.
+
+expect="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+file="put_continue.txt"
+
+resp=$( (echo -en "PUT /$file HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nExpect: $expect\r\nContent-Length: 5\r\n\r\n"
+    sleep 1
+    echo -n "hello") | nc -t localhost $PORT | tr -d '\r' | grep "^HTTP/1.1" || true)
+rm -f "$DIR/$file"
+
+if [[ "$resp" == $'HTTP/1.1 100 Continue\nHTTP/1.1 201 Created' ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "$resp" | sed -e 's/^/ >>> response: /'
+fi
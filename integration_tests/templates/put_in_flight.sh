@@ -0,0 +1,45 @@
+#!/bin/bash -ue
+
+# Starts sending the first piece of a resumable upload without finishing it,
+# and checks that an overlapping piece is refused while it is in progress, that
+# a piece that doesn't overlap it is accepted, and that the first piece can be
+# sent again once its connection is gone.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+file="put_in_flight.txt"
+put="PUT /$file HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n"
+
+(echo -en "${put}Content-Range: bytes 0-9/20\r\n\r\nhello"; sleep 2) |
+    nc -t localhost $PORT > /dev/null &
+first=$!
+sleep 0.5
+
+overlapping=$(echo -en "${put}Content-Range: bytes 5-14/20\r\n\r\n0123456789" |
+    nc -t localhost $PORT | tr -d '\r' | head -n 1)
+separate=$(echo -en "${put}Content-Range: bytes 10-19/20\r\n\r\n0123456789" |
+    nc -t localhost $PORT | tr -d '\r' | head -n 1)
+
+wait $first 2> /dev/null || true
+sleep 0.5
+
+retried=$(echo -en "${put}Content-Range: bytes 0-9/20\r\n\r\nhelloworld" |
+    nc -t localhost $PORT | tr -d '\r' | head -n 1)
+contents=$(cat "$DIR/$file" 2> /dev/null || true)
+rm -f "$DIR/$file"
+
+if [[ "$overlapping" == "HTTP/1.1 416 Range not satisfiable" ]] &&
+    [[ "$separate" == "HTTP/1.1 308 Resume incomplete" ]] &&
+    [[ "$retried" == "HTTP/1.1 201 Created" ]] &&
+    [[ "$contents" == "helloworld0123456789" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Overlapping piece: $overlapping"
+    echo "Separate piece: $separate"
+    echo "Retried piece: $retried"
+    echo "Contents: $contents"
+fi
@@ -0,0 +1,31 @@
+#!/bin/bash -ue
+
+# Usage: raw_request.sh <request> <expected> [unexpected]
+# Sends <request> (interpreted by `echo -e`, so use \r\n for line endings)
+# and checks that the response contains a line matching <expected>, and no
+# line matching [unexpected] if it is given.
+
+request="$1"
+expected="$2"
+unexpected="${3:-}"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+resp=$(echo -en "$request" | nc -t localhost $PORT | tr -d '\r')
+
+if echo "$resp" | grep -q -E -- "$expected" &&
+    ! ( [[ -n "$unexpected" ]] && echo "$resp" | grep -q -E -- "$unexpected" )
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected to find: $expected"
+    if [[ -n "$unexpected" ]]
+    then
+        echo "Expected not to find: $unexpected"
+    fi
+    echo "Response:"
+    echo "$resp" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
@@ -0,0 +1,24 @@
+#!/bin/bash -ue
+
+# Usage: raw_request_with_opts.sh <options> <request> <expected> [unexpected]
+# Starts a second server with the extra command line <options>, then runs
+# raw_request.sh against it.
+
+options="$1"
+request="$2"
+expected="$3"
+unexpected="${4:-}"
+
+port=$((PORT + 1))
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options > /dev/null &
+server=$!
+sleep 1
+
+PORT=$port templates/raw_request.sh "$request" "$expected" "$unexpected"
+
+kill $server
+wait $server 2> /dev/null || true
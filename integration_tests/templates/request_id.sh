@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Usage: request_id.sh <file>
+# Starts a second server, requests <file> with and without an X-Request-Id
+# and checks that each response carries an id that also appears in the log,
+# and that an inbound id is echoed back.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log=$(mktemp)
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless > "$log" &
+server=$!
+sleep 1
+
+generated=$(curl -s -D - -o /dev/null http://localhost:$port/$file | tr -d '\r' |
+    sed -n -e 's/^X-Request-Id: //p')
+echoed=$(curl -s -D - -o /dev/null -H "X-Request-Id: inbound-42" http://localhost:$port/$file |
+    tr -d '\r' | sed -n -e 's/^X-Request-Id: //p')
+sleep 1
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ -n "$generated" ]] &&
+    [[ "$echoed" == "inbound-42" ]] &&
+    grep -q -F -- "/$file id: $generated" "$log" &&
+    grep -q -F -- "/$file id: inbound-42" "$log"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Generated id: $generated"
+    echo "Echoed id: $echoed"
+    sed -e 's/^/ >>> output: /' "$log"
+fi
+
+rm -f "$log"
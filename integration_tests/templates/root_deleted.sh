@@ -0,0 +1,35 @@
+#!/bin/bash -ue
+
+# Starts a second server on a directory of its own, deletes that directory
+# and checks that requests get a clear 503.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+root=$(mktemp -d)
+
+cargo run -q -- -d $root -p $port -m "127.0.0.1" --headless > /dev/null &
+server=$!
+sleep 1
+
+before=$(echo -en "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" | nc -t localhost $port | head -n1)
+
+rm -r "$root"
+
+after=$(echo -en "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" | nc -t localhost $port | tr -d '\r')
+
+kill $server
+wait $server 2> /dev/null || true
+
+if echo "$before" | grep -q "200 OK" &&
+    echo "$after" | grep -q "^HTTP/1.1 503" &&
+    echo "$after" | grep -q "Server root unavailable"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Before: $before"
+    echo "After: $(echo "$after" | head -n1)"
+fi
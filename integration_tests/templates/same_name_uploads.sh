@@ -0,0 +1,61 @@
+#!/bin/bash -ue
+
+# Usage: same_name_uploads.sh <options> <first-status> <second-status> <contents>
+# Starts a second server with uploading enabled and the extra command line
+# <options>, starts a slow upload, makes a quick upload to the same name while
+# it is in progress, and checks the status each gets and which one's contents
+# end up in the file.
+
+options="$1"
+first_expected="$2"
+second_expected="$3"
+contents_expected="$4"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+mkdir -p "$DIR/same-name" "$DIR/same-name-staging"
+first_out=$(mktemp)
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u $options > /dev/null &
+server=$!
+sleep 1
+
+part="--xyz\r\nContent-Disposition: form-data; name=\"f\"; filename=\"same.txt\"\r\n\r\n"
+end="\r\n--xyz--\r\n"
+head="POST /same-name HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=xyz\r\n"
+length=$(echo -en "${part}xxxx$end" | wc -c)
+
+# The first upload stalls partway through its body.
+(echo -en "${head}Content-Length: $length\r\n\r\n${part}firs"; sleep 2; echo -en "t$end") |
+    timeout 5 nc -t localhost $port > "$first_out" &
+first=$!
+sleep 1
+
+second_status=$(echo -en "${head}Content-Length: $length\r\n\r\n${part}2nd!$end" |
+    timeout 3 nc -t localhost $port | head -n1 | tr -d '\r' || true)
+wait $first || true
+first_status=$(head -n1 "$first_out" | tr -d '\r')
+
+kill $server
+wait $server 2> /dev/null || true
+
+contents=$(cat "$DIR/same-name/same.txt" 2> /dev/null || true)
+rm -rf "$DIR/same-name" "$DIR/same-name-staging" "$first_out"
+
+if [[ "$first_status" == "HTTP/1.1 $first_expected"* ]] &&
+    [[ "$second_status" == "HTTP/1.1 $second_expected"* ]] &&
+    [[ "$contents" == "$contents_expected" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "First upload: expected $first_expected, got: $first_status"
+    echo "Second upload: expected $second_expected, got: $second_status"
+    echo "Expected $contents_expected in the file, found: $contents"
+fi
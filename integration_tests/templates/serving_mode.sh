@@ -0,0 +1,41 @@
+#!/bin/bash -ue
+
+# Usage: serving_mode.sh <options> <get-status> <post-status> <put-status>
+# Starts a second server with uploading enabled and the extra command line
+# <options>, then downloads test_small.img, uploads it by form and by PUT, and
+# checks the status of each request.
+
+options="$1"
+expected="$2 $3 $4"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u $options > /dev/null &
+server=$!
+sleep 1
+
+get=$(curl -s -o /dev/null -w "%{http_code}" "http://localhost:$port/test_small.img")
+post=$(curl -s -o /dev/null -w "%{http_code}" -F "fileupload=@$DIR/test_small.img;filename=serving-mode-post" \
+    "http://localhost:$port/")
+put=$(curl -s -o /dev/null -w "%{http_code}" -T "$DIR/test_small.img" \
+    "http://localhost:$port/serving-mode-put")
+
+kill $server
+wait $server 2> /dev/null || true
+
+rm -f "$DIR/serving-mode-post" "$DIR/serving-mode-put"
+
+if [[ "$get $post $put" == "$expected" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected $expected, got: $get $post $put"
+fi
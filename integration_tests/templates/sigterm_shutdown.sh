@@ -0,0 +1,49 @@
+#!/bin/bash -ue
+
+# Usage: sigterm_shutdown.sh <file>
+# Starts a second server, begins a slow download of <file>, and sends the
+# server SIGTERM part way through. The download should still complete, new
+# connections should be refused, and the server should then exit cleanly.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+download=$(mktemp)
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless > /dev/null &
+server=$!
+sleep 1
+
+curl -s --limit-rate 256k -o "$download" "http://localhost:$port/$file" &
+client=$!
+sleep 1
+
+kill -TERM $server
+sleep 0.5
+refused=0
+curl -s -o /dev/null --max-time 2 "http://localhost:$port/$file" || refused=$?
+
+wait $client || true
+status=0
+timeout 10 tail --pid=$server -f /dev/null || status=timeout
+wait $server 2> /dev/null || status=$?
+
+res1=$(md5sum < "$DIR/$file" | awk '{ print $1 }')
+res2=$(md5sum < "$download" | awk '{ print $1 }')
+rm -f "$download"
+
+if [[ "$res1" == "$res2" ]] && [[ "$refused" != "0" ]] && [[ "$status" == "0" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    kill -KILL $server 2> /dev/null || true
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Source: $res1"
+    echo "Download: $res2"
+    echo "curl exit code for a new connection: $refused (0 means it was served)"
+    echo "Server exit status: $status"
+fi
@@ -0,0 +1,42 @@
+#!/bin/bash -ue
+
+# Usage: slow_request.sh <options>
+# Starts a second server with the extra command line <options>, trickles a
+# request to it one byte per second and checks that the connection is dropped
+# without a response.
+
+options="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options > /dev/null &
+server=$!
+sleep 1
+
+request=$'GET / HTTP/1.1\r\nHost: localhost\r\n\r\n'
+
+start=$SECONDS
+resp=$(
+(
+for (( i=0; i<${#request}; i++ ))
+do
+    echo -n "${request:$i:1}" 2> /dev/null || break
+    sleep 1
+done
+) | nc -t localhost $port | head -n1)
+elapsed=$((SECONDS - start))
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ -z "$resp" ]] && (( elapsed < 15 ))
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Response after ${elapsed}s: $resp"
+fi
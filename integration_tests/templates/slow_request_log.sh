@@ -0,0 +1,50 @@
+#!/bin/bash -ue
+
+# Usage: slow_request_log.sh <log-format>
+# Starts a second server with --slow-request-ms 300 and the given log format,
+# downloads a small file, uploads a 1M file at a limited rate, and checks that
+# only the slow upload is flagged.
+
+format="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log=$(mktemp)
+mkdir -p "$DIR/slow-upload"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --slow-request-ms 300 \
+    --log-format "$format" > "$log" &
+server=$!
+sleep 1
+
+curl -s -o /dev/null "http://localhost:$port/test_small.img"
+pushd "$DIR" > /dev/null
+curl -s -o /dev/null --limit-rate 1M --form "fileupload=@test_1m.img" \
+    "http://localhost:$port/slow-upload"
+popd > /dev/null
+sleep 0.5
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ "$format" == "text" ]]
+then
+    grep -q "^SLOW [0-9]*ms .* 201 POST /slow-upload" "$log" &&
+        ! grep -q "^SLOW .*/test_small.img" "$log"
+else
+    grep '"path":"/slow-upload"' "$log" | grep -q '"slow":true' &&
+        grep '"path":"/test_small.img"' "$log" | grep -q '"slow":false'
+fi && passed=1 || passed=0
+
+if [[ $passed == 1 ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    sed -e 's/^/ >>> output: /' "$log"
+fi
+
+rm -rf "$log" "$DIR/slow-upload"
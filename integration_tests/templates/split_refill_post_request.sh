@@ -0,0 +1,50 @@
+#!/bin/bash -ue
+
+# Usage: split_refill_post_request.sh <size> <offset>
+# Uploads a file of <size> bytes, pausing once the body has been sent up to
+# <offset> bytes past the end of the file's data (negative offsets land inside
+# the data). With <size> around the server's upload buffer size, this puts the
+# closing delimiter across a refill of the buffer.
+
+size="$1"
+offset="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+source_file=$(mktemp)
+body=$(mktemp)
+output_file="refill-$size-$offset.img"
+boundary="refill-test-boundary"
+
+head -c "$size" /dev/urandom > "$source_file"
+
+printf -- "--%s\r\nContent-Disposition: form-data; name=\"f\"; filename=\"%s\"\r\n\r\n" \
+    "$boundary" "$output_file" > "$body"
+split=$(( $(stat -c %s "$body") + size + offset ))
+cat "$source_file" >> "$body"
+printf "\r\n--%s--\r\n" "$boundary" >> "$body"
+
+(
+    printf "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n"
+    printf "Content-Type: multipart/form-data; boundary=%s\r\n" "$boundary"
+    printf "Content-Length: %s\r\n\r\n" "$(stat -c %s "$body")"
+    head -c "$split" "$body"
+    sleep 1
+    tail -c +$((split + 1)) "$body"
+) | nc -t localhost $PORT | head -n1 | sed -e 's/^/ >>> response: /'
+
+res1=$(md5sum < "$source_file" | awk '{ print $1 }')
+res2=$(md5sum < "$DIR/$output_file" 2> /dev/null | awk '{ print $1 }' || true)
+
+if [[ "$res1" == "$res2" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Source: $res1"
+    echo "Output: $res2"
+fi
+
+rm -f "$source_file" "$body" "$DIR/$output_file"
@@ -0,0 +1,48 @@
+#!/bin/bash -ue
+
+# Usage: staged_upload.sh <file> <temp-dir>
+# Starts a second server that stages uploads in <temp-dir>, uploads <file>
+# and checks that it ends up in place and that nothing is left behind.
+
+file="$1"
+temp_dir="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+mkdir -p "$temp_dir" $DIR/staged-upload
+output_file="staged-upload/$file"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --upload-temp-dir "$temp_dir" > /dev/null &
+server=$!
+sleep 1
+
+pushd $DIR > /dev/null
+curl -s --form "fileupload=@$file" http://localhost:$port/staged-upload > /dev/null
+popd > /dev/null
+
+kill $server
+wait $server 2> /dev/null || true
+
+res="$(md5sum "$DIR/$file" "$DIR/$output_file" | awk '{ print $1 }')"
+
+res1=$(echo $res | awk '{ print $1 }')
+res2=$(echo $res | awk '{ print $2 }')
+
+leftover=$(ls -A "$temp_dir")
+
+if [[ "$res1" ==  "$res2" ]] && [[ -z "$leftover" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Source: $res1"
+    echo "Output: $res2"
+    echo "Left in temp dir: $leftover"
+fi
+
+rm -f "$DIR/$output_file"
+rmdir "$temp_dir"
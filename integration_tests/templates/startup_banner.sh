@@ -0,0 +1,48 @@
+#!/bin/bash -ue
+
+# Starts a second server headless with uploads and ZIP downloads, once with
+# text logs and once with JSON logs, and checks that each prints a banner with
+# the revision, root, address and enabled features.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+root=$(realpath "$DIR")
+text_log=$(mktemp)
+json_log=$(mktemp)
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --zip > "$text_log" &
+server=$!
+sleep 1
+kill $server
+wait $server 2> /dev/null || true
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u --zip --log-format json > "$json_log" &
+server=$!
+sleep 1
+kill $server
+wait $server 2> /dev/null || true
+
+if grep -q "^hypershare revision" "$text_log" &&
+    grep -q -x "Serving $root" "$text_log" &&
+    grep -q -x "Features: uploads, listings, zip" "$text_log" &&
+    grep -q -x "Listening on 127.0.0.1:$port" "$text_log" &&
+    head -n1 "$json_log" | python3 -c '
+import json, sys
+banner = json.loads(sys.stdin.read())
+assert banner["event"] == "startup", banner
+assert isinstance(banner["version"], str), banner
+assert banner["root"] == "'"$root"'", banner
+assert banner["address"] == "127.0.0.1:'"$port"'", banner
+assert banner["features"] == ["uploads", "listings", "zip"], banner
+' 2> /dev/null
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    sed -e 's/^/ >>> output: /' "$text_log" "$json_log"
+fi
+
+rm -f "$text_log" "$json_log"
@@ -0,0 +1,25 @@
+#!/bin/bash -ue
+
+# Usage: startup_error.sh <options> <expected>
+# Starts a second server with the extra command line <options> and checks
+# that it exits with output matching <expected>.
+
+options="$1"
+expected="$2"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+output=$(timeout 10 cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless $options 2>&1 || true)
+
+if echo "$output" | grep -q -E -- "$expected"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected to find: $expected"
+    echo "$output" | head -n 10 | sed -e 's/^/ >>> output: /'
+fi
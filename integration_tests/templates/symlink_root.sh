@@ -0,0 +1,52 @@
+#!/bin/bash -ue
+
+# Starts second servers on a real root and on a symlink to it, with and
+# without --no-symlink-root, and checks that only the symlinked root is
+# warned about or refused.
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+link=$(mktemp -u)
+ln -s "$DIR" "$link"
+
+# Runs a server on <root> with any extra options for a second and prints what
+# it said.
+run_server() {
+    root="$1"
+    shift
+    log=$(mktemp)
+    cargo run -q -- -d "$root" -p $port -m "127.0.0.1" --headless "$@" > "$log" 2>&1 &
+    server=$!
+    sleep 1
+    kill $server 2> /dev/null || true
+    wait $server 2> /dev/null || true
+    cat "$log"
+    rm -f "$log"
+}
+
+real=$(run_server "$DIR")
+real_strict=$(run_server "$DIR" --no-symlink-root)
+linked=$(run_server "$link/")
+linked_strict=$(run_server "$link" --no-symlink-root)
+
+rm "$link"
+
+if ! echo "$real" | grep -q "symlink" &&
+    ! echo "$real_strict" | grep -q "symlink" &&
+    echo "$real_strict" | grep -q "^Listening on" &&
+    echo "$linked" | grep -q "^Warning: .* is a symlink" &&
+    echo "$linked" | grep -q "^Listening on" &&
+    echo "$linked_strict" | grep -q "^Error: .* is a symlink" &&
+    ! echo "$linked_strict" | grep -q "^Listening on"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Real root: $real"
+    echo "Real root, strict: $real_strict"
+    echo "Symlinked root: $linked"
+    echo "Symlinked root, strict: $linked_strict"
+fi
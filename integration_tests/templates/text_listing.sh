@@ -0,0 +1,34 @@
+#!/bin/bash -ue
+
+# Usage: text_listing.sh <query> [curl-args...]
+# Lists a small directory with the query string <query> (which may be empty)
+# and any extra curl arguments, and checks that the listing comes back as
+# plain text with one name per line.
+
+query="$1"
+shift
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+mkdir -p "$DIR/text_listing/sub"
+echo "a" > "$DIR/text_listing/a.txt"
+echo "b" > "$DIR/text_listing/b.txt"
+
+headers=$(mktemp)
+listing=$(curl -s -D "$headers" "$@" "http://localhost:$PORT/text_listing/$query")
+content_type=$(tr -d '\r' < "$headers" | grep "^Content-Type: " || true)
+rm -f "$headers"
+rm -r "$DIR/text_listing"
+
+expected=$(printf "sub/\na.txt\nb.txt")
+
+if [[ "$listing" == "$expected" ]] && [[ "$content_type" == "Content-Type: text/plain; charset=utf-8" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "$content_type"
+    echo "$listing" | head -n 20 | sed -e 's/^/ >>> response: /'
+fi
@@ -0,0 +1,47 @@
+#!/bin/bash -ue
+
+# Usage: transfer_end_log.sh <log-format>
+# Starts a second server with the given log format, downloads test_small.img in
+# full, and aborts a download of a 64M file after its first byte. Checks that
+# the history says the first transfer completed and the second was aborted.
+
+format="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log=$(mktemp)
+truncate -s 64M "$DIR/transfer-end.img"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless --log-format "$format" > "$log" &
+server=$!
+sleep 1
+
+curl -s -o /dev/null "http://localhost:$port/test_small.img"
+curl -s "http://localhost:$port/transfer-end.img" | head -c 1 > /dev/null
+sleep 0.5
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ "$format" == "text" ]]
+then
+    grep -q "^COMPLETED .* 200 GET  */test_small.img" "$log" &&
+        grep -q "^ABORTED .* 200 GET  */transfer-end.img .*after [0-9]* bytes$" "$log" &&
+        ! grep -q "^COMPLETED .*/transfer-end.img" "$log"
+else
+    grep '"path":"/test_small.img"' "$log" | grep -q '"completed":true' &&
+        grep '"path":"/transfer-end.img"' "$log" | grep -q '"completed":false'
+fi && passed=1 || passed=0
+
+if [[ $passed == 1 ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    sed -e 's/^/ >>> output: /' "$log"
+fi
+
+rm -f "$log" "$DIR/transfer-end.img"
@@ -0,0 +1,39 @@
+#!/bin/bash -ue
+
+# Usage: two_requests.sh <first> <second> <delay> <expected>
+# Sends two requests on one connection, <delay> seconds apart (in a single
+# write if <delay> is 0), and checks that exactly two lines of the response
+# match <expected>.
+
+first="$1"
+second="$2"
+delay="$3"
+expected="$4"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+resp=$(
+(
+if [[ "$delay" == "0" ]]
+then
+    echo -en "$first$second"
+else
+    echo -en "$first"
+    sleep $delay
+    echo -en "$second"
+fi
+) | nc -t localhost $PORT | tr -d '\r')
+
+count=$(echo "$resp" | grep -c -E -- "$expected" || true)
+
+if [[ "$count" == "2" ]]
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected two lines matching: $expected (found $count)"
+    echo "Response:"
+    echo "$resp" | grep -E "^HTTP/" | sed -e 's/^/ >>> response: /'
+fi
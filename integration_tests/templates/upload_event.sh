@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Usage: upload_event.sh <file>
+# Starts a second server, uploads <file> to it and checks that the completed
+# upload is reported with its size.
+
+file="$1"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+log=$(mktemp)
+
+mkdir -p $DIR/event-upload
+output_file="event-upload/$file"
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u > "$log" &
+server=$!
+sleep 1
+
+pushd $DIR > /dev/null
+curl -s --form "fileupload=@$file" http://localhost:$port/event-upload > /dev/null
+popd > /dev/null
+sleep 1
+
+kill $server
+wait $server 2> /dev/null || true
+
+size=$(stat -c %s "$DIR/$file")
+expected="Upload complete: /$output_file ($size bytes) from 127.0.0.1"
+
+if grep -q -F -- "$expected" "$log"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected to find: $expected"
+    sed -e 's/^/ >>> output: /' "$log"
+fi
+
+rm -f "$DIR/$output_file" "$log"
@@ -0,0 +1,43 @@
+#!/bin/bash -ue
+
+# Usage: upload_filename.sh <disposition-params> <expected-status> [stored-name]
+# Uploads a small file whose Content-Disposition ends with <disposition-params>,
+# checks the status, and, if <stored-name> is given, that the file was stored
+# under that name.
+
+params="$1"
+expected="$2"
+stored="${3:-}"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+body=$(printf -- "--xyz\r\nContent-Disposition: form-data; name=\"f\"; %s\r\n\r\nhello\r\n--xyz--\r\n" \
+    "$params")
+
+status=$(
+    {
+        printf "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n"
+        printf "Content-Type: multipart/form-data; boundary=xyz\r\n"
+        printf "Content-Length: %s\r\n\r\n" "$(printf "%s" "$body" | wc -c)"
+        printf "%s" "$body"
+    } | timeout 3 nc -t localhost $PORT | head -n1 | tr -d '\r'
+)
+
+contents=""
+if [[ -n "$stored" ]]
+then
+    contents=$(cat "$DIR/$stored" 2> /dev/null || true)
+    rm -f "$DIR/$stored"
+fi
+
+if [[ "$status" == "HTTP/1.1 $expected"* ]] &&
+    { [[ -z "$stored" ]] || [[ "$contents" == "hello" ]]; }
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected $expected, got: $status"
+    [[ -z "$stored" ]] || echo "Contents of $stored: $contents"
+fi
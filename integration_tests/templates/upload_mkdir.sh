@@ -0,0 +1,57 @@
+#!/bin/bash -ue
+
+# Usage: upload_mkdir.sh <options> <POST|PUT> <target> <expected-status> <path>
+# Starts a second server with uploading enabled and the extra command line
+# <options>, uploads test_small.img to <target>, by form or by PUT, and checks
+# the status, and that <path>, relative to $DIR, exists only if the upload
+# succeeded.
+
+options="$1"
+method="$2"
+target="$3"
+expected="$4"
+path="$5"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+port=$((PORT + 1))
+
+# <options> is split into words, but shouldn't be glob-expanded.
+set -f
+
+cargo run -q -- -d $DIR -p $port -m "127.0.0.1" --headless -u $options > /dev/null &
+server=$!
+sleep 1
+
+if [[ "$method" == "POST" ]]
+then
+    upload=(-F "fileupload=@$DIR/test_small.img")
+else
+    upload=(-T "$DIR/test_small.img")
+fi
+status=$(curl -s -o /dev/null -w "%{http_code}" --path-as-is "${upload[@]}" \
+    "http://localhost:$port$target")
+
+kill $server
+wait $server 2> /dev/null || true
+
+if [[ -e "$DIR/$path" ]]
+then
+    created=1
+else
+    created=0
+fi
+rm -rf "$DIR/upload-mkdir" "$DIR/../upload-mkdir-escape"
+
+if [[ "$status" == "$expected" ]] &&
+    { { [[ "$expected" == "201" ]] && [[ $created == 1 ]]; } ||
+        { [[ "$expected" != "201" ]] && [[ $created == 0 ]]; }; }
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    echo "Expected $expected, got: $status"
+    echo "$path was created: $created"
+fi
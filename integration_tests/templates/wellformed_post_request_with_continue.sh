@@ -1,6 +1,11 @@
 #!/bin/bash -ue
 
+# Usage: wellformed_post_request_with_continue.sh <file> [expect]
+# Uploads <file> with an Expect header (100-continue by default), sending the
+# body only after a second, and checks that 100 Continue was sent.
+
 file="$1"
+expect="${2:-100-continue}"
 
 output_file="dest.img"
 
@@ -19,7 +24,7 @@ POST / HTTP/1.1$CR
 Host: localhost$CR
 Connection: close$CR
 Content-Type: multpart/form-data;boundary="$BOUNDARY"$CR
-Expect: 100-continue$CR
+Expect: $expect$CR
 $CR
 --$BOUNDARY$CR
 Content-Disposition: form-data; filename="$output_file"$CR
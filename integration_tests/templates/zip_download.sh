@@ -0,0 +1,33 @@
+#!/bin/bash -ue
+
+# Usage: zip_download.sh <dir> [<range>]
+# Downloads a directory with ?zip, unzips it and compares it to the original.
+# With <range>, the download asks for just that Range, and must get the whole
+# archive with 200 and Accept-Ranges: none anyway.
+
+dir="$1"
+range="${2:-}"
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+NC='\033[0m' # No Color
+
+output_dir=$(mktemp -d)
+
+headers=$(curl -s -D - -H "${range:+Range: $range}" -o "$output_dir/out.zip" \
+    "http://localhost:$PORT/$dir/?zip" | tr -d '\r')
+
+unzip -q "$output_dir/out.zip" -d "$output_dir/unzipped" || true
+
+if diff -r "$DIR/$dir" "$output_dir/unzipped" > /dev/null &&
+    echo "$headers" | head -n1 | grep -q "^HTTP/1.1 200" &&
+    echo "$headers" | grep -q -x "Accept-Ranges: none"
+then
+    echo -e "${GREEN}Passed${NC}"
+else
+    echo -e "${RED}Failed!!!${NC}"
+    diff -r "$DIR/$dir" "$output_dir/unzipped" | head -n 10 | sed -e 's/^/ >>> diff: /'
+    echo "$headers" | sed -e 's/^/ >>> response: /'
+fi
+
+rm -r "$output_dir"
@@ -1,6 +1,6 @@
 pub mod types;
 
-use crate::opts::types::Opts;
+use crate::{http::ServingMode, opts::types::Opts};
 
 use types::{Connection, ConnectionSet, ControlEvent};
 
@@ -26,7 +26,32 @@ use std::{
 use nix::unistd;
 use std::{net::SocketAddr, os::unix::io::RawFd};
 
-fn build_conn_str(addr: &SocketAddr, conn: &mut Connection) -> String {
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+// Shortens `s` to at most `width` characters by replacing its middle with an ellipsis, so both
+// the start of a path and the file name at its end stay visible.
+fn ellipsize(s: &str, width: usize) -> String {
+    let len = s.chars().count();
+    if len <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let kept = width - 1;
+    let head = (kept + 1) / 2;
+    let tail = kept - head;
+    let mut res: String = s.chars().take(head).collect();
+    res.push('…');
+    res.extend(s.chars().skip(len - tail));
+    res
+}
+
+// The space a list has for each line, inside the screen's margin and the list's borders
+fn list_width(term_width: u16) -> usize { term_width.saturating_sub(4) as usize }
+
+// Only the URI is shortened to fit `width`, so the address and request count are always shown.
+fn build_conn_str(addr: &SocketAddr, conn: &mut Connection, width: usize) -> String {
     let ip_str = match addr {
         SocketAddr::V4(v4_addr) => {
             format!("{host}:{port}", host = v4_addr.ip(), port = v4_addr.port())
@@ -40,10 +65,15 @@ fn build_conn_str(addr: &SocketAddr, conn: &mut Connection) -> String {
         }
     };
 
-    format!(
-        "{ip_req:<26} => {uri}",
+    let prefix = format!(
+        "{ip_req:<26} => ",
         ip_req = format!("{ip:<22} #{num}", ip = ip_str, num = conn.num_requests,),
-        uri = conn.last_requested_uri
+    );
+    let uri_width = width.saturating_sub(prefix.chars().count());
+    format!(
+        "{}{}",
+        prefix,
+        ellipsize(&conn.last_requested_uri, uri_width)
     )
 }
 
@@ -54,13 +84,33 @@ fn build_speed_str(conn: &mut Connection) -> String {
         100 * conn.bytes_sent / conn.bytes_requested
     };
     let speed = conn.estimated_speed();
+    let upload_str = if conn.uploading {
+        match conn.upload_progress() {
+            Some(upload_perc) => format!(
+                "/{len} ({perc}%)",
+                len = conn.upload_length.unwrap_or(0),
+                perc = upload_perc
+            ),
+            None => {
+                conn.spinner_idx = (conn.spinner_idx + 1) % SPINNER.len();
+                format!(" ({})", SPINNER[conn.spinner_idx])
+            }
+        }
+    } else {
+        format!("")
+    };
     let speed_str = format!(
-        "D:{sent}/{reqd}\t ({perc}% {speed} MiB/s) U:{upsent}",
+        "D:{sent}/{reqd}\t ({perc}% {speed} MiB/s) U:{upsent}{upload}",
         sent = conn.bytes_sent,
         reqd = conn.bytes_requested,
         perc = perc,
         speed = speed / (1024. * 1024.),
-        upsent = conn.bytes_read,
+        upsent = if conn.uploading {
+            conn.upload_bytes_read
+        } else {
+            conn.bytes_read
+        },
+        upload = upload_str,
     );
 
     speed_str
@@ -71,10 +121,11 @@ fn build_conn_span<'a>(
     conn: &'a mut Connection,
     term_width: u16,
 ) -> Vec<Spans<'static>> {
-    let conn_s = build_conn_str(addr, conn);
+    let width = list_width(term_width);
+    let conn_s = build_conn_str(addr, conn, width);
     let speed_s = build_speed_str(conn);
 
-    if conn_s.len() + speed_s.len() + 1 <= (term_width - 4) as usize {
+    if conn_s.chars().count() + speed_s.len() + 1 <= width {
         vec![Spans::from(Span::raw(format!("{} {}", conn_s, speed_s)))]
     } else {
         vec![
@@ -97,7 +148,9 @@ pub fn display(
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut enabled = !opts.start_disabled;
+    let mut serving_mode = ServingMode::from_opts(opts);
+    let mut dir_listings = !opts.disable_directory_listings;
+    let mut uploading = opts.uploading_enabled;
 
     'outer: loop {
         // Print that the connection has been established
@@ -112,11 +165,24 @@ pub fn display(
                     .collect()
             };
 
+            let messages_uploads: Vec<ListItem> = {
+                conn_set
+                    .uploads
+                    .iter()
+                    .map(|s| ListItem::new(vec![Spans::from(Span::raw(s))]))
+                    .collect()
+            };
+
             let messages_history: Vec<ListItem> = {
                 conn_set
                     .history
                     .iter()
-                    .map(|s| ListItem::new(vec![Spans::from(Span::raw(s))]))
+                    .map(|s| {
+                        ListItem::new(vec![Spans::from(Span::raw(ellipsize(
+                            s,
+                            list_width(width),
+                        )))])
+                    })
                     .collect()
             };
 
@@ -126,8 +192,10 @@ pub fn display(
                     .margin(1)
                     .constraints(
                         [
-                            Constraint::Length(7),
+                            Constraint::Length(8),
                             Constraint::Min(2),
+                            // Recent uploads are only shown while uploading is enabled
+                            Constraint::Length(if uploading { 7 } else { 0 }),
                             Constraint::Percentage(50),
                         ]
                         .as_ref(),
@@ -143,22 +211,19 @@ pub fn display(
                         "Listening on {}:{}",
                         opts.hostmask, opts.port
                     )))]),
+                    ListItem::new(vec![Spans::from(Span::raw(format!(
+                        "Serving as uid {}, gid {}",
+                        unistd::geteuid(),
+                        unistd::getegid()
+                    )))]),
                     ListItem::new(vec![Spans::from(Span::raw(format!(
                         "Directory listings: {}",
-                        if opts.disable_directory_listings {
-                            "Disabled"
-                        } else {
-                            "Enabled"
-                        }
+                        if dir_listings { "Enabled" } else { "Disabled" }
                     )))]),
                     ListItem::new(vec![Spans::from(Span::raw(format!(
                         "Uploading: {}{}",
-                        if opts.uploading_enabled {
-                            "Enabled"
-                        } else {
-                            "Disabled"
-                        },
-                        if opts.size_limit > 0 && opts.uploading_enabled {
+                        if uploading { "Enabled" } else { "Disabled" },
+                        if opts.size_limit > 0 && uploading {
                             format!(" (limit: {})", opts.size_limit)
                         } else {
                             format!("")
@@ -166,11 +231,7 @@ pub fn display(
                     )))]),
                     ListItem::new(vec![Spans::from(Span::raw(format!(
                         "Status: {}",
-                        if enabled {
-                            "Serving requests"
-                        } else {
-                            "Rejecting requests"
-                        },
+                        serving_mode.describe(),
                     )))]),
                 ])
                 .block(Block::default().borders(Borders::ALL).title("Information"));
@@ -180,12 +241,21 @@ pub fn display(
                     .block(Block::default().borders(Borders::ALL).title("Connections"));
                 f.render_widget(block, chunks[1]);
 
+                if uploading {
+                    let block = List::new(messages_uploads).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title("Recent Uploads"),
+                    );
+                    f.render_widget(block, chunks[2]);
+                }
+
                 let block = List::new(messages_history).block(
                     Block::default()
                         .borders(Borders::ALL)
                         .title("Request History"),
                 );
-                f.render_widget(block, chunks[2]);
+                f.render_widget(block, chunks[3]);
             })?;
         }
 
@@ -196,11 +266,30 @@ pub fn display(
                 }
                 Ok(ControlEvent::Toggle) => {
                     let _ = unistd::write(write_end, b"t");
-                    enabled = !enabled;
+                    serving_mode.toggle_all();
+                }
+                Ok(ControlEvent::ToggleReads) => {
+                    let _ = unistd::write(write_end, b"r");
+                    serving_mode.reads = !serving_mode.reads;
+                }
+                Ok(ControlEvent::ToggleUploads) => {
+                    let _ = unistd::write(write_end, b"w");
+                    serving_mode.uploads = !serving_mode.uploads;
                 }
                 Ok(ControlEvent::CloseAll) => {
                     let _ = unistd::write(write_end, b"k");
                 }
+                Ok(ControlEvent::CloseIdle) => {
+                    let _ = unistd::write(write_end, b"i");
+                }
+                Ok(ControlEvent::ToggleDirListings) => {
+                    let _ = unistd::write(write_end, b"d");
+                    dir_listings = !dir_listings;
+                }
+                Ok(ControlEvent::ToggleUploading) => {
+                    let _ = unistd::write(write_end, b"u");
+                    uploading = !uploading;
+                }
                 Err(mpsc::TryRecvError::Empty) => {
                     break;
                 }
@@ -224,3 +313,38 @@ pub fn display(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ellipsize_leaves_short_strings_alone() {
+        assert_eq!(ellipsize("/short", 10), "/short");
+        assert_eq!(ellipsize("/exact", 6), "/exact");
+        assert_eq!(ellipsize("", 0), "");
+    }
+
+    #[test]
+    fn ellipsize_to_zero_width() {
+        assert_eq!(ellipsize("/anything", 0), "");
+    }
+
+    #[test]
+    fn ellipsize_to_odd_width() {
+        assert_eq!(ellipsize("abcdefghij", 7), "abc…hij");
+        assert_eq!(ellipsize("abcdefghij", 1), "…");
+    }
+
+    #[test]
+    fn ellipsize_to_even_width() {
+        assert_eq!(ellipsize("abcdefghij", 6), "abc…ij");
+        assert_eq!(ellipsize("abcdefghij", 2), "a…");
+    }
+
+    #[test]
+    fn ellipsize_counts_characters_not_bytes() {
+        assert_eq!(ellipsize("日本語のファイル名.txt", 8), "日本語の…txt");
+        assert_eq!(ellipsize("日本語.txt", 7), "日本語.txt");
+    }
+}
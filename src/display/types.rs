@@ -1,6 +1,6 @@
-use crate::http::HttpConnection;
+use crate::http::{CompletedUpload, ConnectionState, HttpConnection};
 
-use std::{collections::HashMap, net::SocketAddr, time};
+use std::{cmp::min, collections::HashMap, net::SocketAddr, time};
 
 pub struct ConnectionSpeedMeasurement {
     speeds: [f32; 3],
@@ -34,6 +34,10 @@ pub struct Connection {
     pub avg_speed: ConnectionSpeedMeasurement,
     pub last_requested_uri: String,
     pub num_requests: usize,
+    pub uploading: bool,
+    pub upload_bytes_read: usize,
+    pub upload_length: Option<usize>,
+    pub spinner_idx: usize,
 }
 
 impl Connection {
@@ -49,6 +53,10 @@ impl Connection {
             avg_speed: ConnectionSpeedMeasurement::new(),
             last_requested_uri: "[Reading...]".to_string(),
             num_requests: 0,
+            uploading: false,
+            upload_bytes_read: 0,
+            upload_length: None,
+            spinner_idx: 0,
         }
     }
 
@@ -56,6 +64,10 @@ impl Connection {
         self.bytes_sent = conn.bytes_sent;
         self.bytes_requested = conn.bytes_requested;
         self.bytes_read = conn.bytes_read;
+        self.uploading = conn.state == ConnectionState::ReadingPostBody
+            || conn.state == ConnectionState::ReadingPutBody;
+        self.upload_bytes_read = conn.body_bytes_read();
+        self.upload_length = conn.upload_length;
         if let Some(uri) = &conn.last_requested_uri {
             if self.num_requests < conn.num_requests {
                 self.last_requested_uri = uri.clone();
@@ -82,6 +94,19 @@ impl Connection {
 
         self.avg_speed.get_avg()
     }
+
+    // Percentage of the upload received, or None if the length was not declared.
+    pub fn upload_progress(&self) -> Option<usize> {
+        upload_progress(self.upload_bytes_read, self.upload_length)
+    }
+}
+
+pub fn upload_progress(bytes_read: usize, content_length: Option<usize>) -> Option<usize> {
+    match content_length {
+        Some(0) => Some(100),
+        Some(len) => Some(min(100, 100 * bytes_read / len)),
+        None => None,
+    }
 }
 
 pub struct History {
@@ -90,27 +115,24 @@ pub struct History {
 }
 
 impl History {
-    pub fn new() -> History {
+    pub fn new(capacity: usize) -> History {
+        // Indices below wrap modulo the capacity, which is only meaningful for at least one slot.
+        assert!(capacity >= 1, "History capacity must be at least 1");
         History {
-            history: vec![None; 50],
+            history: vec![None; capacity],
             history_idx: 0,
         }
     }
 
     pub fn push(&mut self, s: String) {
         self.history[self.history_idx] = Some(s);
-        self.history_idx = (self.history_idx + 1) % 50;
+        self.history_idx = (self.history_idx + 1) % self.capacity();
     }
 
     pub fn iter<'a>(&'a self) -> HistoryIterator<'a> { HistoryIterator::new(self) }
 
-    pub fn get_idx(&self) -> usize {
-        if self.history_idx == 0 {
-            self.capacity() - 1
-        } else {
-            self.history_idx - 1
-        }
-    }
+    // Index of the most recent entry.
+    pub fn get_idx(&self) -> usize { (self.history_idx + self.capacity() - 1) % self.capacity() }
 
     pub fn get(&self, i: usize) -> &Option<String> { &self.history[i] }
 
@@ -143,12 +165,10 @@ impl<'a> Iterator for HistoryIterator<'a> {
             return None;
         }
 
-        let next_idx = if self.curr_idx == 0 {
-            self.data.capacity() - 1
-        } else {
-            self.curr_idx - 1
-        };
+        let capacity = self.data.capacity();
+        let next_idx = (self.curr_idx + capacity - 1) % capacity;
 
+        // With a capacity of 1, the next index is always the start.
         if next_idx == self.start_idx {
             self.done = true;
         }
@@ -159,23 +179,34 @@ impl<'a> Iterator for HistoryIterator<'a> {
             return Some(&s);
         }
 
+        // Slots are filled in order, so an empty one means there is nothing older.
+        self.done = true;
         None
     }
 }
 
+// Number of completed uploads shown in the interface.
+const RECENT_UPLOADS: usize = 5;
+
 pub struct ConnectionSet {
     pub connections: HashMap<SocketAddr, Connection>,
     pub history: History,
+    pub uploads: History,
 }
 
 impl ConnectionSet {
-    pub fn new() -> ConnectionSet {
+    pub fn new(history_size: usize) -> ConnectionSet {
         ConnectionSet {
             connections: HashMap::<SocketAddr, Connection>::new(),
-            history: History::new(),
+            history: History::new(history_size),
+            uploads: History::new(RECENT_UPLOADS),
         }
     }
 
+    pub fn add_upload(&mut self, upload: &CompletedUpload) {
+        self.uploads.push(format_upload(upload));
+    }
+
     pub fn update(&mut self, current_conns: &HashMap<i32, HttpConnection>) {
         let mut reindexed = HashMap::<SocketAddr, &HttpConnection>::new();
         for (_, conn) in current_conns {
@@ -208,8 +239,98 @@ impl ConnectionSet {
     }
 }
 
+pub fn format_upload(upload: &CompletedUpload) -> String {
+    let addr_str = match upload.addr {
+        Some(addr) => addr.ip().to_string(),
+        None => "[unknown]".to_string(),
+    };
+    format!("{} ({} bytes) from {}", upload.path, upload.size, addr_str)
+}
+
 pub enum ControlEvent {
     Quit,
     Toggle,
+    ToggleReads,
+    ToggleUploads,
     CloseAll,
+    CloseIdle,
+    ToggleDirListings,
+    ToggleUploading,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_progress_with_known_length() {
+        assert_eq!(upload_progress(0, Some(200)), Some(0));
+        assert_eq!(upload_progress(50, Some(200)), Some(25));
+        assert_eq!(upload_progress(199, Some(200)), Some(99));
+        assert_eq!(upload_progress(200, Some(200)), Some(100));
+    }
+
+    #[test]
+    fn upload_progress_with_zero_length() {
+        assert_eq!(upload_progress(0, Some(0)), Some(100));
+    }
+
+    #[test]
+    fn upload_progress_past_declared_length() {
+        assert_eq!(upload_progress(300, Some(200)), Some(100));
+    }
+
+    #[test]
+    fn upload_progress_with_unknown_length() {
+        assert_eq!(upload_progress(0, None), None);
+        assert_eq!(upload_progress(1000, None), None);
+    }
+
+    fn history_with(capacity: usize, entries: &[&str]) -> History {
+        let mut history = History::new(capacity);
+        for entry in entries {
+            history.push(entry.to_string());
+        }
+        history
+    }
+
+    #[test]
+    fn history_keeps_the_newest_entries_once_full() {
+        let history = history_with(3, &["a", "b", "c", "d", "e"]);
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec!["e", "d", "c"]);
+    }
+
+    #[test]
+    fn history_iterates_nothing_when_empty() {
+        assert_eq!(history_with(1, &[]).iter().next(), None);
+        assert_eq!(history_with(2, &[]).iter().next(), None);
+    }
+
+    #[test]
+    fn history_with_capacity_one() {
+        assert_eq!(
+            history_with(1, &["a"]).iter().collect::<Vec<_>>(),
+            vec!["a"]
+        );
+        assert_eq!(
+            history_with(1, &["a", "b", "c"]).iter().collect::<Vec<_>>(),
+            vec!["c"]
+        );
+    }
+
+    #[test]
+    fn history_with_capacity_two() {
+        assert_eq!(
+            history_with(2, &["a"]).iter().collect::<Vec<_>>(),
+            vec!["a"]
+        );
+        assert_eq!(
+            history_with(2, &["a", "b"]).iter().collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+        assert_eq!(
+            history_with(2, &["a", "b", "c"]).iter().collect::<Vec<_>>(),
+            vec!["c", "b"]
+        );
+    }
 }
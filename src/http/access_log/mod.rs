@@ -0,0 +1,211 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    http_core::{method_to_str, status_to_code, HttpMethod},
+    CompletedUpload, HttpConnection,
+};
+use crate::timestamp;
+
+// Everything we log about a single request.
+pub struct LogEntry {
+    pub ts: SystemTime,
+    pub addr: SocketAddr,
+    pub method: Option<HttpMethod>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub bytes_sent: usize,
+    // Upload data written out, for form uploads and PUTs
+    pub bytes_received: usize,
+    pub duration: Duration,
+    pub files: Vec<String>,
+    pub request_id: Option<String>,
+    // Whether the request took longer than --slow-request-ms, when that is given
+    pub slow: Option<bool>,
+    // Whether the whole response was sent, once one has been started
+    pub completed: Option<bool>,
+}
+
+impl LogEntry {
+    // Gathers an entry for the connection's current request. Returns None if the peer's
+    // address isn't known.
+    pub fn new(conn: &HttpConnection) -> Option<LogEntry> {
+        let addr = conn.peer_addr?;
+        let (duration, bytes_sent) = match conn.request_start {
+            Some(start) => (start.elapsed(), conn.bytes_sent - conn.request_bytes_start),
+            None => (Duration::from_secs(0), 0),
+        };
+        Some(LogEntry {
+            ts: SystemTime::now(),
+            addr: addr,
+            method: conn.last_requested_method.clone(),
+            path: conn.last_requested_uri.clone(),
+            status: conn
+                .response
+                .as_ref()
+                .map(|resp| status_to_code(&resp.get_status())),
+            bytes_sent: bytes_sent,
+            bytes_received: match (&conn.post_buffer, &conn.put_buffer) {
+                (Some(pb), _) => pb.get_completed_files().iter().map(|(_, size)| size).sum(),
+                (_, Some(pb)) => pb.written(),
+                _ => 0,
+            },
+            duration: duration,
+            files: match &conn.post_buffer {
+                Some(pb) => pb.get_new_files().clone(),
+                None => Vec::new(),
+            },
+            request_id: conn.request_id.clone(),
+            slow: None,
+            completed: None,
+        })
+    }
+
+    // The line shown in the interface's history
+    pub fn to_text(&self) -> String {
+        let ip_str = match self.addr {
+            SocketAddr::V4(addr) => format!("{}:{}", addr.ip(), addr.port()),
+            SocketAddr::V6(addr) => format!("[{}]:{}", addr.ip(), addr.port()),
+        };
+        let code_str = match self.status {
+            Some(code) => code.to_string(),
+            None => "   ".to_string(),
+        };
+        let method_str = match &self.method {
+            Some(method) => method_to_str(method),
+            None => "???",
+        };
+        let path_str = match &self.path {
+            Some(path) => path,
+            None => "[No path...]",
+        };
+        let files_str = if self.files.len() > 0 {
+            format!(" files: {}", self.files.join(", "))
+        } else {
+            "".to_string()
+        };
+        let id_str = match &self.request_id {
+            Some(id) => format!(" id: {}", id),
+            None => "".to_string(),
+        };
+        format!(
+            "{:<22} {} {:<4} {}{}{}",
+            ip_str, code_str, method_str, path_str, files_str, id_str
+        )
+    }
+
+    // A single-line JSON object, for machine parsing
+    pub fn to_json(&self) -> String {
+        let mut fields = vec![
+            ("ts", json_string(&timestamp::rfc3339(self.ts))),
+            ("ip", json_string(&self.addr.ip().to_string())),
+            (
+                "method",
+                json_optional(self.method.as_ref().map(method_to_str)),
+            ),
+            ("path", json_optional(self.path.as_deref())),
+            (
+                "status",
+                match self.status {
+                    Some(code) => code.to_string(),
+                    None => "null".to_string(),
+                },
+            ),
+            ("bytes_sent", self.bytes_sent.to_string()),
+            ("duration_ms", self.duration.as_millis().to_string()),
+            ("request_id", json_optional(self.request_id.as_deref())),
+        ];
+        if let Some(slow) = self.slow {
+            fields.push(("slow", slow.to_string()));
+        }
+        if let Some(completed) = self.completed {
+            fields.push(("completed", completed.to_string()));
+        }
+        json_object(&fields)
+    }
+
+    // A line for --audit-log: like to_json, but about the data that changed hands
+    pub fn to_audit(&self) -> String {
+        let files: Vec<String> = self.files.iter().map(|file| json_string(file)).collect();
+        json_object(&[
+            ("ts", json_string(&timestamp::rfc3339(self.ts))),
+            ("ip", json_string(&self.addr.ip().to_string())),
+            (
+                "method",
+                json_optional(self.method.as_ref().map(method_to_str)),
+            ),
+            ("path", json_optional(self.path.as_deref())),
+            (
+                "status",
+                match self.status {
+                    Some(code) => code.to_string(),
+                    None => "null".to_string(),
+                },
+            ),
+            ("bytes_sent", self.bytes_sent.to_string()),
+            ("bytes_received", self.bytes_received.to_string()),
+            ("files", format!("[{}]", files.join(","))),
+        ])
+    }
+}
+
+// A JSON log line for something that isn't about a single request, such as a refused connection
+pub fn message_json(message: &str) -> String {
+    json_object(&[
+        ("event", json_string("message")),
+        ("ts", json_string(&timestamp::rfc3339(SystemTime::now()))),
+        ("message", json_string(message)),
+    ])
+}
+
+// A JSON log line for an upload that has been received completely
+pub fn upload_json(upload: &CompletedUpload) -> String {
+    json_object(&[
+        ("event", json_string("upload")),
+        ("ts", json_string(&timestamp::rfc3339(SystemTime::now()))),
+        ("path", json_string(&upload.path)),
+        ("size", upload.size.to_string()),
+        (
+            "ip",
+            match upload.addr {
+                Some(addr) => json_string(&addr.ip().to_string()),
+                None => "null".to_string(),
+            },
+        ),
+    ])
+}
+
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("\"{}\":{}", key, value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_optional(s: Option<&str>) -> String {
+    match s {
+        Some(s) => json_string(s),
+        None => "null".to_string(),
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
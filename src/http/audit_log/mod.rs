@@ -0,0 +1,58 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// The file given with --audit-log. Lines are only ever appended, and once the file would grow
+// past `max_size` it is moved aside to `<path>.1`, replacing any earlier one, and a new file is
+// started.
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+    size: u64,
+}
+
+fn open_for_append(path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+impl AuditLog {
+    // A `max_size` of 0 means the file is never rotated.
+    pub fn open(path: &Path, max_size: u64) -> Result<AuditLog, io::Error> {
+        let file = open_for_append(path)?;
+        let size = file.metadata()?.len();
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            max_size: max_size,
+            file: file,
+            size: size,
+        })
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(".1");
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> Result<(), io::Error> {
+        fs::rename(&self.path, self.rotated_path())?;
+        self.file = open_for_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<(), io::Error> {
+        let len = line.len() as u64 + 1;
+        // A line longer than the limit still goes in a file of its own.
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    cmp::min,
+    io::{self, Read, Write},
+};
+
+use brotli::enc::{BrotliCompress, BrotliEncoderParams};
+use flate2::{
+    write::{GzDecoder, GzEncoder},
+    Compression,
+};
+
+// How much of the compressed body is read from the socket at a time.
+const READ_SIZE: usize = 64 * 1024;
+// Compressed data waiting to be decoded is not allowed to grow past this.
+const INPUT_LIMIT: usize = 1024 * 1024;
+
+// The decoding needed for a request body, given its Content-Encoding. Err holds the encoding
+// that isn't supported.
+pub fn body_decoder(content_encoding: Option<&String>) -> Result<Option<GzipBody>, String> {
+    let encoding = match content_encoding {
+        Some(encoding) => encoding.trim().to_lowercase(),
+        None => return Ok(None),
+    };
+    match &encoding[..] {
+        "" | "identity" => Ok(None),
+        "gzip" | "x-gzip" => Ok(Some(GzipBody::new())),
+        _ => Err(encoding),
+    }
+}
+
+// The encodings responses can be compressed with.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    // The name used for it in Accept-Encoding and Content-Encoding
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    fn matches(&self, coding: &str) -> bool {
+        match self {
+            Encoding::Gzip => coding == "gzip" || coding == "x-gzip",
+            Encoding::Brotli => coding == "br",
+        }
+    }
+}
+
+// The codings in an Accept-Encoding header, lowercased, each with its q-value.
+fn accepted_codings(header: &str) -> Vec<(String, f32)> {
+    header
+        .split(',')
+        .map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap_or("").trim().to_lowercase();
+            let q = parts
+                .filter_map(|param| {
+                    let param = param.trim();
+                    if param.starts_with("q=") {
+                        param[2..].trim().parse::<f32>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+            (coding, q)
+        })
+        .collect()
+}
+
+// Which of `offered` a response should be encoded with for a request with this Accept-Encoding
+// header, if any. The one with the highest q-value wins, and between equals the one the client
+// lists first.
+pub fn preferred_encoding(
+    accept_encoding: Option<&String>,
+    offered: &[Encoding],
+) -> Option<Encoding> {
+    let codings = match accept_encoding {
+        Some(header) => accepted_codings(header),
+        None => return None,
+    };
+    let mut best: Option<(Encoding, f32, usize)> = None;
+    for &encoding in offered {
+        let position = codings
+            .iter()
+            .position(|(coding, _)| encoding.matches(coding))
+            .or_else(|| codings.iter().position(|(coding, _)| coding == "*"));
+        let (q, position) = match position {
+            Some(position) => (codings[position].1, position),
+            None => continue,
+        };
+        let better = match best {
+            Some((_, best_q, best_position)) => {
+                q > best_q || (q == best_q && position < best_position)
+            }
+            None => true,
+        };
+        if q > 0.0 && better {
+            best = Some((encoding, q, position));
+        }
+    }
+    best.map(|(encoding, _, _)| encoding)
+}
+
+// Compresses everything `data` has left with `encoding`, at a level from 0 (fastest) up to 9
+// for gzip or 11 for Brotli.
+pub fn encode(data: &mut impl Read, encoding: Encoding, level: u32) -> Result<Vec<u8>, io::Error> {
+    match encoding {
+        Encoding::Gzip => gzip(data, level),
+        Encoding::Brotli => brotli(data, level),
+    }
+}
+
+fn gzip(data: &mut impl Read, level: u32) -> Result<Vec<u8>, io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+    io::copy(data, &mut encoder)?;
+    encoder.finish()
+}
+
+fn brotli(data: &mut impl Read, level: u32) -> Result<Vec<u8>, io::Error> {
+    let params = BrotliEncoderParams {
+        quality: level as i32,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    BrotliCompress(data, &mut out, &params)?;
+    Ok(out)
+}
+
+fn invalid(error: io::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid gzip data: {}", error),
+    )
+}
+
+// Decompresses a gzip-encoded request body as it arrives. Compressed data is decoded only as
+// fast as the output is taken, so a small body can't expand into a huge amount of memory.
+pub struct GzipBody {
+    // Writes decompressed data into a Vec that `decode_into` drains
+    decoder: GzDecoder<Vec<u8>>,
+    input: Vec<u8>,
+    // How much of `input` has been given to the decoder
+    consumed: usize,
+    input_complete: bool,
+    finished: bool,
+}
+
+impl GzipBody {
+    pub fn new() -> GzipBody {
+        GzipBody {
+            decoder: GzDecoder::new(Vec::new()),
+            input: Vec::new(),
+            consumed: 0,
+            input_complete: false,
+            finished: false,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.compact();
+        self.input.extend_from_slice(data);
+    }
+
+    // Reads compressed data from `readable`. Returns 0 once enough input is waiting.
+    pub fn read_from<T>(&mut self, readable: &mut T) -> Result<usize, io::Error>
+    where
+        T: io::Read,
+    {
+        self.compact();
+        let len = self.input.len();
+        let to_read = min(READ_SIZE, INPUT_LIMIT.saturating_sub(len));
+        self.input.resize(len + to_read, 0);
+        let read = match readable.read(&mut self.input[len..]) {
+            Ok(read) => read,
+            Err(error) => {
+                self.input.truncate(len);
+                return Err(error);
+            }
+        };
+        self.input.truncate(len + read);
+        Ok(read)
+    }
+
+    fn compact(&mut self) {
+        self.input.drain(..self.consumed);
+        self.consumed = 0;
+    }
+
+    // Marks the body as complete, so the end of the gzip stream is checked for.
+    pub fn end_of_input(&mut self) { self.input_complete = true; }
+
+    // Fills `out` with as much decompressed data as is available.
+    pub fn decode_into(&mut self, out: &mut [u8]) -> Result<usize, io::Error> {
+        let mut filled = 0;
+        while filled < out.len() {
+            let output = self.decoder.get_mut();
+            if output.len() > 0 {
+                let n = min(output.len(), out.len() - filled);
+                out[filled..filled + n].copy_from_slice(&output[..n]);
+                output.drain(..n);
+                filled += n;
+                continue;
+            }
+
+            let used = if self.consumed < self.input.len() {
+                self.decoder
+                    .write(&self.input[self.consumed..])
+                    .map_err(invalid)?
+            } else {
+                0
+            };
+            if used > 0 {
+                self.consumed += used;
+                self.decoder.flush().map_err(invalid)?;
+            } else if self.input_complete && !self.finished {
+                if self.consumed < self.input.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected data after the end of the gzip stream",
+                    ));
+                }
+                self.decoder.try_finish().map_err(invalid)?;
+                self.finished = true;
+            } else {
+                break;
+            }
+        }
+        Ok(filled)
+    }
+}
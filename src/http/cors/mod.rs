@@ -0,0 +1,90 @@
+use super::http_core::HttpResponse;
+use crate::opts::types::Opts;
+
+// Which cross-origin requests are allowed, and what they may see.
+pub struct CorsPolicy {
+    // Allowed origins. "*" allows any.
+    origins: Vec<String>,
+    methods: Option<String>,
+    headers: Option<String>,
+    expose_headers: Option<String>,
+    credentials: bool,
+}
+
+impl CorsPolicy {
+    // Returns None unless at least one origin is allowed.
+    pub fn from_opts(opts: &Opts) -> Option<CorsPolicy> {
+        if opts.cors_origins.is_empty() {
+            return None;
+        }
+        Some(CorsPolicy {
+            origins: opts.cors_origins.clone(),
+            methods: opts.cors_methods.clone(),
+            headers: opts.cors_headers.clone(),
+            expose_headers: opts.cors_expose_headers.clone(),
+            credentials: opts.cors_credentials,
+        })
+    }
+
+    // The value of Access-Control-Allow-Origin for a request from `origin`, if it is allowed.
+    // Credentialed responses can't use a wildcard, so the origin is echoed instead.
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.origins.iter().any(|o| o == origin) {
+            Some(origin.to_string())
+        } else if self.origins.iter().any(|o| o == "*") {
+            Some(if self.credentials {
+                origin.to_string()
+            } else {
+                "*".to_string()
+            })
+        } else {
+            None
+        }
+    }
+
+    // Adds the headers every response to an allowed cross-origin request gets.
+    pub fn add_headers(&self, origin: &str, resp: &mut HttpResponse) {
+        let allowed = match self.allow_origin(origin) {
+            Some(allowed) => allowed,
+            None => return,
+        };
+        if allowed != "*" {
+            // The response depends on who asked, so caches must keep them apart.
+            resp.add_vary("Origin");
+        }
+        resp.add_header("Access-Control-Allow-Origin".to_string(), allowed);
+        if self.credentials {
+            resp.add_header(
+                "Access-Control-Allow-Credentials".to_string(),
+                "true".to_string(),
+            );
+        }
+        if let Some(expose_headers) = &self.expose_headers {
+            resp.add_header(
+                "Access-Control-Expose-Headers".to_string(),
+                expose_headers.clone(),
+            );
+        }
+    }
+
+    // Adds what a preflight needs on top of `add_headers`. `default_methods` and
+    // `default_headers` are used unless --cors-methods or --cors-headers were given.
+    pub fn add_preflight_headers(
+        &self,
+        origin: &str,
+        default_methods: String,
+        default_headers: Option<String>,
+        resp: &mut HttpResponse,
+    ) {
+        if self.allow_origin(origin).is_none() {
+            return;
+        }
+        resp.add_header(
+            "Access-Control-Allow-Methods".to_string(),
+            self.methods.clone().unwrap_or(default_methods),
+        );
+        if let Some(headers) = self.headers.clone().or(default_headers) {
+            resp.add_header("Access-Control-Allow-Headers".to_string(), headers);
+        }
+    }
+}
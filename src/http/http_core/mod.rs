@@ -1,9 +1,14 @@
-extern crate regex;
-use regex::{Captures, Regex};
-
-use std::{boxed::Box, cmp::min, io, net::TcpStream};
-
-use std::io::Write;
+use std::{
+    boxed::Box,
+    cmp::min,
+    ffi::OsStr,
+    fs, io,
+    net::TcpStream,
+    os::unix::{ffi::OsStrExt, io::AsRawFd, prelude::RawFd},
+    path::Path,
+};
+
+use std::io::{Read, Write};
 
 pub mod types;
 use types::ResponseDataType;
@@ -13,6 +18,9 @@ pub enum HttpMethod {
     GET,
     HEAD,
     POST,
+    PUT,
+    OPTIONS,
+    PROPFIND,
 }
 
 #[derive(PartialEq, Clone)]
@@ -28,7 +36,21 @@ pub fn version_to_str(v: &HttpVersion) -> &'static str {
     }
 }
 
+pub fn method_to_str(m: &HttpMethod) -> &'static str {
+    match m {
+        HttpMethod::GET => "GET",
+        HttpMethod::HEAD => "HEAD",
+        HttpMethod::POST => "POST",
+        HttpMethod::PUT => "PUT",
+        HttpMethod::OPTIONS => "OPTIONS",
+        HttpMethod::PROPFIND => "PROPFIND",
+    }
+}
+
 pub const BUFFER_SIZE: usize = 512 * 1024;
+// Room left in front of each chunk of a chunked response for its size line. The size of a chunk
+// no bigger than BUFFER_SIZE, in hex, and a CRLF fit with plenty to spare.
+const CHUNK_SIZE_LINE_LEN: usize = 16;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum HttpStatus {
@@ -36,13 +58,23 @@ pub enum HttpStatus {
     OK,                      // 200
     Created,                 // 201
     MovedPermanently,        // 301
+    NotModified,             // 304
     PartialContent,          // 206
-    BadRequest,              // 401
+    MultiStatus,             // 207
+    ResumeIncomplete,        // 308
+    BadRequest,              // 400
     PermissionDenied,        // 403
     NotFound,                // 404
     MethodNotAllowed,        // 405
+    Conflict,                // 409
+    LengthRequired,          // 411
     PayloadTooLarge,         // 413
+    UriTooLong,              // 414
+    UnsupportedMediaType,    // 415
+    RangeNotSatisfiable,     // 416
+    ExpectationFailed,       // 417
     UnprocessableEntity,     // 422
+    TooManyRequests,         // 429
     RequestHeadersTooLarge,  // 431
     ServerError,             // 500
     NotImplemented,          // 501
@@ -56,13 +88,23 @@ pub fn status_to_code(status: &HttpStatus) -> u16 {
         HttpStatus::OK => 200,
         HttpStatus::Created => 201,
         HttpStatus::MovedPermanently => 301,
+        HttpStatus::NotModified => 304,
         HttpStatus::PartialContent => 206,
-        HttpStatus::BadRequest => 401,
+        HttpStatus::MultiStatus => 207,
+        HttpStatus::ResumeIncomplete => 308,
+        HttpStatus::BadRequest => 400,
         HttpStatus::PermissionDenied => 403,
         HttpStatus::NotFound => 404,
         HttpStatus::MethodNotAllowed => 405,
+        HttpStatus::Conflict => 409,
+        HttpStatus::LengthRequired => 411,
         HttpStatus::PayloadTooLarge => 413,
+        HttpStatus::UriTooLong => 414,
+        HttpStatus::UnsupportedMediaType => 415,
+        HttpStatus::RangeNotSatisfiable => 416,
+        HttpStatus::ExpectationFailed => 417,
         HttpStatus::UnprocessableEntity => 422,
+        HttpStatus::TooManyRequests => 429,
         HttpStatus::RequestHeadersTooLarge => 431,
         HttpStatus::ServerError => 500,
         HttpStatus::NotImplemented => 501,
@@ -77,13 +119,23 @@ pub fn status_to_message(status: &HttpStatus) -> &'static str {
         HttpStatus::OK => "OK",
         HttpStatus::Created => "Created",
         HttpStatus::MovedPermanently => "Moved permanently",
+        HttpStatus::NotModified => "Not modified",
         HttpStatus::PartialContent => "Partial content",
+        HttpStatus::MultiStatus => "Multi-status",
+        HttpStatus::ResumeIncomplete => "Resume incomplete",
         HttpStatus::BadRequest => "Bad request",
         HttpStatus::PermissionDenied => "Permission denied",
         HttpStatus::NotFound => "Not found",
         HttpStatus::MethodNotAllowed => "Method not allowed",
+        HttpStatus::Conflict => "Conflict",
+        HttpStatus::LengthRequired => "Length required",
         HttpStatus::PayloadTooLarge => "Payload too large",
+        HttpStatus::UriTooLong => "URI too long",
+        HttpStatus::UnsupportedMediaType => "Unsupported media type",
+        HttpStatus::RangeNotSatisfiable => "Range not satisfiable",
+        HttpStatus::ExpectationFailed => "Expectation failed",
         HttpStatus::UnprocessableEntity => "Unprocessable entity",
+        HttpStatus::TooManyRequests => "Too many requests",
         HttpStatus::RequestHeadersTooLarge => "Request header fields too large",
         HttpStatus::ServerError => "Server error",
         HttpStatus::NotImplemented => "Method not implemented",
@@ -101,7 +153,12 @@ pub struct HttpHeader {
 type HttpHeaderSet = Vec<HttpHeader>;
 
 pub struct HttpRequest {
+    // The decoded path. Bytes that aren't valid UTF-8 are replaced, so use `fs_path` to find
+    // the file it refers to.
     pub path: String,
+    path_bytes: Vec<u8>,
+    // The request target exactly as it was received
+    pub raw_target: String,
     pub method: Option<HttpMethod>,
     pub version: HttpVersion,
     headers: HttpHeaderSet,
@@ -114,14 +171,29 @@ impl HttpRequest {
          *
          */
         let lines: Vec<&str> = request_str.split("\r\n").collect();
-        // We know that lines will always be at least 2 lines long.
-        let first: Vec<&str> = lines[0].split(" ").collect();
-        if first.len() != 3 {
+        // An empty line before the request line is allowed, and ignored (RFC 7230 section 3.5).
+        let first_line = match lines.iter().position(|line| line.len() != 0) {
+            Some(idx) => idx,
+            None => return Err(HttpStatus::BadRequest),
+        };
+        // The method ends at the first space and the version starts after the last, so a target
+        // containing a literal space, as some clients send, is still read whole.
+        let (verb, rest) = match lines[first_line].find(' ') {
+            Some(idx) => (&lines[first_line][..idx], &lines[first_line][idx + 1..]),
+            None => return Err(HttpStatus::BadRequest),
+        };
+        let (target, version_str) = match rest.rfind(' ') {
+            Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+            None => return Err(HttpStatus::BadRequest),
+        };
+        if verb.len() == 0 || target.len() == 0 {
+            return Err(HttpStatus::BadRequest);
+        }
+        let (authority, target) = split_absolute_form(target);
+        if authority == Some("") {
             return Err(HttpStatus::BadRequest);
         }
-        let verb = first[0];
-        let path = ignore_get_params(first[1]);
-        let version_str = first[2];
+        let path_bytes = percent_decode(ignore_get_params(&target));
 
         let version = if version_str == "HTTP/1.0" {
             HttpVersion::Http1_0
@@ -131,8 +203,7 @@ impl HttpRequest {
             return Err(HttpStatus::HttpVersionNotSupported);
         };
 
-        // unwrap safe because we know that lines will have a last element
-        if lines.last().unwrap().len() != 0 {
+        if lines.last() != Some(&"") {
             // We never received the end of the request
             return Err(HttpStatus::RequestHeadersTooLarge);
         }
@@ -143,16 +214,24 @@ impl HttpRequest {
             Some(HttpMethod::HEAD)
         } else if verb == "POST" {
             Some(HttpMethod::POST)
+        } else if verb == "PUT" {
+            Some(HttpMethod::PUT)
+        } else if verb == "OPTIONS" {
+            Some(HttpMethod::OPTIONS)
+        } else if verb == "PROPFIND" {
+            Some(HttpMethod::PROPFIND)
         } else {
             None
         };
 
         let mut headers = HttpHeaderSet::new();
-        for header_line in &lines[1..] {
+        for header_line in &lines[first_line + 1..] {
             if header_line.len() == 0 {
                 continue;
             }
-            let keyval: Vec<&str> = header_line.split(":").collect();
+            // Only split on the first ':', as values such as `Host: example.com:8080` may
+            // contain more.
+            let keyval: Vec<&str> = header_line.splitn(2, ":").collect();
             if keyval.len() != 2 {
                 continue;
             }
@@ -162,14 +241,36 @@ impl HttpRequest {
             });
         }
 
+        // The host in an absolute-form target takes the place of any Host header
+        // (RFC 7230 section 5.4).
+        if let Some(authority) = authority {
+            headers.retain(|header| header.key != "host");
+            headers.push(HttpHeader {
+                key: "host".to_string(),
+                value: authority.to_string(),
+            });
+        }
+
         Ok(HttpRequest {
-            path: undo_percent_encoding(path),
+            path: String::from_utf8_lossy(&path_bytes).to_string(),
+            path_bytes: path_bytes,
+            raw_target: target,
             method: method,
             version: version,
             headers: headers,
         })
     }
 
+    // The decoded path relative to the root, which may name a file that isn't valid UTF-8.
+    pub fn fs_path(&self) -> &Path {
+        let bytes = if self.path_bytes.starts_with(b"/") {
+            &self.path_bytes[1..]
+        } else {
+            &self.path_bytes[..]
+        };
+        Path::new(OsStr::from_bytes(bytes))
+    }
+
     pub fn get_header(&self, key: &str) -> Option<&String> {
         for header in &self.headers {
             if header.key == key.to_string() {
@@ -178,34 +279,83 @@ impl HttpRequest {
         }
         None
     }
-}
 
-fn get_byte_from_hex(tens_dig: u8, ones_dig: u8) -> u8 {
-    fn get_byte_from_hex_digit(dig: u8) -> u8 {
-        match dig as char {
-            '0'..='9' => dig - b'0',
-            'a'..='f' => dig - b'a' + 10,
-            'A'..='F' => dig - b'A' + 10,
-            _ => panic!("get_byte_from_hex failed: {} = `{}`", dig, dig as char),
+    // Every value given for a header, in the order they were sent.
+    pub fn get_header_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a String> {
+        self.headers
+            .iter()
+            .filter(move |header| header.key == key)
+            .map(|header| &header.value)
+    }
+
+    // Looks up a parameter in the query string. A parameter given without a value, like
+    // `?zip`, has an empty value.
+    pub fn get_query_param(&self, key: &str) -> Option<String> {
+        let query = match self.raw_target.find('?') {
+            Some(idx) => &self.raw_target[idx + 1..],
+            None => return None,
+        };
+        for param in query.split('&') {
+            let mut keyval = param.splitn(2, '=');
+            if keyval.next() == Some(key) {
+                let value = percent_decode(keyval.next().unwrap_or(""));
+                return Some(String::from_utf8_lossy(&value).to_string());
+            }
         }
+        None
     }
+}
 
-    (get_byte_from_hex_digit(tens_dig) << 4) + get_byte_from_hex_digit(ones_dig)
+// Splits an absolute-form target, like `http://example.com/path`, into its authority and the
+// path and query that follow. Other targets are returned as they are.
+fn split_absolute_form(target: &str) -> (Option<&str>, String) {
+    let scheme_len = if target.len() >= 7 && target[..7].eq_ignore_ascii_case("http://") {
+        7
+    } else if target.len() >= 8 && target[..8].eq_ignore_ascii_case("https://") {
+        8
+    } else {
+        return (None, target.to_string());
+    };
+    let rest = &target[scheme_len..];
+    let path_start = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+    let path = &rest[path_start..];
+    // An absolute URI with an empty path refers to the root.
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    (Some(&rest[..path_start]), path)
 }
 
-fn undo_percent_encoding(path: &str) -> String {
-    lazy_static! {
-        static ref RE: Regex = Regex::new("%([0-9a-fA-F])([0-9a-fA-F])").unwrap();
+fn get_hex_digit(dig: u8) -> Option<u8> {
+    match dig as char {
+        '0'..='9' => Some(dig - b'0'),
+        'a'..='f' => Some(dig - b'a' + 10),
+        'A'..='F' => Some(dig - b'A' + 10),
+        _ => None,
     }
-    let s = RE.replace_all(path, |caps: &Captures| {
-        let dig: u8 = get_byte_from_hex(
-            caps[1].bytes().nth(0).unwrap(),
-            caps[2].bytes().nth(0).unwrap(),
-        );
-        let dig_arr: [u8; 1] = [dig];
-        String::from_utf8_lossy(&dig_arr[..]).to_string()
-    });
-    s.to_string()
+}
+
+// Decodes %XX sequences into the bytes they stand for. The result need not be UTF-8, as
+// filenames on Unix are arbitrary bytes. Anything that isn't a valid sequence is kept as-is.
+pub fn percent_decode(path: &str) -> Vec<u8> {
+    let bytes = path.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (get_hex_digit(bytes[i + 1]), get_hex_digit(bytes[i + 2]))
+            {
+                res.push((hi << 4) + lo);
+                i += 3;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    res
 }
 
 pub struct HttpResponse {
@@ -215,7 +365,12 @@ pub struct HttpResponse {
     headers_written: bool,
     data: ResponseDataType,
     buffer: Box<[u8]>,
+    // Bytes of `buffer` that have been read from the body but not yet written
+    buffered: (usize, usize),
     bytes_to_write: usize,
+    chunked: bool,
+    // Set once a streamed body has reached its end
+    stream_ended: bool,
 }
 
 impl HttpResponse {
@@ -233,7 +388,10 @@ impl HttpResponse {
                 v.into_boxed_slice()
             },
             data: ResponseDataType::None,
+            buffered: (0, 0),
             bytes_to_write: 0,
+            chunked: false,
+            stream_ended: false,
         }
     }
 
@@ -248,6 +406,23 @@ impl HttpResponse {
         });
     }
 
+    // Records a request header that was used to choose what to send. They are all listed in a
+    // single Vary header, so caches know which requests can share this response.
+    pub fn add_vary(&mut self, request_header: &str) {
+        match self.headers.iter_mut().find(|header| header.key == "Vary") {
+            Some(vary) => {
+                if !vary
+                    .value
+                    .split(',')
+                    .any(|name| name.trim().eq_ignore_ascii_case(request_header))
+                {
+                    vary.value = format!("{}, {}", vary.value, request_header);
+                }
+            }
+            None => self.add_header("Vary".to_string(), request_header.to_string()),
+        }
+    }
+
     pub fn set_content_length(&mut self, size: usize) {
         self.headers.push(HttpHeader {
             key: "Content-Length".to_string(),
@@ -256,7 +431,42 @@ impl HttpResponse {
         self.bytes_to_write = size;
     }
 
-    pub fn get_code(&self) -> String { status_to_code(&self.status).to_string() }
+    // Sends the body as a series of chunks, for bodies whose length isn't known up front.
+    pub fn set_chunked(&mut self) {
+        self.add_header("Transfer-Encoding".to_string(), "chunked".to_string());
+        self.chunked = true;
+    }
+
+    // Streamed bodies are sent until their source ends rather than up to a Content-Length.
+    pub fn is_streaming(&self) -> bool {
+        match self.data {
+            ResponseDataType::Stream(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool { self.stream_ended && self.buffered.0 == self.buffered.1 }
+
+    // The source a streamed body is waiting on, when everything read from it has been sent.
+    pub fn waiting_on(&self) -> Option<RawFd> {
+        match self.data {
+            ResponseDataType::Stream(ref src)
+                if !self.stream_ended && self.buffered.0 == self.buffered.1 =>
+            {
+                Some(src.as_raw_fd())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_status(&self) -> HttpStatus { self.status }
+
+    pub fn get_header(&self, key: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|header| header.key == key)
+            .map(|header| &header.value)
+    }
 
     pub fn write_headers_to_stream(&mut self, mut stream: &TcpStream) -> Result<(), io::Error> {
         assert_eq!(self.headers_written, false);
@@ -282,26 +492,73 @@ impl HttpResponse {
         Ok(())
     }
 
-    pub fn partial_write_to_stream(&mut self, stream: &TcpStream) -> Result<usize, io::Error> {
+    // Writes the next part of the body, no more than `max` bytes of it.
+    pub fn partial_write_to_stream(
+        &mut self,
+        stream: &TcpStream,
+        max: usize,
+    ) -> Result<usize, io::Error> {
         assert_eq!(self.headers_written, true);
         let amt_written = match self.data {
             ResponseDataType::String(ref mut s) => generic_partial_write_to_stream(
                 self.bytes_to_write,
                 &mut self.buffer[..],
+                &mut self.buffered,
                 s,
                 stream,
+                max,
             ),
             ResponseDataType::File(ref mut fle) => generic_partial_write_to_stream(
                 self.bytes_to_write,
                 &mut self.buffer[..],
+                &mut self.buffered,
                 fle,
                 stream,
+                max,
+            ),
+            ResponseDataType::Mapped(ref mut map) => {
+                map.partial_write_to_stream(min(min(self.bytes_to_write, BUFFER_SIZE), max), stream)
+            }
+            ResponseDataType::Bytes(ref mut bytes) => generic_partial_write_to_stream(
+                self.bytes_to_write,
+                &mut self.buffer[..],
+                &mut self.buffered,
+                bytes,
+                stream,
+                max,
+            ),
+            ResponseDataType::Shared(ref mut shared) => generic_partial_write_to_stream(
+                self.bytes_to_write,
+                &mut self.buffer[..],
+                &mut self.buffered,
+                shared,
+                stream,
+                max,
+            ),
+            ResponseDataType::Zip(ref mut zip) => generic_partial_write_to_stream(
+                self.bytes_to_write,
+                &mut self.buffer[..],
+                &mut self.buffered,
+                zip,
+                stream,
+                max,
+            ),
+            ResponseDataType::Stream(ref mut src) => stream_partial_write_to_stream(
+                self.chunked,
+                &mut self.buffer[..],
+                &mut self.buffered,
+                &mut self.stream_ended,
+                src,
+                stream,
+                max,
             ),
             ResponseDataType::None => Ok(0),
         };
 
         if let Ok(amt) = amt_written {
-            self.bytes_to_write -= amt;
+            if !self.is_streaming() {
+                self.bytes_to_write -= amt;
+            }
         }
 
         amt_written
@@ -311,22 +568,79 @@ impl HttpResponse {
 fn generic_partial_write_to_stream<T>(
     bytes_to_write: usize,
     buffer: &mut [u8],
+    buffered: &mut (usize, usize),
     body: &mut T,
     mut stream: &TcpStream,
+    max: usize,
 ) -> Result<usize, io::Error>
 where
-    T: io::Seek + io::Read,
+    T: io::Read,
 {
-    let write_length = min(bytes_to_write, BUFFER_SIZE);
-    let amt_read = body.read(&mut buffer[..write_length])?;
-    if amt_read == 0 {
-        return Ok(0);
+    // Only read more once everything already read has been written, so a short write doesn't
+    // need the body to be seekable. Reads never go past what is left of the Content-Length, so
+    // a file that grows while it is being sent can't spill into the next response.
+    if buffered.0 == buffered.1 {
+        let write_length = min(bytes_to_write, BUFFER_SIZE);
+        let amt_read = body.read(&mut buffer[..write_length])?;
+        if amt_read == 0 {
+            return Ok(0);
+        }
+        *buffered = (0, amt_read);
     }
-    // HttpResponse::write_fully(&buffer[..amt_read], stream)?;
-    let amt_written = stream.write(&buffer[..amt_read])?;
-    if amt_written < amt_read {
-        body.seek(io::SeekFrom::Current((amt_read - amt_written) as i64))?;
+    let end = min(buffered.1, buffered.0.saturating_add(max));
+    let amt_written = stream.write(&buffer[buffered.0..end])?;
+    buffered.0 += amt_written;
+    Ok(amt_written)
+}
+
+// Like generic_partial_write_to_stream, but for a non-blocking source with no known length.
+// Nothing is written while the source has nothing to give. Once it ends, `ended` is set, and a
+// chunked body is finished off with the last, empty chunk.
+fn stream_partial_write_to_stream(
+    chunked: bool,
+    buffer: &mut [u8],
+    buffered: &mut (usize, usize),
+    ended: &mut bool,
+    body: &mut fs::File,
+    mut stream: &TcpStream,
+    max: usize,
+) -> Result<usize, io::Error> {
+    if buffered.0 == buffered.1 {
+        if *ended {
+            return Ok(0);
+        }
+        let (start, end) = if chunked {
+            (CHUNK_SIZE_LINE_LEN, buffer.len() - 2)
+        } else {
+            (0, buffer.len())
+        };
+        let amt_read = match body.read(&mut buffer[start..end]) {
+            Ok(amt) => amt,
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(0),
+            Err(error) => return Err(error),
+        };
+        if amt_read == 0 {
+            *ended = true;
+            if !chunked {
+                return Ok(0);
+            }
+            let last_chunk = b"0\r\n\r\n";
+            buffer[..last_chunk.len()].copy_from_slice(last_chunk);
+            *buffered = (0, last_chunk.len());
+        } else if chunked {
+            let size_line = format!("{:x}\r\n", amt_read);
+            let chunk_start = start - size_line.len();
+            let chunk_end = start + amt_read + 2;
+            buffer[chunk_start..start].copy_from_slice(size_line.as_bytes());
+            buffer[start + amt_read..chunk_end].copy_from_slice(b"\r\n");
+            *buffered = (chunk_start, chunk_end);
+        } else {
+            *buffered = (0, amt_read);
+        }
     }
+    let end = min(buffered.1, buffered.0.saturating_add(max));
+    let amt_written = stream.write(&buffer[buffered.0..end])?;
+    buffered.0 += amt_written;
     Ok(amt_written)
 }
 
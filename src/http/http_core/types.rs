@@ -1,8 +1,21 @@
 use std::{
+    cmp::min,
     fs,
-    io::{self, Read, Seek, SeekFrom},
+    io::{self, Read, Seek, SeekFrom, Write},
+    net::TcpStream,
+    os::unix::io::AsRawFd,
+    ptr,
+    rc::Rc,
+    slice,
 };
 
+use nix::{
+    libc::c_void,
+    sys::mman::{mmap, munmap, MapFlags, ProtFlags},
+};
+
+use crate::http::{nix_to_io, zip_stream::ZipStream};
+
 pub struct SeekableString {
     pub start: usize,
     pub data: String,
@@ -12,6 +25,23 @@ impl SeekableString {
     pub fn new(d: String) -> SeekableString { SeekableString { start: 0, data: d } }
 }
 
+// Where a seek from `current` in a body of `len` bytes ends up. Like a file, seeking before the
+// start is an error, but unlike one, seeking past the end stops at the end.
+fn seek_position(current: usize, len: usize, pos: SeekFrom) -> Result<usize, io::Error> {
+    let (base, offset) = match pos {
+        SeekFrom::Start(i) => return Ok(min(i, len as u64) as usize),
+        SeekFrom::Current(i) => (current, i),
+        SeekFrom::End(i) => (len, i),
+    };
+    match (base as i64).checked_add(offset) {
+        Some(target) if target >= 0 => Ok(min(target as u64, len as u64) as usize),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid seek to a negative or overflowing position",
+        )),
+    }
+}
+
 impl Read for SeekableString {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         let mut slice = &self.data.as_bytes()[self.start..];
@@ -23,17 +53,174 @@ impl Read for SeekableString {
 
 impl Seek for SeekableString {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
-        self.start = match pos {
-            SeekFrom::Start(i) => i as usize,
-            SeekFrom::Current(i) => ((self.start as i64) + i) as usize,
-            SeekFrom::End(i) => ((self.data.len() as i64) - i) as usize,
-        };
+        self.start = seek_position(self.start, self.data.len(), pos)?;
         Ok(self.start as u64)
     }
 }
 
+// A file mapped into memory once, with --mmap, so that its body can be written to the socket
+// straight from the mapping instead of being read into a buffer first.
+pub struct MappedFile {
+    file: fs::File,
+    addr: *mut c_void,
+    len: usize,
+    pos: usize,
+}
+
+impl MappedFile {
+    pub fn new(file: fs::File) -> Result<MappedFile, io::Error> {
+        let len = file.metadata()?.len() as usize;
+        // Empty mappings aren't allowed, and there's nothing to map anyway.
+        let addr = if len == 0 {
+            ptr::null_mut()
+        } else {
+            unsafe {
+                mmap(
+                    ptr::null_mut(),
+                    len,
+                    ProtFlags::PROT_READ,
+                    MapFlags::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            }
+            .map_err(nix_to_io)?
+        };
+        Ok(MappedFile {
+            file: file,
+            addr: addr,
+            len: len,
+            pos: 0,
+        })
+    }
+
+    // The length of the file when it was mapped. Only that much is ever served.
+    pub fn len(&self) -> usize { self.len }
+
+    // Up to `max` bytes of the mapping from the current position. Touching a page past the end
+    // of a file that has shrunk since it was mapped raises SIGBUS, which kills the whole server,
+    // so this fails instead if the file is now too short. That only narrows the window: the file
+    // can still shrink between the check and the copy. Nothing here can recover from that, which
+    // is why --mmap is documented as unsafe for files that may be truncated while served.
+    fn next_slice(&self, max: usize) -> Result<&[u8], io::Error> {
+        let end = min(self.len, self.pos.saturating_add(max));
+        if self.pos >= end {
+            return Ok(&[]);
+        }
+        if (self.file.metadata()?.len() as usize) < end {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "File shrank while it was being served",
+            ));
+        }
+        let mapping = unsafe { slice::from_raw_parts(self.addr as *const u8, self.len) };
+        Ok(&mapping[self.pos..end])
+    }
+
+    pub fn partial_write_to_stream(
+        &mut self,
+        max: usize,
+        mut stream: &TcpStream,
+    ) -> Result<usize, io::Error> {
+        let written = stream.write(self.next_slice(max)?)?;
+        self.pos += written;
+        Ok(written)
+    }
+}
+
+impl Read for MappedFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let read = self.next_slice(buf.len())?.read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+impl Seek for MappedFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
+        self.pos = seek_position(self.pos, self.len, pos)?;
+        Ok(self.pos as u64)
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if !self.addr.is_null() {
+            let _ = unsafe { munmap(self.addr, self.len) };
+        }
+    }
+}
+
 pub enum ResponseDataType {
     String(SeekableString),
     File(fs::File),
+    Mapped(MappedFile),
+    Bytes(io::Cursor<Vec<u8>>),
+    // Data kept in memory for good, such as a virtual file, which every response shares
+    Shared(io::Cursor<Rc<[u8]>>),
+    Zip(ZipStream),
+    // A source of unknown length, such as a FIFO, read without blocking until it ends
+    Stream(fs::File),
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string_at(start: usize) -> SeekableString {
+        let mut s = SeekableString::new("0123456789".to_string());
+        s.start = start;
+        s
+    }
+
+    #[test]
+    fn seek_from_start_past_the_end_stops_at_the_end() {
+        assert_eq!(string_at(0).seek(SeekFrom::Start(4)).unwrap(), 4);
+        assert_eq!(string_at(0).seek(SeekFrom::Start(10)).unwrap(), 10);
+        assert_eq!(string_at(0).seek(SeekFrom::Start(11)).unwrap(), 10);
+        assert_eq!(string_at(0).seek(SeekFrom::Start(u64::MAX)).unwrap(), 10);
+    }
+
+    #[test]
+    fn seek_from_current_out_of_range() {
+        assert_eq!(string_at(4).seek(SeekFrom::Current(-4)).unwrap(), 0);
+        assert_eq!(string_at(4).seek(SeekFrom::Current(100)).unwrap(), 10);
+        assert!(string_at(4).seek(SeekFrom::Current(-5)).is_err());
+        assert!(string_at(4).seek(SeekFrom::Current(i64::MIN)).is_err());
+        assert!(string_at(4).seek(SeekFrom::Current(i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn seek_from_end_out_of_range() {
+        assert_eq!(string_at(0).seek(SeekFrom::End(-3)).unwrap(), 7);
+        assert_eq!(string_at(0).seek(SeekFrom::End(5)).unwrap(), 10);
+        assert!(string_at(0).seek(SeekFrom::End(-11)).is_err());
+    }
+
+    #[test]
+    fn failed_seek_keeps_the_position() {
+        let mut s = string_at(4);
+        assert!(s.seek(SeekFrom::End(-11)).is_err());
+        let mut rest = String::new();
+        s.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "456789");
+    }
+
+    #[test]
+    fn mapped_file_seeks_are_clamped() {
+        let path = std::env::temp_dir().join(format!("hypershare-seek-{}", std::process::id()));
+        fs::write(&path, "0123456789").unwrap();
+        let mut map = MappedFile::new(fs::File::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(map.seek(SeekFrom::Start(20)).unwrap(), 10);
+        assert_eq!(map.seek(SeekFrom::End(-3)).unwrap(), 7);
+        assert!(map.seek(SeekFrom::Current(-8)).is_err());
+        assert!(map.seek(SeekFrom::End(-11)).is_err());
+
+        let mut rest = String::new();
+        map.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "789");
+    }
+}
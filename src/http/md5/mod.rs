@@ -0,0 +1,180 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+// Per-round shift amounts
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+lazy_static! {
+    // floor(abs(sin(i + 1)) * 2^32)
+    static ref CONSTANTS: [u32; 64] = {
+        let mut table = [0u32; 64];
+        for i in 0..64 {
+            table[i] = (((i + 1) as f64).sin().abs() * 4294967296.0) as u32;
+        }
+        table
+    };
+}
+
+// An incremental MD5 digest.
+pub struct Md5 {
+    state: [u32; 4],
+    // Bytes of an incomplete block
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Md5 {
+    pub fn new() -> Md5 {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        while data.len() > 0 {
+            let amt = std::cmp::min(64 - self.block_len, data.len());
+            self.block[self.block_len..self.block_len + amt].copy_from_slice(&data[..amt]);
+            self.block_len += amt;
+            data = &data[amt..];
+            if self.block_len == 64 {
+                let block = self.block;
+                self.process_block(&block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; 16] {
+        let bit_len = self.total_len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_le_bytes());
+
+        let mut digest = [0u8; 16];
+        for (i, word) in self.state.iter().enumerate() {
+            digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn process_block(&mut self, block: &[u8; 64]) {
+        let mut m = [0u32; 16];
+        for i in 0..16 {
+            m[i] = u32::from_le_bytes([
+                block[i * 4],
+                block[i * 4 + 1],
+                block[i * 4 + 2],
+                block[i * 4 + 3],
+            ]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(CONSTANTS[i])
+                .wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i]));
+        }
+
+        self.state[0] = self.state[0].wrapping_add(a);
+        self.state[1] = self.state[1].wrapping_add(b);
+        self.state[2] = self.state[2].wrapping_add(c);
+        self.state[3] = self.state[3].wrapping_add(d);
+    }
+}
+
+pub fn to_hex(digest: &[u8; 16]) -> String { digest.iter().map(|b| format!("{:02x}", b)).collect() }
+
+// Parses a digest as written in a .md5sum sidecar. Anything after the hex digits (such as a
+// file name) is ignored.
+pub fn from_hex(hex: &str) -> Option<[u8; 16]> {
+    let hex = hex.split_whitespace().next()?;
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut digest = [0u8; 16];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+// The digest in base64, as the Content-MD5 header wants it.
+pub fn to_base64(digest: &[u8; 16]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(24);
+    for chunk in digest.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+pub fn digest_file(path: &Path) -> Result<[u8; 16], io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut md5 = Md5::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let amt = file.read(&mut buffer)?;
+        if amt == 0 {
+            break;
+        }
+        md5.update(&buffer[..amt]);
+    }
+    Ok(md5.finish())
+}
+
+// Writes the digest of `path` to `sidecar`, in the format directory listings read. Does nothing
+// if the sidecar already exists.
+pub fn write_sidecar(path: &Path, sidecar: &Path) -> Result<(), io::Error> {
+    let digest = digest_file(path)?;
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(sidecar)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if let Err(e) = file.write_all(format!("{}\n", to_hex(&digest)).as_bytes()) {
+        let _ = fs::remove_file(sidecar);
+        return Err(e);
+    }
+    Ok(())
+}
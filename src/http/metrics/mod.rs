@@ -0,0 +1,68 @@
+use std::cell::Cell;
+
+// Counters served at /_metrics with --enable-metrics. They are kept whether or not it is
+// enabled, since keeping them is nearly free.
+pub struct Metrics {
+    requests: Cell<u64>,
+    bytes_sent: Cell<u64>,
+    active_connections: Cell<usize>,
+    uploads: Cell<u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            requests: Cell::new(0),
+            bytes_sent: Cell::new(0),
+            active_connections: Cell::new(0),
+            uploads: Cell::new(0),
+        }
+    }
+
+    pub fn count_request(&self) { self.requests.set(self.requests.get() + 1); }
+
+    pub fn count_bytes_sent(&self, amt: usize) {
+        self.bytes_sent.set(self.bytes_sent.get() + amt as u64);
+    }
+
+    pub fn set_active_connections(&self, count: usize) { self.active_connections.set(count); }
+
+    pub fn count_upload(&self) { self.uploads.set(self.uploads.get() + 1); }
+
+    // The counters in the Prometheus text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let metrics: [(&str, &str, &str, u64); 4] = [
+            (
+                "hypershare_requests_total",
+                "counter",
+                "Requests received.",
+                self.requests.get(),
+            ),
+            (
+                "hypershare_bytes_sent_total",
+                "counter",
+                "Response body bytes sent.",
+                self.bytes_sent.get(),
+            ),
+            (
+                "hypershare_active_connections",
+                "gauge",
+                "Connections currently open.",
+                self.active_connections.get() as u64,
+            ),
+            (
+                "hypershare_uploads_total",
+                "counter",
+                "Uploads completed.",
+                self.uploads.get(),
+            ),
+        ];
+        let mut s = String::new();
+        for (name, kind, help, value) in &metrics {
+            s.push_str(&format!("# HELP {} {}\n", name, help));
+            s.push_str(&format!("# TYPE {} {}\n", name, kind));
+            s.push_str(&format!("{} {}\n", name, value));
+        }
+        s
+    }
+}
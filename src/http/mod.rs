@@ -1,42 +1,180 @@
+mod access_log;
+mod audit_log;
 mod boyer_moore;
+mod content_encoding;
+mod cors;
 pub mod http_core;
+mod md5;
+mod metrics;
 mod post_buffer;
+mod put_buffer;
+mod rate_limit;
+mod zip_stream;
 
 use boyer_moore_magiclen::BMByte;
-
-use crate::rendering;
+use regex::Regex;
+
+use crate::{opts, rendering, timestamp};
+pub use access_log::{json_string, upload_json};
+use access_log::{message_json, LogEntry};
+pub use audit_log::AuditLog;
+use content_encoding::Encoding;
+use cors::CorsPolicy;
+use metrics::Metrics;
 use post_buffer::PostBuffer;
+use put_buffer::{PutBuffer, ReceivedRanges, UploadRange};
+use rate_limit::RateLimiter;
+use zip_stream::ZipStream;
 
-use crate::opts::types::Opts;
+use crate::opts::types::{ContentTypeRule, LogFormat, Opts, RequestRate};
 
 use http_core::{
-    types::{ResponseDataType, SeekableString},
+    types::{MappedFile, ResponseDataType, SeekableString},
     HttpMethod, HttpRequest, HttpResponse, HttpStatus, HttpVersion,
 };
 
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+};
 
 use nix::{
-    sys::select::{select, FdSet},
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    libc::{ENOENT, O_NONBLOCK},
+    sys::{
+        select::{select, FdSet},
+        socket::{
+            bind, getsockopt, listen, setsockopt, shutdown, socket, sockopt, AddressFamily,
+            InetAddr, Shutdown, SockAddr, SockFlag, SockType,
+        },
+        time::{TimeVal, TimeValLike},
+    },
     unistd,
 };
-use std::os::unix::{io::AsRawFd, prelude::RawFd};
+use std::os::unix::{
+    fs::{FileTypeExt, OpenOptionsExt},
+    io::{AsRawFd, FromRawFd},
+    prelude::RawFd,
+};
 
 use std::path::{Path, PathBuf};
 
 use std::{
-    fs,
+    fs::{self, OpenOptions},
     io::{self, Read, Seek},
-    net::{SocketAddr, TcpListener, TcpStream},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
 };
 
-use std::sync::mpsc;
+use std::{iter, rc::Rc, sync::mpsc, thread, time};
 
-use std::cmp::{max, min};
+use std::cmp::{max, min, Ordering};
 
-use std::{format, str::from_utf8};
+use std::format;
 
 const BUFFER_SIZE: usize = 4096;
+// Files up to this size are digested for --content-md5 when they have no sidecar.
+const CONTENT_MD5_COMPUTE_LIMIT: u64 = 1024 * 1024;
+// How long clients turned away by --max-concurrent-uploads are asked to wait.
+const UPLOAD_RETRY_AFTER_SECS: u64 = 5;
+// Resumable uploads are given up on once no piece of them has arrived for this long.
+const PARTIAL_UPLOAD_MAX_IDLE_SECS: u64 = 24 * 60 * 60;
+// Responses are compressed in memory while every other connection waits, so larger ones are
+// always sent as they are.
+const COMPRESSION_MAX_SIZE: usize = 1024 * 1024;
+// Brotli's top levels are many times slower than the rest, so they're only used for bodies up to
+// this size. Larger ones are compressed at BROTLI_LARGE_BODY_LEVEL at most.
+const BROTLI_SLOW_LEVELS_MAX_SIZE: usize = 64 * 1024;
+const BROTLI_LARGE_BODY_LEVEL: u32 = 9;
+// Files with these extensions are compressed already, so aren't worth compressing again.
+const COMPRESSED_EXTENSIONS: [&str; 20] = [
+    "7z", "avif", "br", "bz2", "gif", "gz", "jpeg", "jpg", "mkv", "mov", "mp3", "mp4", "ogg",
+    "png", "rar", "webm", "webp", "xz", "zip", "zst",
+];
+// Searches of the served tree are made at this path, shadowing any file of the same name.
+const SEARCH_PATH: &str = "/_search";
+// Likewise for metrics, when --enable-metrics is given.
+const METRICS_PATH: &str = "/_metrics";
+// How the connection preface of an HTTP/2 client starts (RFC 7540 section 3.5).
+const HTTP2_PREFACE_LINE: &[u8] = b"PRI * HTTP/2.0\r\n";
+
+fn nix_to_io(error: nix::Error) -> io::Error {
+    match error.as_errno() {
+        Some(errno) => io::Error::from(errno),
+        None => io::Error::new(io::ErrorKind::Other, error.to_string()),
+    }
+}
+
+// Listens on `addr` with room for `backlog` connections waiting to be accepted. The standard
+// library picks the backlog itself, so the socket is set up by hand.
+fn listen_on(addr: &SocketAddr, backlog: usize) -> Result<TcpListener, io::Error> {
+    let family = if addr.is_ipv4() {
+        AddressFamily::Inet
+    } else {
+        AddressFamily::Inet6
+    };
+    let fd = socket(family, SockType::Stream, SockFlag::SOCK_CLOEXEC, None).map_err(nix_to_io)?;
+    // Owning the socket right away closes it if anything below fails.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    setsockopt(fd, sockopt::ReuseAddr, &true).map_err(nix_to_io)?;
+    bind(fd, &SockAddr::new_inet(InetAddr::from_std(addr))).map_err(nix_to_io)?;
+    listen(fd, backlog).map_err(nix_to_io)?;
+    Ok(listener)
+}
+
+// Takes over a listening socket that was passed to us, as by inetd or systemd.
+fn inherit_listener(fd: RawFd) -> Result<TcpListener, io::Error> {
+    if !getsockopt(fd, sockopt::AcceptConn).map_err(nix_to_io)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("descriptor {} is not a listening socket", fd),
+        ));
+    }
+    fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).map_err(nix_to_io)?;
+    Ok(unsafe { TcpListener::from_raw_fd(fd) })
+}
+
+// Like `TcpListener::bind`, but with a backlog of our choosing.
+fn bind_listener(address: &str, backlog: usize) -> Result<TcpListener, io::Error> {
+    let mut last_error = None;
+    for addr in address.to_socket_addrs()? {
+        match listen_on(&addr, backlog) {
+            Ok(listener) => return Ok(listener),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+    }))
+}
+
+// Binds to `address`, retrying with a short backoff for up to `retry_for` while the address
+// is still in use.
+fn bind_with_retry(
+    address: &str,
+    backlog: usize,
+    retry_for: time::Duration,
+) -> Result<TcpListener, io::Error> {
+    let deadline = time::Instant::now() + retry_for;
+    let mut backoff = time::Duration::from_millis(100);
+    loop {
+        match bind_listener(address, backlog) {
+            Ok(listener) => return Ok(listener),
+            Err(error) => {
+                if error.kind() != io::ErrorKind::AddrInUse
+                    || time::Instant::now() + backoff > deadline
+                {
+                    return Err(error);
+                }
+            }
+        }
+        thread::sleep(backoff);
+        backoff = min(backoff * 2, time::Duration::from_secs(1));
+    }
+}
 
 fn resolve_io_error(error: &io::Error) -> Option<HttpStatus> {
     match error.kind() {
@@ -51,7 +189,10 @@ struct ContentRange {
     pub len: Option<usize>,
 }
 
-fn decode_content_range(range_str: &str) -> Option<ContentRange> {
+// Decodes a Range header for a body of `full_length` bytes. A suffix range, `bytes=-N`, is the
+// last N bytes. One that can't be satisfied, `bytes=-0` or any suffix of an empty body, starts
+// at the end, which callers answer with 416.
+fn decode_content_range(range_str: &str, full_length: usize) -> Option<ContentRange> {
     if !range_str.starts_with("bytes=") {
         return None;
     }
@@ -71,15 +212,31 @@ fn decode_content_range(range_str: &str) -> Option<ContentRange> {
     let start_str = &range_str[eq_ind + 1..dash_ind];
     let end_str = &range_str[dash_ind + 1..];
 
-    let start_int: usize = if start_str.len() > 0 {
-        match start_str.parse() {
+    if start_str.is_empty() {
+        let suffix_len: usize = match end_str.parse() {
             Ok(i) => i,
             _ => {
                 return None;
             }
+        };
+        let len = min(suffix_len, full_length);
+        if len == 0 {
+            return Some(ContentRange {
+                start: full_length,
+                len: None,
+            });
+        }
+        return Some(ContentRange {
+            start: full_length - len,
+            len: Some(len),
+        });
+    }
+
+    let start_int: usize = match start_str.parse() {
+        Ok(i) => i,
+        _ => {
+            return None;
         }
-    } else {
-        0
     };
 
     let end_int: Option<usize> = if end_str.len() > 0 {
@@ -94,7 +251,7 @@ fn decode_content_range(range_str: &str) -> Option<ContentRange> {
     };
 
     if let Some(end_i) = end_int {
-        if end_i == 0 || start_int > end_i {
+        if start_int > end_i {
             None
         } else {
             Some(ContentRange {
@@ -110,36 +267,72 @@ fn decode_content_range(range_str: &str) -> Option<ContentRange> {
     }
 }
 
+// Decodes the Content-Range of an upload, e.g. `bytes 0-99/1000`.
+fn decode_upload_content_range(range_str: &str) -> Option<UploadRange> {
+    if !range_str.starts_with("bytes ") {
+        return None;
+    }
+    let dash_ind = range_str.find('-')?;
+    let slash_ind = range_str.find('/')?;
+    if slash_ind < dash_ind {
+        return None;
+    }
+
+    let start: usize = range_str[6..dash_ind].trim().parse().ok()?;
+    let end: usize = range_str[dash_ind + 1..slash_ind].trim().parse().ok()?;
+    let total: usize = range_str[slash_ind + 1..].trim().parse().ok()?;
+
+    if start > end || end >= total {
+        return None;
+    }
+
+    Some(UploadRange {
+        start: start,
+        end: end,
+        total: total,
+    })
+}
+
 fn decode_request(req_body: &[u8]) -> Result<HttpRequest, HttpStatus> {
-    let request_str = match from_utf8(req_body) {
-        Ok(dec) => dec,
-        Err(_err) => {
-            // write_error(format!("Could not decode request: {}", err));
-            return Err(HttpStatus::BadRequest);
-        }
-    };
+    // The request line must be ASCII. Header values may contain other bytes, which we never
+    // need verbatim.
+    let line_end = req_body
+        .iter()
+        .position(|&b| b == b'\r')
+        .unwrap_or(req_body.len());
+    if !req_body[..line_end].is_ascii() {
+        return Err(HttpStatus::BadRequest);
+    }
+    let request_str = String::from_utf8_lossy(req_body);
 
-    return HttpRequest::new(request_str);
+    return HttpRequest::new(&request_str);
 }
 
 #[derive(PartialEq, Debug)]
 pub enum ConnectionState {
     ReadingRequest,
     ReadingPostBody,
+    ReadingPutBody,
     WritingResponse,
     Closing,
 }
 
 pub struct HttpConnection {
     pub stream: TcpStream,
+    // Looked up when the connection is accepted, as it can't be once the peer has gone away
+    pub peer_addr: Option<SocketAddr>,
     pub state: ConnectionState,
 
     // Buffer for holding a pending request
     pub buffer: Box<[u8; BUFFER_SIZE]>,
     pub bytes_read: usize,
     pub body_start_location: usize,
+    // End of the current request in the buffer. Anything after it is the start of the next,
+    // pipelined request.
+    pub request_end: usize,
 
     pub post_buffer: Option<PostBuffer>,
+    pub put_buffer: Option<PutBuffer>,
 
     // Space to store a per-request string response
     pub response: Option<HttpResponse>,
@@ -152,51 +345,279 @@ pub struct HttpConnection {
 
     pub bytes_requested: usize,
     pub bytes_sent: usize,
+
+    // When the current request was received, and `bytes_sent` at that point. Cleared once the
+    // request has been logged.
+    pub request_start: Option<time::Instant>,
+    pub request_bytes_start: usize,
+
+    // Sent back in X-Request-Id and logged, so requests can be correlated across proxies
+    pub request_id: Option<String>,
+
+    // Index of the virtual host serving the current request, if it isn't the default root
+    pub vhost: Option<usize>,
+
+    // Origin header of the current request, for CORS
+    pub origin: Option<String>,
+
+    // Declared Content-Length of the POST body being read, if any
+    pub upload_length: Option<usize>,
+
+    // File being sent in the current response, counted as a hit once fully written
+    pub served_file: Option<PathBuf>,
+
+    // Bytes of a request body nobody is going to read, still to be skipped over before the
+    // next request
+    pub discard_bytes: usize,
+
+    // Bytes received since `rate_window_start`, for enforcing a minimum request rate
+    pub rate_window_start: time::Instant,
+    pub rate_window_bytes: usize,
 }
 
 impl HttpConnection {
     pub fn new(stream: TcpStream) -> HttpConnection {
         return HttpConnection {
+            peer_addr: stream.peer_addr().ok(),
             stream: stream,
             state: ConnectionState::ReadingRequest,
             buffer: Box::new([0; BUFFER_SIZE]),
             bytes_read: 0,
             body_start_location: 0,
+            request_end: 0,
             post_buffer: None,
+            put_buffer: None,
             response: None,
             keep_alive: true,
             bytes_requested: 0,
             bytes_sent: 0,
+            request_start: None,
+            request_bytes_start: 0,
+            request_id: None,
+            vhost: None,
+            origin: None,
             last_requested_uri: None,
             last_requested_method: None,
             num_requests: 0,
+            upload_length: None,
+            served_file: None,
+            discard_bytes: 0,
+            rate_window_start: time::Instant::now(),
+            rate_window_bytes: 0,
         };
     }
 
     pub fn reset(&mut self) {
-        self.bytes_read = 0;
+        // Keep whatever the client has already sent of its next request. `bytes_read` also counts
+        // body bytes read past the buffer, but then nothing of the next request is in it yet.
+        let leftover = self.bytes_read - min(self.request_end, self.bytes_read);
+        if leftover > 0 {
+            self.buffer
+                .copy_within(self.bytes_read - leftover..self.bytes_read, 0);
+        }
+        self.bytes_read = leftover;
+        self.request_end = 0;
+        self.rate_window_start = time::Instant::now();
+        self.rate_window_bytes = 0;
         self.response = None;
         self.post_buffer = None;
+        self.put_buffer = None;
+        self.upload_length = None;
+        self.served_file = None;
+        self.request_id = None;
+        self.vhost = None;
+        self.origin = None;
+    }
+
+    // Whether this connection has no request in progress, either because it hasn't sent one
+    // yet or because it is being kept alive after the last one
+    pub fn is_between_requests(&self) -> bool {
+        self.state == ConnectionState::ReadingRequest && self.bytes_read == 0
+    }
+
+    // Whether this is a keep-alive connection that has finished a request and is waiting for the
+    // next one to start
+    pub fn is_idle(&self) -> bool { self.num_requests > 0 && self.is_between_requests() }
+
+    // Whether the current response stopped before all of it was sent
+    pub fn response_aborted(&self) -> bool {
+        match &self.response {
+            Some(resp) if resp.is_streaming() => !resp.is_complete(),
+            Some(_) => self.bytes_sent < self.bytes_requested,
+            None => false,
+        }
+    }
+
+    // The source a streamed response is waiting on for more to send, if any
+    pub fn waiting_on(&self) -> Option<RawFd> {
+        self.response.as_ref().and_then(|resp| resp.waiting_on())
+    }
+
+    // Number of request body bytes received so far
+    pub fn body_bytes_read(&self) -> usize {
+        if self.bytes_read > self.body_start_location {
+            self.bytes_read - self.body_start_location
+        } else {
+            0
+        }
+    }
+}
+
+// A file held in memory and served in place of anything on disk at the same path.
+pub struct VirtualFile {
+    pub data: Rc<[u8]>,
+    pub content_type: Option<String>,
+}
+
+// Sent to the interface whenever an upload has been received completely.
+pub struct CompletedUpload {
+    pub path: String,
+    pub size: usize,
+    pub addr: Option<SocketAddr>,
+}
+
+// Which kinds of requests are being served. Either can be paused from the interface, in which case
+// new requests of that kind get a 503 while everything else carries on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ServingMode {
+    pub reads: bool,
+    pub uploads: bool,
+}
+
+impl ServingMode {
+    pub fn from_opts(opts: &Opts) -> ServingMode {
+        ServingMode {
+            reads: !opts.start_disabled && !opts.start_reads_paused,
+            uploads: !opts.start_disabled && !opts.start_uploads_paused,
+        }
+    }
+
+    // Pauses everything if anything is being served, and resumes everything otherwise.
+    pub fn toggle_all(&mut self) {
+        let serving = self.reads || self.uploads;
+        self.reads = !serving;
+        self.uploads = !serving;
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match (self.reads, self.uploads) {
+            (true, true) => "Serving requests",
+            (true, false) => "Serving requests, uploads paused",
+            (false, true) => "Accepting uploads only",
+            (false, false) => "Rejecting requests",
+        }
+    }
+}
+
+// Features that can be switched on and off from the interface while the server runs.
+struct Features {
+    dir_listings: bool,
+    uploading: bool,
+}
+
+impl Features {
+    // Flips the feature a control byte from the interface stands for, if it stands for one.
+    fn toggle(&mut self, control: u8) {
+        match control {
+            b'd' => self.dir_listings = !self.dir_listings,
+            b'u' => self.uploading = !self.uploading,
+            _ => {}
+        }
     }
 }
 
 enum HttpResult {
     Response(HttpResponse, usize),
     Error(HttpStatus, Option<String>),
+    // An error response that needs extra headers
+    ErrorWithHeaders(HttpStatus, Option<String>, Vec<(String, String)>),
     ReadRequestBody,
 }
 
 pub struct HttpTui<'a> {
     listener: TcpListener,
+    // The address actually listened on, which differs from the requested one when the port was
+    // 0 or the socket was inherited
+    local_addr: SocketAddr,
     root_dir: &'a Path,
     history_channel: mpsc::Sender<String>,
-    dir_listings: bool,
-    disabled: bool,
-    uploading: bool,
+    features: Features,
+    serving_mode: ServingMode,
     upload_size_limit: usize,
+    max_discard_bytes: usize,
+    max_concurrent_uploads: usize,
+    max_uri_length: usize,
+    // Connections reading a form upload, as of the start of this pass through the main loop
+    uploads_in_progress: Cell<usize>,
     index_file: &'a str,
     no_index_file: bool,
     no_append_slash: bool,
+    virtual_files: HashMap<String, VirtualFile>,
+    deny_dotdot_encoded: bool,
+    listing_sort: rendering::ListingSort,
+    // Resumable PUT uploads that have not received every range yet
+    partial_uploads: RefCell<HashMap<PathBuf, ReceivedRanges>>,
+    // Number of times each file has been fully served since startup
+    hits: RefCell<HashMap<PathBuf, u64>>,
+    show_hits: bool,
+    zip_downloads: bool,
+    // When set, uploads are only accepted (and the upload form only shown) if the request
+    // carries this token
+    upload_token: Option<&'a str>,
+    upload_form: bool,
+    redirect_file_slash: bool,
+    allow_fifo: bool,
+    // Set while the root directory is missing, so the warning is only logged once
+    root_missing: Cell<bool>,
+    min_request_rate: Option<RequestRate>,
+    rate_limiter: Option<RateLimiter>,
+    // Where form uploads are written until they are complete
+    upload_temp_dir: Option<PathBuf>,
+    upload_mkdir: bool,
+    upload_channel: Option<mpsc::Sender<CompletedUpload>>,
+    log_format: LogFormat,
+    slow_request: Option<time::Duration>,
+    audit_log: Option<RefCell<AuditLog>>,
+    generate_md5: bool,
+    content_md5: bool,
+    gzip_level: Option<u32>,
+    brotli_level: Option<u32>,
+    gzip_min_size: usize,
+    no_keepalive: bool,
+    mmap: bool,
+    // For HTML files that are served, rather than pages we generate
+    html_content_type: String,
+    show_perms: bool,
+    show_dir_counts: bool,
+    show_readme: bool,
+    thumbnails: bool,
+    listing_md5_limit: Option<usize>,
+    dark_mode: bool,
+    // Operator-supplied CSS for listings, used verbatim
+    listing_css: Option<String>,
+    // From --header
+    extra_headers: Vec<(String, String)>,
+    // Decides whether to keep a newly accepted connection, by the address it came from
+    accept_filter: Option<Box<dyn Fn(&SocketAddr) -> bool>>,
+    // Reject multipart boundaries that don't follow RFC 2046 instead of making the best of them
+    strict_boundary: bool,
+    // Files we have already tried to generate a .md5sum sidecar for
+    md5_started: RefCell<HashSet<PathBuf>>,
+    content_types: &'a [ContentTypeRule],
+    hide: &'a [String],
+    hide_hard: bool,
+    // Roots to serve instead of `root_dir` for requests to these hosts
+    vhosts: Vec<(String, PathBuf)>,
+    // Directories consulted before the default root, highest priority first
+    overlays: Vec<PathBuf>,
+    cors: Option<CorsPolicy>,
+    // Request ids are this prefix, which is unique to this run, followed by a counter
+    request_id_prefix: String,
+    request_count: Cell<u64>,
+    // Used in place of the built-in error page layout
+    error_template: Option<String>,
+    metrics_enabled: bool,
+    metrics: Metrics,
 }
 
 impl HttpTui<'_> {
@@ -205,28 +626,164 @@ impl HttpTui<'_> {
         sender: mpsc::Sender<String>,
         opts: &'a Opts,
     ) -> Result<HttpTui<'a>, io::Error> {
-        let listener = TcpListener::bind(format!(
-            "{mask}:{port}",
-            mask = &opts.hostmask,
-            port = &opts.port
-        ))?;
+        let listener = match opts::inherited_listener_fd(opts) {
+            Some(fd) => inherit_listener(fd)?,
+            None => bind_with_retry(
+                &format!("{mask}:{port}", mask = &opts.hostmask, port = &opts.port),
+                opts.backlog,
+                time::Duration::from_secs(opts.bind_retry),
+            )?,
+        };
+        let local_addr = listener.local_addr()?;
         Ok(HttpTui {
             listener: listener,
+            local_addr: local_addr,
             root_dir: root_dir,
             history_channel: sender,
-            dir_listings: !opts.disable_directory_listings,
-            disabled: opts.start_disabled,
-            uploading: opts.uploading_enabled,
+            features: Features {
+                dir_listings: !opts.disable_directory_listings,
+                uploading: opts.uploading_enabled,
+            },
+            serving_mode: ServingMode::from_opts(opts),
             upload_size_limit: opts.size_limit,
+            max_discard_bytes: opts.max_discard_bytes,
+            max_concurrent_uploads: opts.max_concurrent_uploads,
+            max_uri_length: opts.max_uri_length,
+            uploads_in_progress: Cell::new(0),
             index_file: &opts.index_file,
             no_index_file: opts.no_index_file,
             no_append_slash: opts.no_append_slash,
+            virtual_files: HashMap::new(),
+            deny_dotdot_encoded: opts.deny_dotdot_encoded,
+            listing_sort: if opts.sort_files_first {
+                rendering::ListingSort::FilesFirst
+            } else if opts.sort_mixed {
+                rendering::ListingSort::Mixed
+            } else {
+                rendering::ListingSort::DirsFirst
+            },
+            partial_uploads: RefCell::new(HashMap::new()),
+            hits: RefCell::new(HashMap::new()),
+            show_hits: opts.show_hits,
+            zip_downloads: opts.zip_downloads,
+            upload_token: opts.upload_token.as_deref(),
+            upload_form: !opts.no_upload_form,
+            redirect_file_slash: opts.redirect_file_slash,
+            allow_fifo: opts.allow_fifo,
+            root_missing: Cell::new(false),
+            min_request_rate: opts.min_request_rate,
+            rate_limiter: if opts.global_rate_limit > 0 {
+                Some(RateLimiter::new(opts.global_rate_limit))
+            } else {
+                None
+            },
+            upload_temp_dir: opts.upload_temp_dir.as_ref().map(PathBuf::from),
+            upload_mkdir: opts.upload_mkdir,
+            upload_channel: None,
+            log_format: opts.log_format,
+            slow_request: opts.slow_request_ms.map(time::Duration::from_millis),
+            audit_log: None,
+            generate_md5: opts.generate_md5,
+            content_md5: opts.content_md5,
+            gzip_level: opts.gzip_level,
+            brotli_level: opts.brotli_level,
+            gzip_min_size: opts.gzip_min_size,
+            no_keepalive: opts.no_keepalive,
+            mmap: opts.mmap,
+            html_content_type: with_charset("text/html", &opts.default_charset),
+            show_perms: opts.show_perms,
+            show_dir_counts: opts.show_dir_counts,
+            show_readme: opts.show_readme,
+            thumbnails: opts.thumbnails,
+            listing_md5_limit: opts.listing_md5_limit,
+            dark_mode: opts.dark_mode,
+            listing_css: None,
+            extra_headers: Vec::new(),
+            accept_filter: None,
+            strict_boundary: opts.strict_boundary,
+            md5_started: RefCell::new(HashSet::new()),
+            content_types: &opts.content_types,
+            hide: &opts.hide,
+            hide_hard: opts.hide_hard,
+            vhosts: Vec::new(),
+            overlays: Vec::new(),
+            cors: CorsPolicy::from_opts(opts),
+            request_id_prefix: format!("{:x}", timestamp::unix_secs(time::SystemTime::now())),
+            request_count: Cell::new(0),
+            error_template: None,
+            metrics_enabled: opts.enable_metrics,
+            metrics: Metrics::new(),
         })
     }
 
+    // Register a file to be served from memory. `path` is the request path, including the
+    // leading '/'. Virtual files are consulted before the filesystem.
+    pub fn add_virtual_file(&mut self, path: &str, data: Vec<u8>, content_type: Option<String>) {
+        self.virtual_files.insert(
+            path.to_string(),
+            VirtualFile {
+                data: Rc::from(data),
+                content_type: content_type,
+            },
+        );
+    }
+
+    // The address we are listening on. With `--port 0` the port is the one the kernel picked.
+    pub fn local_addr(&self) -> SocketAddr { self.local_addr }
+
+    pub fn add_virtual_files(&mut self, files: HashMap<String, Vec<u8>>) {
+        for (path, data) in files {
+            let content_type = if path.ends_with(".html") {
+                Some(self.html_content_type.clone())
+            } else {
+                None
+            };
+            self.add_virtual_file(&path, data, content_type);
+        }
+    }
+
+    // Serve `root` to requests whose Host header names `host`. Roots must be canonical.
+    pub fn add_vhosts(&mut self, vhosts: Vec<(String, PathBuf)>) {
+        for (host, root) in vhosts {
+            self.vhosts.push((host_name(&host), root));
+        }
+    }
+
+    // Layer these directories over the default root. Each must be canonical.
+    pub fn add_overlays(&mut self, overlays: Vec<PathBuf>) { self.overlays = overlays; }
+
+    pub fn set_error_template(&mut self, template: Option<String>) {
+        self.error_template = template;
+    }
+
+    pub fn set_listing_css(&mut self, css: Option<String>) { self.listing_css = css; }
+
+    pub fn set_audit_log(&mut self, log: Option<AuditLog>) {
+        self.audit_log = log.map(RefCell::new);
+    }
+
+    // Added to every response, after the server's own headers.
+    pub fn set_extra_headers(&mut self, headers: Vec<(String, String)>) {
+        self.extra_headers = headers;
+    }
+
+    // Connections from addresses `filter` returns false for are closed as soon as they are
+    // accepted, before anything is read from them.
+    pub fn set_accept_filter(&mut self, filter: Option<Box<dyn Fn(&SocketAddr) -> bool>>) {
+        self.accept_filter = filter;
+    }
+
+    // Completed uploads will be reported on `sender`.
+    pub fn set_upload_channel(&mut self, sender: mpsc::Sender<CompletedUpload>) {
+        self.upload_channel = Some(sender);
+    }
+
     pub fn run(&mut self, pipe_read: RawFd, func: impl Fn(&HashMap<RawFd, HttpConnection>)) {
         let mut connections = HashMap::<RawFd, HttpConnection>::new();
         let l_raw_fd = self.listener.as_raw_fd();
+        // Set once we've been asked to shut down. New connections are refused, and we exit
+        // as soon as every request in flight has been answered.
+        let mut draining = false;
 
         'main: loop {
             let mut r_fds = FdSet::new();
@@ -234,18 +791,41 @@ impl HttpTui<'_> {
             let mut e_fds = FdSet::new();
 
             // First add listener:
-            r_fds.insert(l_raw_fd);
-            e_fds.insert(l_raw_fd);
+            if !draining {
+                r_fds.insert(l_raw_fd);
+                e_fds.insert(l_raw_fd);
+            }
 
             r_fds.insert(pipe_read);
             e_fds.insert(pipe_read);
 
+            // Once --global-rate-limit has been reached, responses wait until there's more
+            // allowance. Requests are still read in the meantime.
+            let throttled = match &self.rate_limiter {
+                Some(limiter) => {
+                    limiter.refill();
+                    !limiter.can_send()
+                }
+                None => false,
+            };
+
+            // Sources that streamed responses are waiting on, and the connection each is for
+            let mut sources = HashMap::<RawFd, RawFd>::new();
             for (fd, http_conn) in &connections {
                 match http_conn.state {
-                    ConnectionState::WritingResponse => {
-                        w_fds.insert(*fd);
-                    }
-                    ConnectionState::ReadingRequest | ConnectionState::ReadingPostBody => {
+                    ConnectionState::WritingResponse if throttled => {}
+                    ConnectionState::WritingResponse => match http_conn.waiting_on() {
+                        Some(source) => {
+                            r_fds.insert(source);
+                            sources.insert(source, *fd);
+                        }
+                        None => {
+                            w_fds.insert(*fd);
+                        }
+                    },
+                    ConnectionState::ReadingRequest
+                    | ConnectionState::ReadingPostBody
+                    | ConnectionState::ReadingPutBody => {
                         r_fds.insert(*fd);
                     }
                     _ => {}
@@ -253,14 +833,27 @@ impl HttpTui<'_> {
                 e_fds.insert(*fd);
             }
 
+            // Wake up regularly to check for slow connections, and when throttled responses can
+            // be sent again.
+            let mut timeout = TimeVal::seconds(1);
+            if let Some(limiter) = self.rate_limiter.as_ref().filter(|_| throttled) {
+                let wait = TimeVal::microseconds(limiter.wait().as_micros() as i64);
+                timeout = min(timeout, max(wait, TimeVal::microseconds(1)));
+            }
             match select(
                 None,
                 Some(&mut r_fds),
                 Some(&mut w_fds),
                 Some(&mut e_fds),
-                None,
+                if self.min_request_rate.is_some() || throttled {
+                    Some(&mut timeout)
+                } else {
+                    None
+                },
             ) {
                 Ok(_res) => {}
+                // Interrupted by a signal, which will have poked the pipe if it matters
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
                 Err(e) => {
                     println!("Got error while selecting: {}", e);
                     break;
@@ -268,6 +861,15 @@ impl HttpTui<'_> {
             }
 
             let mut force_close: bool = false;
+            let mut close_idle: bool = false;
+
+            self.uploads_in_progress.set(
+                connections
+                    .values()
+                    .filter(|conn| conn.state == ConnectionState::ReadingPostBody)
+                    .count(),
+            );
+            self.metrics.set_active_connections(connections.len());
 
             match r_fds.highest() {
                 None => {}
@@ -286,11 +888,28 @@ impl HttpTui<'_> {
                                     break 'main;
                                 }
                                 if buf[0] as char == 't' {
-                                    self.disabled = !self.disabled;
+                                    self.serving_mode.toggle_all();
+                                }
+                                if buf[0] as char == 'r' {
+                                    self.serving_mode.reads = !self.serving_mode.reads;
+                                }
+                                if buf[0] as char == 'w' {
+                                    self.serving_mode.uploads = !self.serving_mode.uploads;
                                 }
                                 if buf[0] as char == 'k' {
                                     force_close = true;
                                 }
+                                if buf[0] as char == 'i' {
+                                    close_idle = true;
+                                }
+                                if buf[0] as char == 's' && !draining {
+                                    draining = true;
+                                    let _ = shutdown(l_raw_fd, Shutdown::Both);
+                                    self.log_message(
+                                        "Shutting down once in-flight requests finish".to_string(),
+                                    );
+                                }
+                                self.features.toggle(buf[0]);
                                 if buf[0] as char == 'p' {
                                     // Poked :)
                                     // This is used to trigger another call
@@ -303,7 +922,16 @@ impl HttpTui<'_> {
                         }
                         if fd == l_raw_fd {
                             // If listener, get accept new connection and add it.
-                            if let Ok((stream, _addr)) = self.listener.accept() {
+                            if let Ok((stream, addr)) = self.listener.accept() {
+                                if let Some(filter) = &self.accept_filter {
+                                    if !filter(&addr) {
+                                        self.log_message(format!(
+                                            "Refused connection from {}",
+                                            addr.ip()
+                                        ));
+                                        continue;
+                                    }
+                                }
                                 let conn = HttpTui::create_http_connection(stream);
                                 let pfd = conn.stream.as_raw_fd();
                                 connections.insert(pfd, conn);
@@ -312,12 +940,13 @@ impl HttpTui<'_> {
                             // as we don't know if there is any data for us to read yet.
                             continue;
                         }
+                        let fd = sources.get(&fd).cloned().unwrap_or(fd);
                         // TODO: Error checking here
                         let mut conn = connections.get_mut(&fd).unwrap();
                         match self.handle_conn_sigpipe(&mut conn) {
                             Ok(_) => {}
                             Err(error) => {
-                                let _ = self.history_channel.send(format!(
+                                self.log_message(format!(
                                     "Uncaught OS error while handling connection: {}",
                                     error
                                 ));
@@ -367,69 +996,179 @@ impl HttpTui<'_> {
                 }
             }
 
+            if let Some(rate) = self.min_request_rate {
+                for conn in connections.values_mut() {
+                    self.check_request_rate(conn, &rate);
+                }
+            }
+            self.expire_partial_uploads();
+
             let to_remove: Vec<_> = connections
                 .iter()
-                .filter(|&(_, conn)| conn.state == ConnectionState::Closing || force_close)
+                .filter(|&(_, conn)| {
+                    conn.state == ConnectionState::Closing
+                        || force_close
+                        || (close_idle && conn.is_idle())
+                        || (draining && conn.is_between_requests())
+                })
                 .map(|(k, _)| k.clone())
                 .collect();
             for fd in to_remove {
-                if let Some(conn) = connections.get(&fd) {
-                    if conn.num_requests == 0 {
+                if let Some(conn) = connections.get_mut(&fd) {
+                    let aborted = conn.response_aborted();
+                    match conn.state {
+                        ConnectionState::ReadingPostBody | ConnectionState::ReadingPutBody => {
+                            self.write_conn_to_audit_log(conn)
+                        }
+                        _ if aborted => self.write_conn_to_audit_log(conn),
+                        _ => {}
+                    }
+                    if aborted {
+                        self.write_transfer_end_to_history(conn);
+                    }
+                    if conn.state == ConnectionState::ReadingPutBody {
+                        self.abandon_put(conn);
+                    }
+                    // In JSON mode, requests are logged once their response is complete, so
+                    // log any that never got that far.
+                    let unlogged =
+                        self.log_format == LogFormat::Json && conn.request_start.is_some();
+                    if conn.num_requests == 0 || unlogged {
                         self.write_conn_to_history(conn);
                     }
                 }
                 connections.remove(&fd);
             }
             func(&connections);
+            if draining && connections.is_empty() {
+                break 'main;
+            }
         }
     }
 
-    fn write_conn_to_history(&self, conn: &HttpConnection) {
-        if let Ok(peer_addr) = conn.stream.peer_addr() {
-            let ip_str = match peer_addr {
-                SocketAddr::V4(addr) => format!("{}:{}", addr.ip(), addr.port()),
-                SocketAddr::V6(addr) => format!("[{}]:{}", addr.ip(), addr.port()),
-            };
-            let code_str = match &conn.response {
-                Some(resp) => resp.get_code(),
-                None => "   ".to_string(),
-            };
-            let path_str = match &conn.last_requested_uri {
-                Some(path) => path,
-                None => "[No path...]",
-            };
-            let method_str = match &conn.last_requested_method {
-                Some(HttpMethod::GET) => "GET",
-                Some(HttpMethod::HEAD) => "HEAD",
-                Some(HttpMethod::POST) => "POST",
-                None => "???",
+    // Closes a connection that has sent less than the minimum rate while its request is being
+    // read. Otherwise, starts a new measuring period once the current one is over.
+    fn check_request_rate(&self, conn: &mut HttpConnection, rate: &RequestRate) {
+        if conn.state != ConnectionState::ReadingRequest {
+            return;
+        }
+        if conn.rate_window_start.elapsed() < time::Duration::from_secs(rate.seconds) {
+            return;
+        }
+        if conn.rate_window_bytes < rate.bytes {
+            let ip_str = match conn.stream.peer_addr() {
+                Ok(addr) => addr.ip().to_string(),
+                Err(_) => "[unknown]".to_string(),
             };
-            let pb_str = match &conn.post_buffer {
-                Some(pb) => {
-                    format!(
-                        "{}{}",
-                        if pb.get_new_files().len() > 0 {
-                            " files: "
-                        } else {
-                            ""
-                        },
-                        pb.get_new_files().join(", ")
-                    )
-                }
-                None => {
-                    format!("")
+            self.log_message(format!(
+                "Dropped {}: request sent slower than {} bytes in {}s",
+                ip_str, rate.bytes, rate.seconds
+            ));
+            conn.state = ConnectionState::Closing;
+        } else {
+            conn.rate_window_start = time::Instant::now();
+            conn.rate_window_bytes = 0;
+        }
+    }
+
+    fn write_conn_to_history(&self, conn: &mut HttpConnection) {
+        if let Some(mut entry) = LogEntry::new(conn) {
+            let line = match self.log_format {
+                LogFormat::Text => entry.to_text(),
+                LogFormat::Json => {
+                    entry.slow = self
+                        .slow_request
+                        .map(|threshold| entry.duration >= threshold);
+                    entry.completed = conn.response.as_ref().map(|_| !conn.response_aborted());
+                    entry.to_json()
                 }
             };
-            let _ = self.history_channel.send(format!(
-                "{:<22} {} {:<4} {}{}",
-                ip_str, code_str, method_str, path_str, pb_str
-            ));
+            let _ = self.history_channel.send(line);
+        }
+        // Text logs keep the start time so the request can be timed once its response is sent.
+        if self.log_format == LogFormat::Json {
+            conn.request_start = None;
+        }
+    }
+
+    // Text logs show requests before they have been served, so ones that turn out to be slower
+    // than --slow-request-ms are logged again once their response has been sent. JSON entries
+    // are written then anyway, and are marked instead.
+    fn write_slow_request_to_history(&self, conn: &HttpConnection) {
+        let threshold = match self.slow_request {
+            Some(threshold) if self.log_format == LogFormat::Text => threshold,
+            _ => return,
+        };
+        if let Some(entry) = LogEntry::new(conn) {
+            if conn.request_start.is_some() && entry.duration >= threshold {
+                let _ = self.history_channel.send(format!(
+                    "SLOW {}ms {}",
+                    entry.duration.as_millis(),
+                    entry.to_text()
+                ));
+            }
+        }
+    }
+
+    // Text logs show downloads again once they are over, saying whether the whole response was
+    // sent or the transfer was aborted. JSON entries are written then anyway, and say so instead.
+    fn write_transfer_end_to_history(&self, conn: &HttpConnection) {
+        if self.log_format != LogFormat::Text || conn.last_requested_method != Some(HttpMethod::GET)
+        {
+            return;
+        }
+        if let Some(entry) = LogEntry::new(conn) {
+            let _ = self.history_channel.send(if conn.response_aborted() {
+                format!(
+                    "ABORTED {} after {} bytes",
+                    entry.to_text(),
+                    entry.bytes_sent
+                )
+            } else {
+                format!("COMPLETED {} ({} bytes)", entry.to_text(), entry.bytes_sent)
+            });
+        }
+    }
+
+    // Downloads and uploads go in the --audit-log once they are over, whether or not they
+    // completed. Nothing can be done about a failed write short of stopping the server, so
+    // they are ignored.
+    fn write_conn_to_audit_log(&self, conn: &HttpConnection) {
+        let log = match &self.audit_log {
+            Some(log) => log,
+            None => return,
+        };
+        match conn.last_requested_method {
+            Some(HttpMethod::GET) | Some(HttpMethod::POST) | Some(HttpMethod::PUT) => {}
+            _ => return,
+        }
+        if let Some(entry) = LogEntry::new(conn) {
+            let _ = log.borrow_mut().write_line(&entry.to_audit());
+        }
+    }
+
+    // Text logs show uploads again once their body has been received. JSON logs only have one
+    // entry per request, written when the response has been sent.
+    // Writes a message that isn't about a single request to the history.
+    fn log_message(&self, message: String) {
+        let _ = self
+            .history_channel
+            .send(format_message(self.log_format, message));
+    }
+
+    fn write_upload_to_history(&self, conn: &mut HttpConnection) {
+        if self.log_format == LogFormat::Text {
+            self.write_conn_to_history(conn);
         }
     }
 
     fn handle_request(&self, conn: &mut HttpConnection) -> Result<ConnectionState, io::Error> {
         let res = self.parse_and_service_request(conn);
-        self.write_conn_to_history(conn);
+        // Text logs show requests as soon as they come in; JSON logs wait for the response to
+        // be sent so they can include its size and duration.
+        if self.log_format == LogFormat::Text {
+            self.write_conn_to_history(conn);
+        }
 
         let state = match res {
             Ok(state) => state,
@@ -458,7 +1197,7 @@ impl HttpTui<'_> {
         conn: &mut HttpConnection,
     ) -> Result<ConnectionState, io::Error> {
         let buffer = &mut conn.buffer;
-        let bytes_read = match conn.stream.read(&mut buffer[conn.bytes_read..]) {
+        let mut bytes_read = match conn.stream.read(&mut buffer[conn.bytes_read..]) {
             Ok(size) => size,
             Err(_err) => {
                 /*
@@ -472,29 +1211,104 @@ impl HttpTui<'_> {
             }
         };
 
+        conn.rate_window_bytes += bytes_read;
+
+        if bytes_read > 0 && conn.discard_bytes > 0 {
+            // Drop what's left of the previous request's body.
+            let discarded = min(conn.discard_bytes, bytes_read);
+            let start = conn.bytes_read;
+            buffer.copy_within(start + discarded..start + bytes_read, start);
+            conn.discard_bytes -= discarded;
+            bytes_read -= discarded;
+            if bytes_read == 0 {
+                return Ok(ConnectionState::ReadingRequest);
+            }
+        }
+
         conn.bytes_read += bytes_read;
         if bytes_read == 0 {
             return Ok(ConnectionState::Closing);
+        } else if buffer[..conn.bytes_read].starts_with(HTTP2_PREFACE_LINE) {
+            // An HTTP/2 client that didn't negotiate first. What follows the preface is binary
+            // frames, so answer as soon as its first line is in and close.
+            conn.keep_alive = false;
+            return self.create_oneoff_response(
+                HttpStatus::HttpVersionNotSupported,
+                conn,
+                Some("HTTP/2 is not supported. Please use HTTP/1.1.".to_string()),
+            );
+        } else if self.request_target_too_long(&buffer[..conn.bytes_read]) {
+            conn.keep_alive = false;
+            return self.create_oneoff_response(
+                HttpStatus::UriTooLong,
+                conn,
+                Some(format!(
+                    "The requested URI is too long. It must be at most {} bytes.",
+                    self.max_uri_length
+                )),
+            );
         } else if conn.bytes_read == buffer.len() {
+            if !buffer.contains(&b'\n') {
+                // Still on the request line, so it's the target that didn't fit.
+                conn.keep_alive = false;
+                return self.create_oneoff_response(
+                    HttpStatus::UriTooLong,
+                    conn,
+                    Some(format!(
+                        "The requested URI is too long. It must be less than {} bytes.",
+                        BUFFER_SIZE
+                    )),
+                );
+            }
             if let Some(start) = boyer_moore::find_body_start(&conn.buffer[..conn.bytes_read]) {
                 conn.body_start_location = start;
                 return self.handle_request(conn);
             }
+            // Without the end of the headers there's no telling where the next request starts.
+            conn.keep_alive = false;
             return self.create_oneoff_response(
                 HttpStatus::RequestHeadersTooLarge,
                 conn,
-                Some(
-                    "Request headers are too long. The total size must be less than 4KB."
-                        .to_string(),
-                ),
+                Some(format!(
+                    "Request headers are too long. The total size must be less than {} bytes.",
+                    BUFFER_SIZE
+                )),
             );
         } else {
-            if let Some(start) = boyer_moore::find_body_start(&conn.buffer[..conn.bytes_read]) {
-                conn.body_start_location = start;
-                return self.handle_request(conn);
-            }
-            Ok(ConnectionState::ReadingRequest)
+            self.handle_buffered_request(conn)
+        }
+    }
+
+    // Whether the target on the request line, as much of it as has arrived, is over
+    // --max-uri-length. This is checked before the rest of the request is in so that a long
+    // target can't be mistaken for long headers.
+    fn request_target_too_long(&self, buffer: &[u8]) -> bool {
+        if self.max_uri_length == 0 {
+            return false;
+        }
+        let line_end = buffer.iter().position(|&b| b == b'\r' || b == b'\n');
+        let line = &buffer[..line_end.unwrap_or(buffer.len())];
+        let target = match line.iter().position(|&b| b == b' ') {
+            Some(start) => &line[start + 1..],
+            None => return false,
+        };
+        let target_len = target
+            .iter()
+            .position(|&b| b == b' ')
+            .unwrap_or(target.len());
+        target_len > self.max_uri_length
+    }
+
+    // Handles the request in the connection's buffer, if all of its headers have arrived.
+    fn handle_buffered_request(
+        &self,
+        conn: &mut HttpConnection,
+    ) -> Result<ConnectionState, io::Error> {
+        if let Some(start) = boyer_moore::find_body_start(&conn.buffer[..conn.bytes_read]) {
+            conn.body_start_location = start;
+            return self.handle_request(conn);
         }
+        Ok(ConnectionState::ReadingRequest)
     }
 
     fn handle_post(
@@ -502,18 +1316,61 @@ impl HttpTui<'_> {
         req: &HttpRequest,
         conn: &mut HttpConnection,
     ) -> Result<HttpResult, io::Error> {
-        if !self.uploading {
+        if !self.features.uploading {
             return Ok(HttpResult::Error(
                 HttpStatus::MethodNotAllowed,
                 Some(format!("This server does not accept POST requests.")),
             ));
         }
 
+        if !self.has_upload_token(req) {
+            return Ok(HttpResult::Error(
+                HttpStatus::PermissionDenied,
+                Some("A valid upload token is required to upload files.".to_string()),
+            ));
+        }
+
+        let decoder = match content_encoding::body_decoder(req.get_header("content-encoding")) {
+            Ok(decoder) => decoder,
+            Err(encoding) => {
+                return Ok(HttpResult::Error(
+                    HttpStatus::UnsupportedMediaType,
+                    Some(format!("Unsupported Content-Encoding: {}", encoding)),
+                ));
+            }
+        };
+
+        // Each form upload has a large buffer of its own, so limit how many run at once.
+        if self.max_concurrent_uploads > 0
+            && self.uploads_in_progress.get() >= self.max_concurrent_uploads
+        {
+            return Ok(HttpResult::ErrorWithHeaders(
+                HttpStatus::TooManyRequests,
+                Some("Too many uploads are in progress. Please try again shortly.".to_string()),
+                vec![(
+                    "Retry-After".to_string(),
+                    UPLOAD_RETRY_AFTER_SECS.to_string(),
+                )],
+            ));
+        }
+
         // Returning an error in this function is questionable.
         // Any browser making a real POST request will have its connection
         // reset while sending its data over. They will receive the error
         // message, but probably won't display it.
 
+        if self.strict_boundary {
+            match boundary_param(req) {
+                Some(param) if !is_strict_boundary(param) => {
+                    return Ok(HttpResult::Error(
+                        HttpStatus::BadRequest,
+                        Some(format!("Invalid multipart boundary: {}", param)),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
         let boundary = match get_post_boundary(req) {
             Some(b) => b,
             None => {
@@ -550,9 +1407,13 @@ impl HttpTui<'_> {
             &req.path[..]
         };
 
-        let path = self.root_dir.join(normalized_path);
+        let root_dir = self.root_for(conn);
+        let path = root_dir.join(normalized_path);
+        if self.upload_mkdir {
+            create_upload_dirs(root_dir, &path)?;
+        }
 
-        let canonical_path = match get_and_check_canon_path(&self.root_dir, path)? {
+        let canonical_path = match get_and_check_canon_path(root_dir, path)? {
             Some(path) => path,
             None => {
                 return Ok(HttpResult::Error(
@@ -562,27 +1423,456 @@ impl HttpTui<'_> {
             }
         };
 
+        // Chunked uploads have no declared length; the interface shows them as indeterminate.
+        conn.upload_length = match req.get_header("content-length") {
+            Some(len) => len.trim().parse::<usize>().ok(),
+            None => None,
+        };
+
+        // The end of a compressed body may still be on its way after the final boundary has been
+        // decoded, so the connection can't be reused.
+        if decoder.is_some() {
+            conn.keep_alive = false;
+        }
+
         let pb = PostBuffer::new(
             canonical_path,
             post_delimeter,
             real_boundary,
             &conn.buffer[conn.body_start_location..conn.bytes_read],
             self.upload_size_limit,
+            self.max_discard_bytes,
+            self.upload_temp_dir.clone(),
+            decoder,
         );
 
         conn.post_buffer = Some(pb);
+        self.uploads_in_progress
+            .set(self.uploads_in_progress.get() + 1);
+        conn.request_end = conn.bytes_read;
+        conn.discard_bytes = 0;
         Ok(HttpResult::ReadRequestBody)
     }
 
-    fn handle_get(&self, req: &HttpRequest) -> Result<HttpResult, io::Error> {
+    fn handle_put(
+        &self,
+        req: &HttpRequest,
+        conn: &mut HttpConnection,
+    ) -> Result<HttpResult, io::Error> {
+        if !self.features.uploading {
+            return Ok(HttpResult::Error(
+                HttpStatus::MethodNotAllowed,
+                Some(format!("This server does not accept PUT requests.")),
+            ));
+        }
+
+        if !self.has_upload_token(req) {
+            return Ok(HttpResult::Error(
+                HttpStatus::PermissionDenied,
+                Some("A valid upload token is required to upload files.".to_string()),
+            ));
+        }
+
+        let decoder = match content_encoding::body_decoder(req.get_header("content-encoding")) {
+            Ok(decoder) => decoder,
+            Err(encoding) => {
+                return Ok(HttpResult::Error(
+                    HttpStatus::UnsupportedMediaType,
+                    Some(format!("Unsupported Content-Encoding: {}", encoding)),
+                ));
+            }
+        };
+
+        let content_length: usize = match req.get_header("content-length") {
+            Some(len) => match len.trim().parse() {
+                Ok(len) => len,
+                _ => {
+                    return Ok(HttpResult::Error(
+                        HttpStatus::BadRequest,
+                        Some(format!("Invalid Content-Length: {}", len)),
+                    ));
+                }
+            },
+            None => {
+                return Ok(HttpResult::Error(
+                    HttpStatus::LengthRequired,
+                    Some("PUT requests must include a Content-Length.".to_string()),
+                ));
+            }
+        };
+
+        // A Content-Range makes this one piece of a resumable upload.
+        let range = match req.get_header("content-range") {
+            Some(range_str) => match decode_upload_content_range(range_str) {
+                Some(range) if range.end - range.start + 1 == content_length => Some(range),
+                _ => {
+                    return Ok(HttpResult::Error(
+                        HttpStatus::BadRequest,
+                        Some(format!("Invalid Content-Range: {}", range_str)),
+                    ));
+                }
+            },
+            None => None,
+        };
+        if range.is_some() && decoder.is_some() {
+            return Ok(HttpResult::Error(
+                HttpStatus::BadRequest,
+                Some("Compressed uploads can't be sent in pieces.".to_string()),
+            ));
+        }
+
+        let total_size = match &range {
+            Some(range) => range.total,
+            None => content_length,
+        };
+        if self.upload_size_limit > 0 && total_size > self.upload_size_limit {
+            return Ok(HttpResult::Error(
+                HttpStatus::PayloadTooLarge,
+                Some(format!(
+                    "Upload size limit of {} bytes exceeded",
+                    self.upload_size_limit
+                )),
+            ));
+        }
+
+        let normalized_path = if req.path.starts_with("/") {
+            &req.path[1..]
+        } else {
+            &req.path[..]
+        };
+        let (dir, filename) = match normalized_path.rfind('/') {
+            Some(i) => (&normalized_path[..i], &normalized_path[i + 1..]),
+            None => ("", normalized_path),
+        };
+        if filename == "" || filename == "." || filename == ".." {
+            return Ok(HttpResult::Error(
+                HttpStatus::BadRequest,
+                Some(format!("Invalid filename: {}", filename)),
+            ));
+        }
+
+        let root_dir = self.root_for(conn);
+        if self.upload_mkdir {
+            create_upload_dirs(root_dir, &root_dir.join(dir))?;
+        }
+        let canonical_dir = match get_and_check_canon_path(root_dir, root_dir.join(dir))? {
+            Some(path) => path,
+            None => {
+                return Ok(HttpResult::Error(
+                    HttpStatus::NotFound,
+                    Some("Path disallowed.".to_string()),
+                ));
+            }
+        };
+        let file_path = canonical_dir.join(filename);
+
+        let mut partial_uploads = self.partial_uploads.borrow_mut();
+        let open_result = match &range {
+            Some(range) => match partial_uploads.get(&file_path) {
+                Some(received) => {
+                    if received.total() != range.total {
+                        return Ok(HttpResult::Error(
+                            HttpStatus::BadRequest,
+                            Some(format!(
+                                "Upload size {} does not match the size of the upload in progress \
+                                 ({}).",
+                                range.total,
+                                received.total()
+                            )),
+                        ));
+                    }
+                    if received.overlaps(range) {
+                        return Ok(HttpResult::Error(
+                            HttpStatus::RangeNotSatisfiable,
+                            Some(format!(
+                                "Range {}-{} overlaps data that has already been received, or is \
+                                 being received.",
+                                range.start, range.end
+                            )),
+                        ));
+                    }
+                    OpenOptions::new().write(true).open(&file_path)
+                }
+                None => {
+                    let res = OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&file_path);
+                    if res.is_ok() {
+                        partial_uploads.insert(file_path.clone(), ReceivedRanges::new(range.total));
+                    }
+                    res
+                }
+            },
+            None => OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&file_path),
+        };
+
+        let file = match open_result {
+            Ok(file) => file,
+            Err(error) => {
+                if error.kind() == io::ErrorKind::AlreadyExists {
+                    return Ok(HttpResult::Error(
+                        HttpStatus::Conflict,
+                        Some(
+                            "A file with this name already exists. Please use a different name."
+                                .to_string(),
+                        ),
+                    ));
+                }
+                return Err(error);
+            }
+        };
+        if let Some(range) = &range {
+            if let Some(received) = partial_uploads.get_mut(&file_path) {
+                received.start(range);
+            }
+        }
+        drop(partial_uploads);
+
+        conn.upload_length = Some(content_length);
+        conn.put_buffer = Some(PutBuffer::new(
+            file_path,
+            file,
+            range,
+            content_length,
+            &conn.buffer[conn.body_start_location..conn.bytes_read],
+            decoder,
+            self.upload_size_limit,
+        )?);
+        // A pipelined request may follow the body in what has been read so far.
+        conn.request_end = conn.body_start_location + content_length;
+        conn.discard_bytes = 0;
+
+        Ok(HttpResult::ReadRequestBody)
+    }
+
+    // The token may be given as a `token` query parameter (which is how the upload form passes
+    // it along) or in an X-Upload-Token header.
+    fn has_upload_token(&self, req: &HttpRequest) -> bool {
+        match self.upload_token {
+            Some(token) => {
+                req.get_query_param("token").as_deref() == Some(token)
+                    || req.get_header("x-upload-token").map(|t| t.as_str()) == Some(token)
+            }
+            None => true,
+        }
+    }
+
+    // Whether listings for this request include the upload form. With --no-upload-form, uploads
+    // are still accepted from clients that don't need it.
+    fn shows_upload_form(&self, req: &HttpRequest) -> bool {
+        self.features.uploading && self.upload_form && self.has_upload_token(req)
+    }
+
+    fn allowed_methods(&self) -> String {
+        if self.features.uploading {
+            "GET, HEAD, POST, PUT, OPTIONS, PROPFIND".to_string()
+        } else {
+            "GET, HEAD, OPTIONS, PROPFIND".to_string()
+        }
+    }
+
+    // Request headers a cross-origin upload needs to be allowed to send, if uploading is enabled
+    fn allowed_upload_headers(&self) -> Option<String> {
+        if !self.features.uploading {
+            None
+        } else if self.upload_token.is_some() {
+            Some("Content-Type, X-Upload-Token".to_string())
+        } else {
+            Some("Content-Type".to_string())
+        }
+    }
+
+    fn handle_options(
+        &self,
+        req: &HttpRequest,
+        conn: &HttpConnection,
+    ) -> Result<HttpResult, io::Error> {
+        // `OPTIONS *` and `Max-Forwards: 0` ask about the server as a whole, so don't
+        // look anything up on the filesystem.
+        let server_wide =
+            req.raw_target == "*" || req.get_header("max-forwards").map(|v| v.trim()) == Some("0");
+
+        if !server_wide {
+            let normalized_path = if req.path.starts_with("/") {
+                &req.path[1..]
+            } else {
+                &req.path[..]
+            };
+            let root_dir = self.root_for(conn);
+            let path = root_dir.join(normalized_path);
+            if get_and_check_canon_path(root_dir, path)?.is_none() {
+                return Ok(HttpResult::Error(
+                    HttpStatus::NotFound,
+                    Some("Path disallowed.".to_string()),
+                ));
+            }
+        }
+
+        let mut resp = HttpResponse::new(HttpStatus::OK, &req.version);
+        resp.add_header("Server".to_string(), "hypershare".to_string());
+        resp.add_header("Allow".to_string(), self.allowed_methods());
+        // Lets WebDAV clients know they can mount us, read-only.
+        resp.add_header("DAV".to_string(), "1".to_string());
+        // Hints for clients working out what they can do before they do it.
+        resp.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+        if self.features.uploading && self.upload_size_limit > 0 {
+            resp.add_header(
+                "X-Upload-Size-Limit".to_string(),
+                self.upload_size_limit.to_string(),
+            );
+        }
+        if let (Some(cors), Some(origin)) = (&self.cors, req.get_header("origin")) {
+            if req.get_header("access-control-request-method").is_some() {
+                cors.add_preflight_headers(
+                    origin,
+                    self.allowed_methods(),
+                    self.allowed_upload_headers(),
+                    &mut resp,
+                );
+            }
+        }
+        resp.set_content_length(0);
+        Ok(HttpResult::Response(resp, 0))
+    }
+
+    // Whether --hide-hard turns away a request for this path, because one of its components is
+    // hidden.
+    fn is_hard_hidden(&self, req: &HttpRequest) -> bool {
+        self.hide_hard
+            && req
+                .path
+                .split('/')
+                .any(|name| rendering::is_hidden(name, self.hide))
+    }
+
+    // A read-only subset of WebDAV: describes a file or directory and, unless `Depth: 0` is
+    // given, a directory's entries. Deeper levels aren't walked even for `Depth: infinity`.
+    fn handle_propfind(
+        &self,
+        req: &HttpRequest,
+        conn: &HttpConnection,
+    ) -> Result<HttpResult, io::Error> {
+        if self.is_hard_hidden(req) {
+            return Ok(hidden_path_error());
+        }
+        let root_dir = self.root_for(conn);
+        let path = root_dir.join(req.fs_path());
+        let canonical_path = match get_and_check_canon_path(root_dir, path) {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                return Ok(HttpResult::Error(
+                    HttpStatus::NotFound,
+                    Some("Path disallowed.".to_string()),
+                ));
+            }
+            Err(error) => {
+                return match resolve_io_error(&error) {
+                    Some(http_error) => Ok(HttpResult::Error(http_error, Some(error.to_string()))),
+                    None => Err(error),
+                };
+            }
+        };
+
+        let include_children = req.get_header("depth").map(|v| v.trim()) != Some("0");
+        if include_children && !self.features.dir_listings && canonical_path.is_dir() {
+            return Ok(HttpResult::Error(
+                HttpStatus::PermissionDenied,
+                Some(format!("Unable to list this directory.")),
+            ));
+        }
+
+        let body = rendering::render_multistatus(
+            &href_path(req),
+            &canonical_path,
+            include_children,
+            self.listing_sort,
+            self.hide,
+        )?;
+
+        let mut resp = HttpResponse::new(HttpStatus::MultiStatus, &req.version);
+        resp.add_header("Server".to_string(), "hypershare".to_string());
+        resp.add_header(
+            "Content-Type".to_string(),
+            "application/xml; charset=utf-8".to_string(),
+        );
+        let len = body.len();
+        resp.set_content_length(len);
+        resp.add_body(ResponseDataType::String(SeekableString::new(body)));
+        Ok(HttpResult::Response(resp, len))
+    }
+
+    fn handle_get(
+        &self,
+        req: &HttpRequest,
+        conn: &mut HttpConnection,
+    ) -> Result<HttpResult, io::Error> {
+        if let Some(vf) = self.virtual_files.get(&req.path) {
+            let data = ResponseDataType::Shared(io::Cursor::new(Rc::clone(&vf.data)));
+            return self.create_ranged_response(
+                req,
+                data,
+                vf.data.len(),
+                self.content_type_override(&req.path)
+                    .or(vf.content_type.as_deref()),
+                true,
+            );
+        }
+
+        if self.is_hard_hidden(req) {
+            return Ok(hidden_path_error());
+        }
+
+        if req.path == SEARCH_PATH {
+            return self.handle_search(req, conn);
+        }
+
+        if self.metrics_enabled && req.path == METRICS_PATH {
+            let s = self.metrics.render();
+            let len = s.len();
+            let data = ResponseDataType::String(SeekableString::new(s));
+            return self.create_ranged_response(
+                req,
+                data,
+                len,
+                Some("text/plain; version=0.0.4"),
+                false,
+            );
+        }
+
         let normalized_path = if req.path.starts_with("/") {
             &req.path[1..]
         } else {
             &req.path[..]
         };
 
-        let path = self.root_dir.join(normalized_path);
-        let mut canonical_path = match get_and_check_canon_path(&self.root_dir, path)? {
+        let root_dir = self.find_root(conn, req.fs_path());
+        let path = root_dir.join(req.fs_path());
+
+        // A trailing slash only makes sense on a directory. Don't serve a file under it.
+        if normalized_path.ends_with('/') && !path.is_dir() {
+            let trimmed = normalized_path.trim_end_matches('/');
+            let file_path = root_dir.join(trimmed);
+            if let Ok(Some(file_path)) = get_and_check_canon_path(root_dir, file_path) {
+                if file_path.is_file() {
+                    if !self.redirect_file_slash {
+                        return Ok(HttpResult::Error(
+                            HttpStatus::NotFound,
+                            Some(format!("/{} is not a directory.", trimmed)),
+                        ));
+                    }
+                    let mut resp = HttpResponse::new(HttpStatus::MovedPermanently, &req.version);
+                    resp.add_header("Location".to_string(), format!("/{}", trimmed));
+                    resp.add_header("Server".to_string(), format!("hypershare"));
+                    return Ok(HttpResult::Response(resp, 0));
+                }
+            }
+        }
+
+        let mut canonical_path = match get_and_check_canon_path(root_dir, path)? {
             Some(path) => path,
             None => {
                 return Ok(HttpResult::Error(
@@ -602,6 +1892,11 @@ impl HttpTui<'_> {
             Ok(data) => data,
         };
 
+        if self.zip_downloads && original_metadata.is_dir() && req.get_query_param("zip").is_some()
+        {
+            return self.create_zip_response(req, &canonical_path);
+        }
+
         if !self.no_append_slash {
             if normalized_path.len() > 0
                 && original_metadata.is_dir()
@@ -614,21 +1909,53 @@ impl HttpTui<'_> {
             }
         }
 
-        // If we are a directory, attempt to find the index file.
+        // With overlays, a directory is the merge of that directory in every root that has it.
+        let listing_dirs = if original_metadata.is_dir() {
+            self.directories_at(conn, req.fs_path())
+        } else {
+            Vec::new()
+        };
+
+        // If we are a directory, at any depth, attempt to find the index file. Variants in the
+        // client's preferred languages (e.g. index.fr.html) come first.
         // If it's not there, just render the directory.
+        let mut serving_index = false;
+        let mut index_language = None;
         let metadata = if original_metadata.is_dir() && !self.no_index_file {
-            canonical_path.push(self.index_file);
-            match fs::metadata(&canonical_path) {
-                Err(_error) => {
-                    canonical_path.pop();
-                    original_metadata
+            let languages = match req.get_header("accept-language") {
+                Some(header) => accepted_languages(header),
+                None => Vec::new(),
+            };
+            let mut candidates = languages
+                .into_iter()
+                .map(|lang| (localized_name(self.index_file, &lang), Some(lang)))
+                .chain(iter::once((self.index_file.to_string(), None)));
+            let index = candidates.find_map(|(name, lang)| {
+                listing_dirs.iter().find_map(|dir| {
+                    let index_path = dir.join(&name);
+                    match fs::metadata(&index_path) {
+                        Ok(data) if data.is_file() => Some((index_path, data, lang.clone())),
+                        _ => None,
+                    }
+                })
+            });
+            match index {
+                Some((index_path, data, lang)) => {
+                    canonical_path = index_path;
+                    serving_index = true;
+                    index_language = lang;
+                    data
                 }
-                Ok(data) => data,
+                None => original_metadata,
             }
         } else {
             original_metadata
         };
 
+        if self.allow_fifo && metadata.file_type().is_fifo() {
+            return self.create_fifo_response(req, conn, &canonical_path);
+        }
+
         if !metadata.is_file() && !metadata.is_dir() {
             return Ok(HttpResult::Error(
                 HttpStatus::PermissionDenied,
@@ -636,44 +1963,411 @@ impl HttpTui<'_> {
             ));
         }
 
-        if !self.dir_listings && metadata.is_dir() {
+        if !self.features.dir_listings && metadata.is_dir() {
             return Ok(HttpResult::Error(
                 HttpStatus::PermissionDenied,
                 Some(format!("Unable to list this directory.")),
             ));
         }
 
-        let (mut response_data, full_length, mime) = if metadata.is_dir() {
-            let s: String = rendering::render_directory(
-                normalized_path,
-                canonical_path.as_path(),
-                self.uploading,
-            );
-            let len = s.len();
-            let data = ResponseDataType::String(SeekableString::new(s));
-            (data, len, Some("text/html; charset=utf-8"))
+        // Listings can be revalidated, unless they show hit counts, which change without the
+        // directory changing.
+        let validators = if metadata.is_dir() && !self.show_hits {
+            listing_validators(
+                &listing_dirs,
+                self.listing_sort,
+                self.hide,
+                wants_text_listing(req),
+                self.shows_upload_form(req),
+            )
         } else {
-            let data = ResponseDataType::File(fs::File::open(&canonical_path)?);
-            let len = if metadata.is_file() {
-                metadata.len() as usize
+            None
+        };
+        if let Some((etag, modified)) = &validators {
+            if is_not_modified(req, etag, *modified) {
+                let mut resp = HttpResponse::new(HttpStatus::NotModified, &req.version);
+                resp.add_header("Server".to_string(), format!("hypershare"));
+                resp.add_header("ETag".to_string(), etag.clone());
+                resp.add_header("Last-Modified".to_string(), timestamp::http_date(*modified));
+                resp.add_vary("Accept");
+                return Ok(HttpResult::Response(resp, 0));
+            }
+        }
+
+        // Listings are generated per request and may change between requests, so byte ranges
+        // of them are meaningless.
+        let mut content_md5 = None;
+        let (response_data, full_length, mime, ranges_allowed) =
+            if metadata.is_dir() && wants_text_listing(req) {
+                let s = self.render_text_listing(&listing_dirs);
+                let len = s.len();
+                let data = ResponseDataType::String(SeekableString::new(s));
+                (data, len, Some("text/plain; charset=utf-8"), false)
+            } else if metadata.is_dir() {
+                let hits = self.hits.borrow();
+                let s: String = rendering::render_directory(
+                    normalized_path,
+                    &href_path(req),
+                    &listing_dirs,
+                    &rendering::ListingOptions {
+                        show_form: self.shows_upload_form(req),
+                        upload_token: self.upload_token,
+                        sort: self.listing_sort,
+                        hits: if self.show_hits { Some(&hits) } else { None },
+                        zip_link: self.zip_downloads,
+                        show_perms: self.show_perms,
+                        dir_counts: self.show_dir_counts,
+                        readme: self.show_readme,
+                        thumbnails: self.thumbnails,
+                        md5_limit: self.listing_md5_limit,
+                        dark_mode: self.dark_mode,
+                        css: self.listing_css.as_deref(),
+                        hide: self.hide,
+                    },
+                );
+                let len = s.len();
+                let data = ResponseDataType::String(SeekableString::new(s));
+                (data, len, Some("text/html; charset=utf-8"), false)
             } else {
-                std::u32::MAX as usize
-            };
-            // (data, len, None)
-            (
-                data,
-                len,
-                if req.path.ends_with(".html") {
-                    Some("text/html; charset=utf-8")
+                let file = fs::File::open(&canonical_path)?;
+                // Only complete downloads of the whole file count as hits.
+                if req.method == Some(HttpMethod::GET) && req.get_header("range").is_none() {
+                    conn.served_file = Some(canonical_path.clone());
+                }
+                if self.generate_md5 && req.method == Some(HttpMethod::GET) {
+                    self.generate_md5_sidecar(&canonical_path);
+                }
+                if self.content_md5 && metadata.is_file() {
+                    content_md5 = file_content_md5(&canonical_path, &metadata);
+                }
+                // A mapping is only as long as the file was when it was mapped, which may not be
+                // the length it had when it was looked up.
+                let (data, len) = if self.mmap && metadata.is_file() {
+                    let map = MappedFile::new(file)?;
+                    let len = map.len();
+                    (ResponseDataType::Mapped(map), len)
+                } else if metadata.is_file() {
+                    (ResponseDataType::File(file), metadata.len() as usize)
+                } else {
+                    (ResponseDataType::File(file), std::u32::MAX as usize)
+                };
+                let mime = if let Some(content_type) = self.content_type_override(&req.path) {
+                    Some(content_type)
+                } else if req.path.ends_with(".html")
+                    || (serving_index && self.index_file.ends_with(".html"))
+                {
+                    Some(self.html_content_type.as_str())
                 } else {
                     None
+                };
+                (data, len, mime, true)
+            };
+
+        let mut result =
+            self.create_ranged_response(req, response_data, full_length, mime, ranges_allowed)?;
+        if let HttpResult::Response(resp, _) = &mut result {
+            if serving_index {
+                // Which index is served depends on the client's languages.
+                resp.add_vary("Accept-Language");
+            } else if metadata.is_dir() {
+                // Listings may be sent as plain text, depending on what the client accepts.
+                resp.add_vary("Accept");
+            }
+            if let Some((etag, modified)) = validators {
+                resp.add_header("ETag".to_string(), etag);
+                resp.add_header("Last-Modified".to_string(), timestamp::http_date(modified));
+            }
+            if let Some(lang) = index_language {
+                resp.add_header("Content-Language".to_string(), lang);
+            }
+            // The digest is of the file as stored, so it doesn't belong on a partial or
+            // compressed response.
+            if let Some(content_md5) = content_md5 {
+                if resp.get_status() == HttpStatus::OK
+                    && resp.get_header("Content-Encoding").is_none()
+                {
+                    resp.add_header("Content-MD5".to_string(), content_md5);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    // Headers added to every response, successful or not
+    fn add_common_headers(&self, conn: &mut HttpConnection, resp: &mut HttpResponse) {
+        resp.add_header("X-Request-Id".to_string(), self.request_id(conn));
+        if let (Some(cors), Some(origin)) = (&self.cors, &conn.origin) {
+            cors.add_headers(origin, resp);
+        }
+        for (name, value) in &self.extra_headers {
+            resp.add_header(name.clone(), value.clone());
+        }
+    }
+
+    fn new_request_id(&self) -> String {
+        let count = self.request_count.get();
+        self.request_count.set(count + 1);
+        format!("{}-{:x}", self.request_id_prefix, count)
+    }
+
+    // The id of the connection's current request. Responses sent before a request could be
+    // parsed get a fresh one.
+    fn request_id(&self, conn: &mut HttpConnection) -> String {
+        if conn.request_id.is_none() {
+            conn.request_id = Some(self.new_request_id());
+        }
+        conn.request_id.clone().unwrap()
+    }
+
+    // The index into `vhosts` of the root that should serve `req`, based on its Host header, or
+    // None for the default root.
+    fn select_vhost(&self, req: &HttpRequest) -> Option<usize> {
+        let host = host_name(req.get_header("host")?);
+        self.vhosts.iter().position(|(name, _)| *name == host)
+    }
+
+    fn root_for(&self, conn: &HttpConnection) -> &Path {
+        match conn.vhost {
+            Some(i) => &self.vhosts[i].1,
+            None => self.root_dir,
+        }
+    }
+
+    // Roots to look in for the connection's request, highest priority first. Overlays only
+    // apply to the default root.
+    fn roots_for(&self, conn: &HttpConnection) -> Vec<&Path> {
+        let mut roots: Vec<&Path> = Vec::new();
+        if conn.vhost.is_none() {
+            roots.extend(self.overlays.iter().map(|o| o.as_path()));
+        }
+        roots.push(self.root_for(conn));
+        roots
+    }
+
+    // The first root that has something at `relative`, or the base root if none does.
+    fn find_root(&self, conn: &HttpConnection, relative: &Path) -> &Path {
+        let roots = self.roots_for(conn);
+        let base = roots[roots.len() - 1];
+        roots
+            .into_iter()
+            .find(|root| root.join(relative).exists())
+            .unwrap_or(base)
+    }
+
+    // `relative` in every root where it is a directory, highest priority first. Each is checked
+    // against its own root.
+    fn directories_at(&self, conn: &HttpConnection, relative: &Path) -> Vec<PathBuf> {
+        self.roots_for(conn)
+            .into_iter()
+            .filter_map(
+                |root| match get_and_check_canon_path(root, root.join(relative)) {
+                    Ok(Some(path)) if path.is_dir() => Some(path),
+                    _ => None,
                 },
             )
+            .collect()
+    }
+
+    // A listing of `dirs` as plain text, for clients that ask for one
+    fn render_text_listing(&self, dirs: &[PathBuf]) -> String {
+        rendering::render_directory_text(dirs, self.listing_sort, self.hide)
+    }
+
+    // The Content-Type forced by the first --content-type rule matching `path`, if any
+    fn content_type_override(&self, path: &str) -> Option<&str> {
+        self.content_types
+            .iter()
+            .find(|rule| rule.matches(path))
+            .map(|rule| rule.content_type.as_str())
+    }
+
+    // Starts computing a .md5sum sidecar for `path` in the background if it doesn't have one.
+    // Each file is only tried once, so a directory we can't write to isn't retried on every
+    // request.
+    fn generate_md5_sidecar(&self, path: &Path) {
+        let file_name = match path.file_name() {
+            Some(name) => name,
+            None => return,
+        };
+        if path.extension().map_or(false, |ext| ext == "md5sum") {
+            return;
+        }
+        let mut sidecar_name = file_name.to_os_string();
+        sidecar_name.push(".md5sum");
+        let sidecar = path.with_file_name(sidecar_name);
+        if sidecar.exists() || !self.md5_started.borrow_mut().insert(path.to_path_buf()) {
+            return;
+        }
+        if let Some(dir) = path.parent() {
+            if unistd::access(dir, unistd::AccessFlags::W_OK).is_err() {
+                return;
+            }
+        }
+
+        let path = path.to_path_buf();
+        let history_channel = self.history_channel.clone();
+        let log_format = self.log_format;
+        thread::spawn(move || {
+            if let Err(e) = md5::write_sidecar(&path, &sidecar) {
+                let message = format!("Could not write {}: {}", sidecar.display(), e);
+                let _ = history_channel.send(format_message(log_format, message));
+            }
+        });
+    }
+
+    fn create_zip_response(
+        &self,
+        req: &HttpRequest,
+        dir_path: &Path,
+    ) -> Result<HttpResult, io::Error> {
+        if !self.features.dir_listings {
+            return Ok(HttpResult::Error(
+                HttpStatus::PermissionDenied,
+                Some(format!("Unable to list this directory.")),
+            ));
+        }
+
+        let zip = match ZipStream::new(dir_path, self.hide) {
+            Ok(zip) => zip,
+            Err(error) => {
+                return Ok(HttpResult::Error(
+                    resolve_io_error(&error).unwrap_or(HttpStatus::ServerError),
+                    Some(error.to_string()),
+                ));
+            }
+        };
+
+        let name = match dir_path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.replace('"', "_"),
+            None => "hypershare".to_string(),
+        };
+
+        // The archive is generated as it is sent, so it can't be seeked into. Range requests get
+        // the whole archive rather than a part that wouldn't unzip.
+        let len = zip.len();
+        let mut resp = HttpResponse::new(HttpStatus::OK, &req.version);
+        resp.add_header("Server".to_string(), "hypershare".to_string());
+        resp.add_header("Accept-Ranges".to_string(), "none".to_string());
+        resp.add_header("Content-Type".to_string(), "application/zip".to_string());
+        resp.add_header(
+            "Content-Disposition".to_string(),
+            format!("attachment; filename=\"{}.zip\"", name),
+        );
+        resp.set_content_length(len);
+        resp.add_body(ResponseDataType::Zip(zip));
+
+        Ok(HttpResult::Response(resp, len))
+    }
+
+    // A FIFO has no length and can't be seeked into, so whatever is written to it is sent as it
+    // arrives until the writer closes it. HTTP/1.0 clients can't take chunks, so they read until
+    // the connection closes instead.
+    fn create_fifo_response(
+        &self,
+        req: &HttpRequest,
+        conn: &mut HttpConnection,
+        path: &Path,
+    ) -> Result<HttpResult, io::Error> {
+        let mut resp = HttpResponse::new(HttpStatus::OK, &req.version);
+        resp.add_header("Server".to_string(), "hypershare".to_string());
+        resp.add_header("Accept-Ranges".to_string(), "none".to_string());
+        if let Some(content_type) = self.content_type_override(&req.path) {
+            resp.add_header("Content-Type".to_string(), content_type.to_string());
+        }
+        if req.version == HttpVersion::Http1_1 {
+            resp.set_chunked();
+        } else {
+            conn.keep_alive = false;
+        }
+        // Opening for HEAD would only take a writer's attention away from a real reader.
+        if req.method == Some(HttpMethod::GET) {
+            let fifo = OpenOptions::new()
+                .read(true)
+                .custom_flags(O_NONBLOCK)
+                .open(path)?;
+            resp.add_body(ResponseDataType::Stream(fifo));
+        }
+        Ok(HttpResult::Response(resp, 0))
+    }
+
+    // Lists everything under the root whose name contains the `q` query parameter.
+    fn handle_search(
+        &self,
+        req: &HttpRequest,
+        conn: &HttpConnection,
+    ) -> Result<HttpResult, io::Error> {
+        if !self.features.dir_listings {
+            return Ok(HttpResult::Error(
+                HttpStatus::PermissionDenied,
+                Some(format!(
+                    "Searching is disabled along with directory listings."
+                )),
+            ));
+        }
+        let term = match req.get_query_param("q") {
+            Some(term) if !term.is_empty() => term,
+            _ => {
+                return Ok(HttpResult::Error(
+                    HttpStatus::BadRequest,
+                    Some(format!(
+                        "Expected a search term, as in {}?q=term",
+                        SEARCH_PATH
+                    )),
+                ));
+            }
         };
+        let roots = self.directories_at(conn, Path::new(""));
+        let s = rendering::render_search(&roots, &term, self.listing_sort, self.hide);
+        let len = s.len();
+        let data = ResponseDataType::String(SeekableString::new(s));
+        self.create_ranged_response(req, data, len, Some("text/html; charset=utf-8"), false)
+    }
+
+    // The encodings responses may be compressed with, in the order they're preferred when a
+    // client likes them equally.
+    fn offered_encodings(&self) -> Vec<Encoding> {
+        [Encoding::Gzip, Encoding::Brotli]
+            .iter()
+            .cloned()
+            .filter(|&encoding| self.compression_level(encoding).is_some())
+            .collect()
+    }
 
-        let (start, range, used_range) = match req.get_header("range") {
+    fn compression_level(&self, encoding: Encoding) -> Option<u32> {
+        match encoding {
+            Encoding::Gzip => self.gzip_level,
+            Encoding::Brotli => self.brotli_level,
+        }
+    }
+
+    fn create_ranged_response(
+        &self,
+        req: &HttpRequest,
+        mut response_data: ResponseDataType,
+        full_length: usize,
+        mime: Option<&str>,
+        ranges_allowed: bool,
+    ) -> Result<HttpResult, io::Error> {
+        let range_header = if ranges_allowed {
+            req.get_header("range")
+        } else {
+            None
+        };
+        let (start, range, used_range) = match range_header {
             Some(content_range_str) => {
-                if let Some(content_range) = decode_content_range(content_range_str) {
+                if let Some(content_range) = decode_content_range(content_range_str, full_length) {
+                    if content_range.start >= full_length {
+                        // Nothing to send; say how long the resource actually is.
+                        let mut resp =
+                            HttpResponse::new(HttpStatus::RangeNotSatisfiable, &req.version);
+                        resp.add_header("Server".to_string(), "hypershare".to_string());
+                        resp.add_header(
+                            "Content-Range".to_string(),
+                            format!("bytes */{}", full_length),
+                        );
+                        resp.set_content_length(0);
+                        return Ok(HttpResult::Response(resp, 0));
+                    }
                     let real_start = min(content_range.start, full_length);
                     let real_len = match content_range.len {
                         Some(len) => min(len, full_length - real_start),
@@ -690,6 +2384,41 @@ impl HttpTui<'_> {
             None => (0, full_length, false),
         };
 
+        // Whether a client that accepts one of the enabled encodings gets this response
+        // compressed. If so, what is sent depends on Accept-Encoding.
+        let offered = self.offered_encodings();
+        let compressible = !used_range
+            && full_length >= self.gzip_min_size
+            && full_length <= COMPRESSION_MAX_SIZE
+            && !offered.is_empty()
+            && worth_compressing(&req.path, mime);
+        let encoding = if compressible {
+            content_encoding::preferred_encoding(req.get_header("accept-encoding"), &offered)
+        } else {
+            None
+        };
+        // HEAD responses are compressed too, as their Content-Length has to match a GET's. The
+        // work is bounded by COMPRESSION_MAX_SIZE.
+        let encoded = match encoding {
+            Some(encoding) => {
+                let mut level = self.compression_level(encoding).unwrap_or(0);
+                if encoding == Encoding::Brotli && full_length > BROTLI_SLOW_LEVELS_MAX_SIZE {
+                    level = min(level, BROTLI_LARGE_BODY_LEVEL);
+                }
+                compress_response_data(&mut response_data, encoding, level)?
+                    .map(|data| (encoding, data))
+            }
+            None => None,
+        };
+        let (range, encoding) = match encoded {
+            Some((encoding, data)) => {
+                let len = data.len();
+                response_data = ResponseDataType::Bytes(io::Cursor::new(data));
+                (len, Some(encoding))
+            }
+            None => (range, None),
+        };
+
         let mut resp = HttpResponse::new(
             if used_range {
                 HttpStatus::PartialContent
@@ -700,19 +2429,24 @@ impl HttpTui<'_> {
         );
 
         resp.add_header("Server".to_string(), "hypershare".to_string());
-        resp.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+        resp.add_header(
+            "Accept-Ranges".to_string(),
+            if ranges_allowed { "bytes" } else { "none" }.to_string(),
+        );
 
         resp.set_content_length(range);
 
+        if compressible {
+            resp.add_vary("Accept-Encoding");
+        }
+        if let Some(encoding) = encoding {
+            resp.add_header("Content-Encoding".to_string(), encoding.name().to_string());
+        }
+
         if used_range {
             resp.add_header(
                 "Content-Range".to_string(),
-                format!(
-                    "bytes {}-{}/{}",
-                    start,
-                    max(start, start + range - 1),
-                    full_length
-                ),
+                format!("bytes {}-{}/{}", start, start + range - 1, full_length),
             );
             match response_data {
                 ResponseDataType::String(ref mut seg) => {
@@ -721,6 +2455,15 @@ impl HttpTui<'_> {
                 ResponseDataType::File(ref mut file) => {
                     file.seek(io::SeekFrom::Start((start) as u64))?;
                 }
+                ResponseDataType::Mapped(ref mut map) => {
+                    map.seek(io::SeekFrom::Start((start) as u64))?;
+                }
+                ResponseDataType::Bytes(ref mut bytes) => {
+                    bytes.seek(io::SeekFrom::Start((start) as u64))?;
+                }
+                ResponseDataType::Shared(ref mut shared) => {
+                    shared.seek(io::SeekFrom::Start((start) as u64))?;
+                }
                 _ => {}
             }
         }
@@ -741,42 +2484,132 @@ impl HttpTui<'_> {
     ) -> Result<ConnectionState, io::Error> {
         let head = &mut conn.buffer[..conn.body_start_location];
         conn.num_requests += 1;
+        self.metrics.count_request();
+        conn.request_start = Some(time::Instant::now());
+        conn.request_bytes_start = conn.bytes_sent;
+        conn.request_id = Some(self.new_request_id());
+
+        let req: HttpRequest = match decode_request(head) {
+            Ok(r) => r,
+            Err(status) => {
+                // Kill the connection if we get invalid data
+                conn.keep_alive = false;
+                return self.create_oneoff_response(
+                    status,
+                    conn,
+                    Some("Could not decode request.".to_string()),
+                );
+            }
+        };
+
+        conn.last_requested_uri = Some(req.path.to_string());
+        conn.last_requested_method = req.method.clone();
+        conn.vhost = self.select_vhost(&req);
+        conn.origin = req.get_header("origin").cloned();
+        if let Some(id) = req.get_header("x-request-id") {
+            if is_valid_request_id(id) {
+                conn.request_id = Some(id.to_string());
+            }
+        }
+
+        // HTTP/1.1 clients must say which host they want (RFC 7230 section 5.4).
+        if req.version == HttpVersion::Http1_1 && req.get_header("host").is_none() {
+            conn.keep_alive = false;
+            return self.create_oneoff_response(
+                HttpStatus::BadRequest,
+                conn,
+                Some("HTTP/1.1 requests must have a Host header.".to_string()),
+            );
+        }
+
+        let is_upload = match req.method {
+            Some(HttpMethod::POST) | Some(HttpMethod::PUT) => true,
+            _ => false,
+        };
+        let paused_message = match (self.serving_mode.reads, self.serving_mode.uploads) {
+            (false, false) => Some(
+                "This server has been temporarily disabled. Please contact the administrator to \
+                 re-enable it.",
+            ),
+            (false, true) if !is_upload => Some(
+                "Downloads from this server have been temporarily paused. Please contact the \
+                 administrator to resume them.",
+            ),
+            (true, false) if is_upload => Some(
+                "Uploads to this server have been temporarily paused. Please contact the \
+                 administrator to resume them.",
+            ),
+            _ => None,
+        };
+        if let Some(message) = paused_message {
+            conn.keep_alive = false;
+            return self.create_oneoff_response(
+                HttpStatus::ServiceUnavailable,
+                conn,
+                Some(message.to_string()),
+            );
+        }
+
+        // Check if keep-alive header was given in the request.
+        // If it was not, assume keep-alive is >= HTTP/1.1.
+        conn.keep_alive = match req.get_header("connection") {
+            Some(value) => value.to_lowercase() == "keep-alive",
+            None => false,
+        } && !self.no_keepalive;
+
+        if ambiguous_body_length(&req) {
+            conn.keep_alive = false;
+            return self.create_oneoff_response(
+                HttpStatus::BadRequest,
+                conn,
+                Some("Conflicting Content-Length or Transfer-Encoding headers.".to_string()),
+            );
+        }
 
-        let req: HttpRequest = match decode_request(head) {
-            Ok(r) => r,
-            Err(status) => {
-                // Kill the connection if we get invalid data
+        // Methods that don't take a body may still be sent one. Unless a handler below reads it,
+        // skip over it so the next request on this connection doesn't start mid-body.
+        if !self.skip_request_body(&req, conn) {
+            conn.keep_alive = false;
+        }
+
+        // 100-continue is the only expectation we know how to meet.
+        if let Some(expectation) = req.get_header("expect") {
+            if !expects_continue(&req) {
+                // The client may still send a body we won't read, so don't reuse the connection.
                 conn.keep_alive = false;
                 return self.create_oneoff_response(
-                    status,
+                    HttpStatus::ExpectationFailed,
                     conn,
-                    Some("Could not decode request.".to_string()),
+                    Some(format!("Unsupported expectation: {}", expectation)),
                 );
             }
-        };
+        }
 
-        conn.last_requested_uri = Some(req.path.to_string());
-        conn.last_requested_method = req.method.clone();
+        // The system calls a path is passed to would take a NUL as its end.
+        if req.path.contains('\0') {
+            return self.create_oneoff_response(
+                HttpStatus::BadRequest,
+                conn,
+                Some("Request path contains a NUL byte.".to_string()),
+            );
+        }
 
-        if self.disabled {
-            conn.keep_alive = false;
+        if self.deny_dotdot_encoded && !is_path_free_of_traversal(&req.path) {
             return self.create_oneoff_response(
-                HttpStatus::ServiceUnavailable,
+                HttpStatus::BadRequest,
                 conn,
-                Some(
-                    "This server has been temporarily disabled. Please contact the administrator \
-                     to re-enable it."
-                        .to_string(),
-                ),
+                Some("Request path contains a traversal or percent-encoded sequence.".to_string()),
             );
         }
 
-        // Check if keep-alive header was given in the request.
-        // If it was not, assume keep-alive is >= HTTP/1.1.
-        conn.keep_alive = match req.get_header("connection") {
-            Some(value) => value.to_lowercase() == "keep-alive",
-            None => false,
-        };
+        // Virtual hosts' roots aren't watched; a missing one just results in 404s.
+        if conn.vhost.is_none() && !self.check_root_available() {
+            return self.create_oneoff_response(
+                HttpStatus::ServiceUnavailable,
+                conn,
+                Some("Server root unavailable.".to_string()),
+            );
+        }
 
         let maybe_result = match req.method {
             None => {
@@ -786,9 +2619,12 @@ impl HttpTui<'_> {
                     Some("This server does not implement the requested HTTP method.".to_string()),
                 );
             }
-            Some(HttpMethod::GET) => self.handle_get(&req),
-            Some(HttpMethod::HEAD) => self.handle_get(&req),
+            Some(HttpMethod::GET) => self.handle_get(&req, conn),
+            Some(HttpMethod::HEAD) => self.handle_get(&req, conn),
             Some(HttpMethod::POST) => self.handle_post(&req, conn),
+            Some(HttpMethod::PUT) => self.handle_put(&req, conn),
+            Some(HttpMethod::OPTIONS) => self.handle_options(&req, conn),
+            Some(HttpMethod::PROPFIND) => self.handle_propfind(&req, conn),
         };
         let result = match maybe_result {
             // Attempt to convert the system error into an HTTP error
@@ -806,12 +2642,19 @@ impl HttpTui<'_> {
             HttpResult::Error(http_status, msg) => {
                 return self.create_oneoff_response(http_status, conn, msg);
             }
+            HttpResult::ErrorWithHeaders(http_status, msg, headers) => {
+                return self.create_oneoff_response_with_headers(http_status, conn, msg, headers);
+            }
             HttpResult::ReadRequestBody => {
+                if conn.put_buffer.is_some() {
+                    return self.check_partial_put_body_initial(&req, conn);
+                }
                 return self.check_partial_post_body_initial(&req, conn);
             }
             HttpResult::Response(resp, range) => (resp, range),
         };
 
+        self.add_common_headers(conn, &mut resp);
         resp.add_header(
             "Connection".to_string(),
             if conn.keep_alive {
@@ -824,17 +2667,80 @@ impl HttpTui<'_> {
         // Write headers
         resp.write_headers_to_stream(&conn.stream)?;
 
-        // If method is HEAD, remove the response body
+        // If method is HEAD, remove the response body. The headers, including Content-Length and
+        // any Content-Range, still describe what a GET would have returned.
         if req.method.unwrap_or(HttpMethod::HEAD) == HttpMethod::HEAD {
             resp.clear_body();
+            // Nothing is going to be sent, so this is neither a download nor owed any bytes.
+            conn.served_file = None;
+        } else {
+            conn.bytes_requested += range;
         }
 
         conn.response = Some(resp);
-        conn.bytes_requested += range;
 
         Ok(ConnectionState::WritingResponse)
     }
 
+    // Arranges for the body of `req` to be skipped. Returns false if the body's length can't be
+    // determined, or if more of it is left than --max-discard-bytes, in which case the connection
+    // can't be reused.
+    fn skip_request_body(&self, req: &HttpRequest, conn: &mut HttpConnection) -> bool {
+        if req.get_header("transfer-encoding").is_some() {
+            return false;
+        }
+        let len: usize = match req.get_header("content-length") {
+            Some(len) => match len.trim().parse() {
+                Ok(len) => len,
+                _ => return false,
+            },
+            None => {
+                conn.request_end = conn.body_start_location;
+                return true;
+            }
+        };
+        let buffered = min(len, conn.bytes_read - conn.body_start_location);
+        if self.max_discard_bytes > 0 && len - buffered > self.max_discard_bytes {
+            return false;
+        }
+        conn.request_end = conn.body_start_location + buffered;
+        conn.discard_bytes = len - buffered;
+        true
+    }
+
+    // The root directory may be removed (and recreated) while we're running. Log when that
+    // changes rather than on every failed request.
+    fn check_root_available(&self) -> bool {
+        let available = self.root_dir.is_dir();
+        if available == self.root_missing.get() {
+            self.root_missing.set(!available);
+            self.log_message(if available {
+                format!(
+                    "Server root is available again: {}",
+                    self.root_dir.display()
+                )
+            } else {
+                format!(
+                    "Warning: server root is unavailable: {}",
+                    self.root_dir.display()
+                )
+            });
+        }
+        available
+    }
+
+    fn report_upload(&self, stream: &TcpStream, root_dir: &Path, path: &Path, size: usize) {
+        self.metrics.count_upload();
+        if let Some(sender) = &self.upload_channel {
+            let relative = path.strip_prefix(root_dir).unwrap_or(path);
+            let _ = sender.send(CompletedUpload {
+                path: format!("/{}", relative.display()),
+                size: size,
+                addr: stream.peer_addr().ok(),
+            });
+        }
+    }
+
     fn write_continue(&self, conn: &mut HttpConnection) -> Result<(), io::Error> {
         let mut resp = HttpResponse::new(HttpStatus::Continue, &HttpVersion::Http1_1);
         resp.write_headers_to_stream(&conn.stream)?;
@@ -846,11 +2752,23 @@ impl HttpTui<'_> {
         conn: &mut HttpConnection,
     ) -> Result<ConnectionState, io::Error> {
         let done = self.write_partial_response(conn)?;
+        if done && conn.response_aborted() {
+            // The body ended early, e.g. because the file shrank. The client will be waiting for
+            // the rest, so the connection can only be closed. The transfer is logged then.
+            return Ok(ConnectionState::Closing);
+        }
         if done {
+            self.write_transfer_end_to_history(conn);
+            self.write_conn_to_audit_log(conn);
+            self.write_slow_request_to_history(conn);
+            if self.log_format == LogFormat::Json {
+                self.write_conn_to_history(conn);
+            }
             if conn.keep_alive {
                 // Reset the data associated with this connection
                 conn.reset();
-                return Ok(ConnectionState::ReadingRequest);
+                // A pipelined request may already be waiting in the buffer.
+                return self.handle_buffered_request(conn);
             } else {
                 return Ok(ConnectionState::Closing);
             }
@@ -862,10 +2780,30 @@ impl HttpTui<'_> {
     fn write_partial_response(&self, conn: &mut HttpConnection) -> Result<bool, io::Error> {
         Ok(match &mut conn.response {
             Some(ref mut resp) => {
-                let amt_written = resp.partial_write_to_stream(&conn.stream)?;
+                // Another connection may have used up the allowance earlier in this pass.
+                let max = match &self.rate_limiter {
+                    Some(limiter) if !limiter.can_send() => return Ok(false),
+                    Some(limiter) => limiter.available(),
+                    None => usize::MAX,
+                };
+                let amt_written = resp.partial_write_to_stream(&conn.stream, max)?;
+                if let Some(limiter) = &self.rate_limiter {
+                    limiter.spend(amt_written);
+                }
                 conn.bytes_sent += amt_written;
-                // If we wrote nothing, we are done
-                amt_written == 0 || conn.bytes_sent >= conn.bytes_requested
+                self.metrics.count_bytes_sent(amt_written);
+                // If we wrote nothing, we are done, unless a stream just has nothing to send yet
+                let done = if resp.is_streaming() {
+                    resp.is_complete()
+                } else {
+                    amt_written == 0 || conn.bytes_sent >= conn.bytes_requested
+                };
+                if done && conn.bytes_sent >= conn.bytes_requested {
+                    if let Some(path) = conn.served_file.take() {
+                        *self.hits.borrow_mut().entry(path).or_insert(0) += 1;
+                    }
+                }
+                done
             }
             None => true,
         })
@@ -896,9 +2834,7 @@ impl HttpTui<'_> {
     ) -> Result<ConnectionState, io::Error> {
         let pb = &mut conn.post_buffer.as_mut().unwrap();
 
-        if req.version == HttpVersion::Http1_1
-            && req.get_header("expect").unwrap_or(&"".to_string()) == "100-continue"
-        {
+        if req.version == HttpVersion::Http1_1 && expects_continue(req) {
             // Call handle_new_data directly so that errors are not
             // suppressed.
             match pb.handle_new_data() {
@@ -935,10 +2871,14 @@ impl HttpTui<'_> {
         &self,
         conn: &mut HttpConnection,
     ) -> Result<ConnectionState, io::Error> {
+        let root_dir = self.root_for(conn);
         let pb = &mut conn.post_buffer.as_mut().unwrap();
         match pb.handle_new_data_queue_error() {
             Ok(done) => {
                 if done {
+                    for (path, size) in pb.get_completed_files() {
+                        self.report_upload(&conn.stream, root_dir, path, *size);
+                    }
                     self.create_oneoff_response(
                         HttpStatus::Created,
                         conn,
@@ -984,7 +2924,7 @@ impl HttpTui<'_> {
                     conn,
                     Some("An error occurred while receiving your file.".to_string()),
                 );
-                let _ = self.write_conn_to_history(conn);
+                self.write_upload_to_history(conn);
                 return res;
             }
 
@@ -992,7 +2932,7 @@ impl HttpTui<'_> {
             match res {
                 Ok(ConnectionState::ReadingPostBody) => {}
                 _ => {
-                    let _ = self.write_conn_to_history(conn);
+                    self.write_upload_to_history(conn);
                 }
             };
 
@@ -1006,6 +2946,187 @@ impl HttpTui<'_> {
         }
     }
 
+    fn check_partial_put_body_initial(
+        &self,
+        req: &HttpRequest,
+        conn: &mut HttpConnection,
+    ) -> Result<ConnectionState, io::Error> {
+        if let Some(state) = self.write_put_buffer(conn)? {
+            return Ok(state);
+        }
+        if conn.put_buffer.as_ref().unwrap().is_done() {
+            return self.finish_put(conn);
+        }
+
+        if req.version == HttpVersion::Http1_1 && expects_continue(req) {
+            self.write_continue(conn)?;
+        }
+
+        Ok(ConnectionState::ReadingPutBody)
+    }
+
+    fn read_partial_put_body(
+        &self,
+        conn: &mut HttpConnection,
+    ) -> Result<ConnectionState, io::Error> {
+        let pb = match &mut conn.put_buffer {
+            Some(pb) => pb,
+            None => {
+                return self.create_oneoff_response(
+                    HttpStatus::ServerError,
+                    conn,
+                    Some("Attempt to read PUT contents without a buffer.".to_string()),
+                );
+            }
+        };
+
+        let bytes_read = match pb.read_into_buffer(&mut conn.stream) {
+            Ok(size) => size,
+            Err(_err) => {
+                // As with POST, problems with the socket itself are not reported to the client.
+                return Ok(ConnectionState::Closing);
+            }
+        };
+        conn.bytes_read += bytes_read;
+
+        if bytes_read == 0 {
+            let res = self.create_oneoff_response(
+                HttpStatus::BadRequest,
+                conn,
+                Some("An error occurred while receiving your file.".to_string()),
+            );
+            self.write_upload_to_history(conn);
+            self.abandon_put(conn);
+            return res;
+        }
+
+        if let Some(state) = self.write_put_buffer(conn)? {
+            return Ok(state);
+        }
+
+        if !conn.put_buffer.as_ref().unwrap().is_done() {
+            return Ok(ConnectionState::ReadingPutBody);
+        }
+
+        let res = self.finish_put(conn);
+        self.write_upload_to_history(conn);
+        res
+    }
+
+    // Writes what has been read of a PUT body to its file. Returns the error response if that
+    // fails.
+    fn write_put_buffer(
+        &self,
+        conn: &mut HttpConnection,
+    ) -> Result<Option<ConnectionState>, io::Error> {
+        let pb = conn.put_buffer.as_mut().unwrap();
+        let error = match pb.write_buffer_to_file() {
+            Ok(()) => return Ok(None),
+            Err(error) => error,
+        };
+
+        let status = if pb.is_over_limit() {
+            HttpStatus::PayloadTooLarge
+        } else if error.kind() == io::ErrorKind::InvalidData {
+            HttpStatus::BadRequest
+        } else {
+            HttpStatus::ServerError
+        };
+        self.abandon_put(conn);
+        conn.keep_alive = false;
+        self.create_oneoff_response(
+            status,
+            conn,
+            Some(format!("Error writing to file: {}", error)),
+        )
+        .map(Some)
+    }
+
+    // Gives up on a PUT whose body won't be written in full, so that it can be sent again. The
+    // truncated file is removed, unless it holds other pieces of a resumable upload.
+    fn abandon_put(&self, conn: &mut HttpConnection) {
+        let pb = match conn.put_buffer.take() {
+            Some(pb) => pb,
+            None => return,
+        };
+        let path = pb.get_path();
+        let range = match pb.get_range() {
+            Some(range) => range,
+            None => {
+                let _ = fs::remove_file(path);
+                return;
+            }
+        };
+        let mut partial_uploads = self.partial_uploads.borrow_mut();
+        let nothing_received = match partial_uploads.get_mut(path) {
+            Some(received) => {
+                received.abandon(&range);
+                received.is_empty()
+            }
+            None => false,
+        };
+        if nothing_received {
+            partial_uploads.remove(path);
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // Removes resumable uploads that haven't been added to in a long time, along with what was
+    // received of them.
+    fn expire_partial_uploads(&self) {
+        let max_idle = time::Duration::from_secs(PARTIAL_UPLOAD_MAX_IDLE_SECS);
+        self.partial_uploads.borrow_mut().retain(|path, received| {
+            if !received.is_stale(max_idle) {
+                return true;
+            }
+            let _ = fs::remove_file(path);
+            self.log_message(format!("Removed unfinished upload {}", path.display()));
+            false
+        });
+    }
+
+    fn finish_put(&self, conn: &mut HttpConnection) -> Result<ConnectionState, io::Error> {
+        let root_dir = self.root_for(conn);
+        let pb = conn.put_buffer.as_ref().unwrap();
+        let range = match pb.get_range() {
+            Some(range) => range,
+            None => {
+                self.report_upload(&conn.stream, root_dir, pb.get_path(), pb.written());
+                return self.create_oneoff_response(
+                    HttpStatus::Created,
+                    conn,
+                    Some(format!("File received.")),
+                );
+            }
+        };
+
+        let path = pb.get_path().to_path_buf();
+        let mut partial_uploads = self.partial_uploads.borrow_mut();
+        let received = partial_uploads
+            .entry(path.clone())
+            .or_insert(ReceivedRanges::new(range.total));
+        received.insert(&range);
+
+        if received.is_complete() {
+            partial_uploads.remove(&path);
+            self.report_upload(&conn.stream, root_dir, &path, range.total);
+            return self.create_oneoff_response(
+                HttpStatus::Created,
+                conn,
+                Some(format!("File received.")),
+            );
+        }
+
+        let received_header = received.to_header();
+        drop(partial_uploads);
+        self.create_oneoff_response_with_headers(
+            HttpStatus::ResumeIncomplete,
+            conn,
+            Some(format!("Received bytes {}-{}.", range.start, range.end)),
+            vec![("Range".to_string(), received_header)],
+        )
+    }
+
     fn handle_conn(&self, conn: &mut HttpConnection) -> Result<(), io::Error> {
         match conn.state {
             ConnectionState::ReadingRequest => {
@@ -1014,6 +3135,9 @@ impl HttpTui<'_> {
             ConnectionState::ReadingPostBody => {
                 conn.state = self.read_partial_post_body(conn)?;
             }
+            ConnectionState::ReadingPutBody => {
+                conn.state = self.read_partial_put_body(conn)?;
+            }
             ConnectionState::WritingResponse => {
                 conn.state = self.write_partial_final_response(conn)?;
             }
@@ -1024,14 +3148,31 @@ impl HttpTui<'_> {
     }
 
     fn create_oneoff_response(
+        &self,
+        status: HttpStatus,
+        conn: &mut HttpConnection,
+        msg: Option<String>,
+    ) -> Result<ConnectionState, io::Error> {
+        self.create_oneoff_response_with_headers(status, conn, msg, Vec::new())
+    }
+
+    fn create_oneoff_response_with_headers(
         &self,
         status: HttpStatus,
         mut conn: &mut HttpConnection,
         msg: Option<String>,
+        headers: Vec<(String, String)>,
     ) -> Result<ConnectionState, io::Error> {
-        let body: String = rendering::render_error(&status, msg);
+        if self.no_keepalive {
+            conn.keep_alive = false;
+        }
+        let body: String = rendering::render_error(&status, msg, self.error_template.as_deref());
         let mut resp = HttpResponse::new(status, &HttpVersion::Http1_1);
         resp.add_header("Server".to_string(), "hypershare".to_string());
+        self.add_common_headers(conn, &mut resp);
+        for (key, value) in headers {
+            resp.add_header(key, value);
+        }
 
         resp.set_content_length(body.len());
         resp.add_header(
@@ -1063,21 +3204,335 @@ impl HttpTui<'_> {
     }
 }
 
-fn get_post_boundary(req: &HttpRequest) -> Option<&str> {
+// The value of the boundary parameter in a request's Content-Type, with any quotes still on.
+fn boundary_param(req: &HttpRequest) -> Option<&str> {
     let ct = req.get_header("content-type")?;
     for segment in ct.split(";") {
         if segment.trim_start().starts_with("boundary=") {
-            let (_, inner) = segment.split_at(segment.find("=")?);
+            return Some(&segment[segment.find("=")? + 1..]);
+        }
+    }
+    None
+}
 
-            // Remove the surrounding quotes
-            if inner.starts_with("=\"") {
-                return Some(&inner[2..inner.len() - 1]);
-            }
+fn get_post_boundary(req: &HttpRequest) -> Option<&str> {
+    let inner = boundary_param(req)?;
 
-            return Some(&inner[1..]);
+    // Remove the surrounding quotes
+    if inner.len() >= 2 && inner.starts_with("\"") && inner.ends_with("\"") {
+        return Some(&inner[1..inner.len() - 1]);
+    }
+
+    Some(inner)
+}
+
+// Whether a boundary parameter is valid according to RFC 2046 section 5.1.1: 1 to 70 characters
+// from a limited set, not ending in a space. Boundaries using characters that aren't allowed in
+// a token (such as spaces) must be quoted.
+fn is_strict_boundary(param: &str) -> bool {
+    let (boundary, quoted) = if param.len() >= 2 && param.starts_with('"') && param.ends_with('"') {
+        (&param[1..param.len() - 1], true)
+    } else {
+        (param, false)
+    };
+    let allowed = |c: char| {
+        c.is_ascii_alphanumeric() || "'+_-.".contains(c) || (quoted && "()/,:=? ".contains(c))
+    };
+    (1..=70).contains(&boundary.len()) && !boundary.ends_with(' ') && boundary.chars().all(allowed)
+}
+
+// Returns false if the (already decoded) path still contains a '..' segment or something
+// that looks like a percent-encoded byte, which would only be there if the client encoded it
+// more than once.
+fn is_path_free_of_traversal(path: &str) -> bool {
+    lazy_static! {
+        static ref ENCODED: Regex = Regex::new("%[0-9a-fA-F]{2}").unwrap();
+    }
+    if ENCODED.is_match(path) {
+        return false;
+    }
+    !path.split('/').any(|segment| segment == "..")
+}
+
+// The Content-MD5 of a file, if it is cheap to come by: from a .md5sum sidecar that is at least
+// as new as the file, or by digesting the file if it is small.
+fn file_content_md5(path: &Path, metadata: &fs::Metadata) -> Option<String> {
+    let mut sidecar_name = path.file_name()?.to_os_string();
+    sidecar_name.push(".md5sum");
+    let sidecar = path.with_file_name(sidecar_name);
+    let sidecar_is_current = match (fs::metadata(&sidecar), metadata.modified()) {
+        (Ok(sidecar_meta), Ok(modified)) => {
+            sidecar_meta.modified().map_or(false, |m| m >= modified)
+        }
+        _ => false,
+    };
+    let digest = if sidecar_is_current {
+        md5::from_hex(&rendering::read_md5_sidecar(&sidecar)?)?
+    } else if metadata.len() <= CONTENT_MD5_COMPUTE_LIMIT {
+        md5::digest_file(path).ok()?
+    } else {
+        return None;
+    };
+    Some(md5::to_base64(&digest))
+}
+
+// The primary subtags of the languages in an Accept-Language header, most preferred first.
+// Wildcards and anything that isn't a plain language code are skipped.
+fn accepted_languages(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = Vec::new();
+    for item in header.split(',') {
+        let mut parts = item.split(';');
+        let tag = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .filter_map(|param| {
+                let param = param.trim();
+                if param.starts_with("q=") {
+                    param[2..].trim().parse::<f32>().ok()
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+
+        let primary = tag.split('-').next().unwrap_or("").to_lowercase();
+        if primary.len() == 0
+            || primary.len() > 8
+            || !primary.bytes().all(|b| b.is_ascii_alphabetic())
+            || quality <= 0.0
+            || languages.iter().any(|(lang, _)| *lang == primary)
+        {
+            continue;
         }
+        languages.push((primary, quality));
     }
-    None
+    // Stable, so equally preferred languages stay in the order they were given.
+    languages.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    languages.into_iter().map(|(lang, _)| lang).collect()
+}
+
+// A weak ETag and a Last-Modified time for a listing of `dirs`. They change when an entry is
+// added, removed or modified, or when the listing would be rendered differently.
+fn listing_validators(
+    dirs: &[PathBuf],
+    sort: rendering::ListingSort,
+    hide: &[String],
+    text: bool,
+    show_form: bool,
+) -> Option<(String, time::SystemTime)> {
+    let entries = rendering::listed_entries(dirs, sort, hide)?;
+    let mut modified = time::UNIX_EPOCH;
+    let dir_metadata = dirs.iter().filter_map(|dir| fs::metadata(dir).ok());
+    let entry_metadata = entries.iter().filter_map(|entry| entry.metadata().ok());
+    for meta in dir_metadata.chain(entry_metadata) {
+        if let Ok(time) = meta.modified() {
+            modified = max(modified, time);
+        }
+    }
+    let nanos = modified
+        .duration_since(time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let etag = format!(
+        "W/\"{:x}-{:x}{}{}\"",
+        nanos,
+        entries.len(),
+        if text { "-txt" } else { "" },
+        if show_form { "-form" } else { "" }
+    );
+    Some((etag, modified))
+}
+
+// Whether a conditional request can be answered with 304 Not Modified. If-None-Match takes
+// precedence over If-Modified-Since, and is compared weakly.
+fn is_not_modified(req: &HttpRequest, etag: &str, modified: time::SystemTime) -> bool {
+    if let Some(if_none_match) = req.get_header("if-none-match") {
+        let tag = etag.trim_start_matches("W/");
+        return if_none_match
+            .split(',')
+            .map(|t| t.trim())
+            .any(|t| t == "*" || t.trim_start_matches("W/") == tag);
+    }
+    match req
+        .get_header("if-modified-since")
+        .and_then(|date| timestamp::parse_http_date(date))
+    {
+        Some(since) => timestamp::unix_secs(modified) <= since,
+        None => false,
+    }
+}
+
+// The requested path without its leading '/', for links in listings. It is encoded again from
+// its decoded form rather than copied from the request, so whatever the client sent, it can't
+// break out of an attribute.
+fn href_path(req: &HttpRequest) -> String {
+    let mut path = rendering::encode_path(req.fs_path());
+    if req.path.ends_with('/') && !path.is_empty() {
+        path.push('/');
+    }
+    path
+}
+
+// Whether the client is waiting for 100 Continue before sending its body. Expectations are
+// case-insensitive (RFC 7231 section 5.1.1).
+fn expects_continue(req: &HttpRequest) -> bool {
+    req.get_header("expect").map_or(false, |value| {
+        value.trim().eq_ignore_ascii_case("100-continue")
+    })
+}
+
+// Whether a directory listing should be plain text rather than HTML: if asked for with
+// `?format=txt`, or if the client accepts plain text but not HTML.
+fn wants_text_listing(req: &HttpRequest) -> bool {
+    if let Some(format) = req.get_query_param("format") {
+        return format == "txt";
+    }
+    match req.get_header("accept") {
+        Some(accept) => {
+            let types: Vec<String> = accept
+                .split(',')
+                .map(|item| item.split(';').next().unwrap_or("").trim().to_lowercase())
+                .collect();
+            types.iter().any(|t| t == "text/plain") && !types.iter().any(|t| t == "text/html")
+        }
+        None => false,
+    }
+}
+
+// `mime` with a charset parameter, unless the charset is `none`.
+fn with_charset(mime: &str, charset: &str) -> String {
+    if charset.eq_ignore_ascii_case("none") {
+        mime.to_string()
+    } else {
+        format!("{}; charset={}", mime, charset)
+    }
+}
+
+// Whether a response for `path` of type `mime` is likely to get any smaller when compressed.
+// Most files are sent without a type, so their extension is all there is to go on.
+fn worth_compressing(path: &str, mime: Option<&str>) -> bool {
+    match mime {
+        Some(mime) => {
+            let essence = mime.split(';').next().unwrap_or("").trim().to_lowercase();
+            essence.starts_with("text/")
+                || essence.ends_with("+xml")
+                || essence.ends_with("+json")
+                || [
+                    "application/javascript",
+                    "application/json",
+                    "application/xml",
+                ]
+                .contains(&essence.as_str())
+        }
+        None => match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => !COMPRESSED_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+            None => true,
+        },
+    }
+}
+
+// Compresses a response body, if it is of a kind that can be.
+fn compress_response_data(
+    data: &mut ResponseDataType,
+    encoding: Encoding,
+    level: u32,
+) -> Result<Option<Vec<u8>>, io::Error> {
+    match data {
+        ResponseDataType::String(s) => content_encoding::encode(s, encoding, level).map(Some),
+        ResponseDataType::File(file) => content_encoding::encode(file, encoding, level).map(Some),
+        ResponseDataType::Mapped(map) => content_encoding::encode(map, encoding, level).map(Some),
+        ResponseDataType::Bytes(b) => content_encoding::encode(b, encoding, level).map(Some),
+        ResponseDataType::Shared(b) => content_encoding::encode(b, encoding, level).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Hidden paths get the same answer as ones that don't exist, so as not to give them away.
+fn hidden_path_error() -> HttpResult {
+    HttpResult::Error(
+        HttpStatus::NotFound,
+        Some(io::Error::from_raw_os_error(ENOENT).to_string()),
+    )
+}
+
+// `name` with a language inserted before its extension, e.g. index.fr.html.
+fn localized_name(name: &str, lang: &str) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{}.{}{}", &name[..dot], lang, &name[dot..]),
+        _ => format!("{}.{}", name, lang),
+    }
+}
+
+// The host part of a Host header, lowercased and without any port.
+fn host_name(header: &str) -> String {
+    let header = header.trim();
+    let host = if header.starts_with('[') {
+        // An IPv6 address, which has colons of its own
+        match header.find(']') {
+            Some(end) => &header[..end + 1],
+            None => header,
+        }
+    } else {
+        match header.rfind(':') {
+            Some(colon) => &header[..colon],
+            None => header,
+        }
+    };
+    host.to_lowercase()
+}
+
+// JSON logs get messages as objects of their own, so that every line of them can be parsed.
+fn format_message(log_format: LogFormat, message: String) -> String {
+    match log_format {
+        LogFormat::Text => message,
+        LogFormat::Json => message_json(&message),
+    }
+}
+
+// Whether the length of the request's body is ambiguous: Content-Length headers that disagree,
+// or one given along with Transfer-Encoding. Something in front of the server could frame such
+// a request differently, so it is refused rather than guessed at.
+fn ambiguous_body_length(req: &HttpRequest) -> bool {
+    let mut lengths = req
+        .get_header_values("content-length")
+        .map(|len| len.trim());
+    match lengths.next() {
+        Some(first) => {
+            req.get_header("transfer-encoding").is_some() || lengths.any(|len| len != first)
+        }
+        None => false,
+    }
+}
+
+// Inbound ids are echoed back in a header and logged, so only accept short, printable ones.
+fn is_valid_request_id(id: &str) -> bool {
+    id.len() > 0 && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+// Creates whatever is missing of the directory `path`, for --upload-mkdir. The part of it that
+// exists has to lead inside `root_dir`, and the rest may only name new directories, so nothing is
+// created outside of it. Anything else is left for get_and_check_canon_path to turn away.
+fn create_upload_dirs(root_dir: &Path, path: &Path) -> Result<(), io::Error> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while fs::symlink_metadata(existing).is_err() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            // Ends in `..`
+            _ => return Ok(()),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut dir = match get_and_check_canon_path(root_dir, existing.to_path_buf()) {
+        Ok(Some(dir)) => dir,
+        _ => return Ok(()),
+    };
+    dir.extend(missing.iter().rev());
+    fs::create_dir_all(dir)
 }
 
 fn get_and_check_canon_path(root_dir: &Path, path: PathBuf) -> Result<Option<PathBuf>, io::Error> {
@@ -1096,3 +3551,42 @@ fn get_and_check_canon_path(root_dir: &Path, path: PathBuf) -> Result<Option<Pat
 
     Ok(Some(canonical_path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_bytes_toggle_features() {
+        let mut features = Features {
+            dir_listings: true,
+            uploading: false,
+        };
+
+        features.toggle(b'd');
+        assert!(!features.dir_listings);
+        assert!(!features.uploading);
+
+        features.toggle(b'u');
+        assert!(!features.dir_listings);
+        assert!(features.uploading);
+
+        features.toggle(b'd');
+        features.toggle(b'u');
+        assert!(features.dir_listings);
+        assert!(!features.uploading);
+    }
+
+    #[test]
+    fn other_control_bytes_leave_features_alone() {
+        let mut features = Features {
+            dir_listings: true,
+            uploading: true,
+        };
+        for control in b"trwkisp" {
+            features.toggle(*control);
+        }
+        assert!(features.dir_listings);
+        assert!(features.uploading);
+    }
+}
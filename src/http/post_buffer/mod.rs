@@ -2,21 +2,98 @@ mod types;
 
 use types::PostBufferError;
 
-use crate::http::http_core::HttpStatus;
+use crate::http::http_core::{percent_decode, HttpStatus};
 
-use std::fs::{self, OpenOptions};
+use std::{
+    cmp::min,
+    fs::{self, OpenOptions},
+};
 
 use std::io::{self, Write};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use nix::errno::Errno;
 
 use core::ptr::copy;
 
 use boyer_moore_magiclen::BMByte;
 
-use crate::http::boyer_moore::{find_body_start, types::BMBuf};
+use crate::http::{
+    boyer_moore::{find_body_start, types::BMBuf},
+    content_encoding::GzipBody,
+};
+
+// Data is written out as it arrives, so the buffer only has to hold a part's headers, or a
+// delimiter that is split across reads. Boundaries come from the request headers, so they are
+// always much shorter than this.
+const POST_BUFFER_SIZE: usize = 256 * 1024;
+// A part's headers are a few short lines, so a block longer than this is rejected rather than
+// buffered.
+const POST_META_MAX_SIZE: usize = 8 * 1024;
+
+// Used to give every staged upload a unique name.
+static STAGED_UPLOADS: AtomicUsize = AtomicUsize::new(0);
+
+// Decodes an RFC 5987 value, charset'language'percent-encoded-text, as sent in filename*.
+// Only UTF-8 and ISO-8859-1 are understood, which are the two every sender has to support.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes = percent_decode(parts.next()?);
+    if charset.eq_ignore_ascii_case("UTF-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("ISO-8859-1") {
+        Some(bytes.iter().map(|&b| b as char).collect())
+    } else {
+        None
+    }
+}
+
+// Uploads never replace a file, so when two are made to the same name at once, whichever
+// finishes second is turned away with this.
+fn already_exists(name: &str) -> PostBufferError {
+    PostBufferError::new(
+        HttpStatus::Conflict,
+        format!(
+            "A file named {} already exists. Please use a different name.",
+            name
+        ),
+    )
+}
+
+fn staging_name() -> String {
+    format!(
+        ".hypershare-upload-{}-{}",
+        process::id(),
+        STAGED_UPLOADS.fetch_add(1, Ordering::Relaxed)
+    )
+}
 
-const POST_BUFFER_SIZE: usize = 32 * 1024 * 1024;
+// Moves a finished upload from the staging directory to where it belongs. It is linked into
+// place rather than renamed, as unlike a rename, a link never replaces a file that appeared while
+// the upload was in progress.
+fn move_into_place(from: &Path, to: &Path) -> Result<(), io::Error> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from),
+        Err(ref e) if e.raw_os_error() == Some(Errno::EXDEV as i32) => {
+            // The staging directory is on another filesystem, so the data has to be copied.
+            let mut dest = OpenOptions::new().write(true).create_new(true).open(to)?;
+            if let Err(e) = io::copy(&mut fs::File::open(from)?, &mut dest) {
+                let _ = fs::remove_file(to);
+                return Err(e);
+            }
+            fs::remove_file(from)
+        }
+        Err(e) => Err(e),
+    }
+}
 
 #[derive(PartialEq)]
 enum PostRequestState {
@@ -32,14 +109,27 @@ pub struct PostBuffer {
     post_delimeter: BMByte,
     post_delimeter_string: String,
     current_filename: Option<PathBuf>,
+    // Where the current file goes once it is complete, if it is being staged elsewhere
+    current_destination: Option<PathBuf>,
     current_file: Option<fs::File>,
     state: PostRequestState,
     dir: PathBuf,
+    temp_dir: Option<PathBuf>,
     parse_idx: usize,
     queued_error: PostBufferError,
     new_files: Vec<String>,
+    // Files that have been written completely, with their sizes
+    completed_files: Vec<(PathBuf, usize)>,
+    current_size: usize,
     total_written: usize,
     size_limit: usize,
+    // Bytes of the body read after an error, and how many may be read before giving up on the
+    // rest. A limit of 0 means the whole body is always read.
+    discarded: usize,
+    discard_limit: usize,
+    // Set if the body is compressed. `buffer` then holds decompressed data.
+    decoder: Option<GzipBody>,
+    decode_failed: bool,
 }
 
 impl PostBuffer {
@@ -49,27 +139,41 @@ impl PostBuffer {
         delim_str: String,
         slice: &[u8],
         size_limit: usize,
+        discard_limit: usize,
+        temp_dir: Option<PathBuf>,
+        mut decoder: Option<GzipBody>,
     ) -> PostBuffer {
+        // Compressed data is decoded into the buffer as the body is handled.
+        let slice = match &mut decoder {
+            Some(decoder) => {
+                decoder.push(slice);
+                &[]
+            }
+            None => slice,
+        };
+
         let mut pb = PostBuffer {
-            buffer: {
-                let mut v: Vec<u8> = Vec::with_capacity(POST_BUFFER_SIZE);
-                unsafe {
-                    v.set_len(POST_BUFFER_SIZE);
-                }
-                v.into_boxed_slice()
-            },
+            buffer: vec![0; POST_BUFFER_SIZE].into_boxed_slice(),
             fill_location: slice.len(),
             post_delimeter: delim,
             post_delimeter_string: delim_str,
             current_filename: None,
+            current_destination: None,
             current_file: None,
             state: PostRequestState::AwaitingFirstBody,
             dir: dir,
+            temp_dir: temp_dir,
             parse_idx: 0,
             queued_error: PostBufferError::no_error(),
             new_files: Vec::<String>::new(),
+            completed_files: Vec::new(),
+            current_size: 0,
             total_written: 0,
             size_limit: size_limit,
+            discarded: 0,
+            discard_limit: discard_limit,
+            decoder: decoder,
+            decode_failed: false,
         };
         pb.buffer[..pb.fill_location].clone_from_slice(slice);
         pb.total_written += pb.fill_location;
@@ -79,15 +183,44 @@ impl PostBuffer {
 
     pub fn get_new_files(&self) -> &Vec<String> { &self.new_files }
 
+    pub fn get_completed_files(&self) -> &Vec<(PathBuf, usize)> { &self.completed_files }
+
     pub fn read_into_buffer<T>(&mut self, readable: &mut T) -> Result<usize, io::Error>
     where
         T: io::Read,
     {
-        let read = readable.read(&mut self.buffer[self.fill_location..])?;
-        self.fill_location += read;
+        let read = match &mut self.decoder {
+            Some(decoder) => decoder.read_from(readable)?,
+            None => {
+                let read = readable.read(&mut self.buffer[self.fill_location..])?;
+                self.fill_location += read;
+                read
+            }
+        };
+        if self.state == PostRequestState::DiscardingData {
+            self.discarded += read;
+        }
         Ok(read)
     }
 
+    // Moves as much decompressed data into the buffer as will fit. Returns how much was added.
+    fn fill_from_decoder(&mut self) -> Result<usize, PostBufferError> {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => return Ok(0),
+        };
+        match decoder.decode_into(&mut self.buffer[self.fill_location..]) {
+            Ok(decoded) => {
+                self.fill_location += decoded;
+                Ok(decoded)
+            }
+            Err(e) => {
+                self.decode_failed = true;
+                Err(PostBufferError::new(HttpStatus::BadRequest, e.to_string()))
+            }
+        }
+    }
+
     fn find_next_delim(&self, start: usize) -> Option<usize> {
         let vec = self
             .post_delimeter
@@ -116,6 +249,31 @@ impl PostBuffer {
 
         self.current_file = None;
 
+        let final_path = match &self.current_destination {
+            Some(destination) => destination.clone(),
+            None => self.current_filename.clone().unwrap(),
+        };
+
+        if let Some(destination) = self.current_destination.take() {
+            let staged = self.current_filename.as_ref().unwrap();
+            match move_into_place(staged, &destination) {
+                Ok(()) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let name = destination.file_name().unwrap_or_default();
+                    return Err(already_exists(&name.to_string_lossy()));
+                }
+                Err(e) => {
+                    return Err(PostBufferError::server_error(format!(
+                        "Could not move the upload into place: {}",
+                        e
+                    )));
+                }
+            }
+            self.current_filename = None;
+        }
+
+        self.completed_files.push((final_path, self.current_size));
+
         Ok(())
     }
 
@@ -143,6 +301,13 @@ impl PostBuffer {
         self.fill_location = remain;
     }
 
+    // Drops everything before the last `len` unparsed bytes.
+    fn keep_tail(&mut self, len: usize) {
+        let keep = min(len, self.fill_location - self.parse_idx);
+        self.parse_idx = self.fill_location - keep;
+        self.shuffle(keep);
+    }
+
     fn write_and_shuffle(&mut self, up_to: usize) -> Result<(), PostBufferError> {
         if up_to <= self.parse_idx {
             // Need to read more before this can occur
@@ -172,6 +337,7 @@ impl PostBuffer {
 
         self.parse_idx += written;
         self.total_written += written;
+        self.current_size += written;
 
         let amount_remaining: usize = self.fill_location - self.parse_idx;
 
@@ -187,14 +353,14 @@ impl PostBuffer {
             ));
         }
 
-        if limit < self.post_delimeter_string.len() {
-            return Err(PostBufferError::server_error(
-                "Not enough data to write anything.".to_string(),
-            ));
+        // Don't write the last few bytes. An incomplete delimeter, or the CRLF that comes
+        // before it, could be here.
+        let reserved = self.post_delimeter_string.len() + 2;
+        if limit < reserved {
+            // Need to read more before anything can be written
+            return Ok(());
         }
-
-        // Don't write the last few bytes. An incomplete delimeter could be here.
-        let real_limit: usize = limit - self.post_delimeter_string.len();
+        let real_limit: usize = limit - reserved;
 
         self.write_and_shuffle(real_limit)?;
 
@@ -210,6 +376,9 @@ impl PostBuffer {
      * We pre-prepare the error message to be sent, but only write its contents
      * when the ConnectionState is switched to WritingResponse, which occurs
      * when we've reached the end of the sent file.
+     * With a discard limit, we give up once that much has been discarded and
+     * send the error straight away. The connection is then closed without
+     * reading the rest, so the browser may not show the error.
      */
     /* If it is desirable to simply have bad POST requests get a TCP RST
      * with no error message (although one is sent before the reset, browsers
@@ -219,13 +388,21 @@ impl PostBuffer {
         loop {
             match self.handle_new_data() {
                 Ok(done) => {
-                    if done && self.state == PostRequestState::DiscardingData {
+                    let discard_limit_reached =
+                        self.discard_limit > 0 && self.discarded > self.discard_limit;
+                    if self.state == PostRequestState::DiscardingData
+                        && (done || discard_limit_reached)
+                    {
                         return Err(self.queued_error.clone());
                     } else {
                         return Ok(done);
                     }
                 }
                 Err(s) => {
+                    // Nothing more can be made of the body, so there's no point reading the rest.
+                    if self.decode_failed {
+                        return Err(s);
+                    }
                     self.state = PostRequestState::DiscardingData;
                     self.queued_error.add_error(&s);
                 }
@@ -236,7 +413,7 @@ impl PostBuffer {
     // `handle_new_data_raw` wrapper that will delete the current file
     // when an error occurs.
     pub fn handle_new_data(&mut self) -> Result<bool, PostBufferError> {
-        let mut res = self.handle_new_data_raw();
+        let mut res = self.handle_decoded_data();
         match res {
             Ok(_) => {}
             Err(ref mut e) => {
@@ -253,25 +430,36 @@ impl PostBuffer {
         res
     }
 
+    // Runs `handle_new_data_raw` over everything that has been decompressed so far. It may not
+    // all fit in the buffer at once, so the buffer is refilled as the parser makes room.
+    fn handle_decoded_data(&mut self) -> Result<bool, PostBufferError> {
+        loop {
+            let decoded = self.fill_from_decoder()?;
+            let done = self.handle_new_data_raw()?;
+            if done || decoded == 0 {
+                return Ok(done);
+            }
+        }
+    }
+
     pub fn handle_new_data_raw(&mut self) -> Result<bool, PostBufferError> {
         // Where parsing should begin
         loop {
             match self.state {
                 PostRequestState::DiscardingData => {
-                    let new_idx = match self.find_next_delim(self.parse_idx) {
+                    let idx = match self.find_next_delim(self.parse_idx) {
                         None => {
-                            // Cannot find the delimeter, so keep reading. This is good
-                            // for slow connections. If we can't find the delimeter in 32M
-                            // eventually `read` will return 0 and the connection will be
-                            // aborted.
-                            self.shuffle(self.post_delimeter_string.len());
+                            // Cannot find the delimeter, so keep reading. Only the end of what
+                            // has been read is kept, in case the delimeter is split across reads.
+                            self.keep_tail(self.post_delimeter_string.len());
                             return Ok(false);
                         }
-                        Some(idx) => idx + self.post_delimeter_string.len(),
+                        Some(idx) => idx,
                     };
+                    let new_idx = idx + self.post_delimeter_string.len();
                     if self.fill_location - new_idx < 2 {
-                        self.shuffle(self.post_delimeter_string.len() + 2);
                         // Need to get \r\n or --
+                        self.keep_tail(self.fill_location - idx);
                         return Ok(false);
                     }
 
@@ -280,14 +468,14 @@ impl PostBuffer {
                         return Ok(true);
                     }
 
-                    self.shuffle(self.post_delimeter_string.len());
+                    self.parse_idx = new_idx;
                 }
                 PostRequestState::AwaitingFirstBody => {
                     let new_idx = match self.find_next_delim(self.parse_idx) {
                         None => {
                             // Cannot find the delimeter, so keep reading. This is good
-                            // for slow connections. If we can't find the delimeter in 32M
-                            // eventually `read` will return 0 and the connection will be
+                            // for slow connections. If we can't find the delimeter before the
+                            // buffer fills, `read` will return 0 and the connection will be
                             // aborted.
                             return Ok(false);
                         }
@@ -314,7 +502,8 @@ impl PostBuffer {
                             return Ok(false);
                         }
                         Some(idx) => {
-                            if idx < 2 {
+                            // For an empty file, the CRLF directly follows the part's headers.
+                            if idx < self.parse_idx + 2 {
                                 return Err(PostBufferError::new(
                                     HttpStatus::BadRequest,
                                     "No CRLF before delimeter. Malformed request.".to_string(),
@@ -329,14 +518,23 @@ impl PostBuffer {
                     self.state = PostRequestState::AwaitingFirstBody;
                 }
                 PostRequestState::AwaitingMeta => {
-                    let body_start =
-                        match find_body_start(&self.buffer[self.parse_idx..self.fill_location]) {
-                            Some(idx) => idx + self.parse_idx,
-                            None => {
-                                // Waiting for more metadata
-                                return Ok(false);
-                            }
-                        };
+                    let meta_end =
+                        find_body_start(&self.buffer[self.parse_idx..self.fill_location]);
+                    let meta_len = meta_end.unwrap_or(self.fill_location - self.parse_idx);
+                    if meta_len > POST_META_MAX_SIZE {
+                        return Err(PostBufferError::new(
+                            HttpStatus::BadRequest,
+                            format!("Part headers are longer than {} bytes", POST_META_MAX_SIZE),
+                        ));
+                    }
+                    let body_start = match meta_end {
+                        Some(idx) => idx + self.parse_idx,
+                        None => {
+                            // Waiting for more metadata, which needs room to arrive
+                            self.keep_tail(self.fill_location - self.parse_idx);
+                            return Ok(false);
+                        }
+                    };
 
                     let meta = &self.buffer[self.parse_idx..body_start];
                     let meta_str = String::from_utf8_lossy(meta).to_string();
@@ -363,17 +561,39 @@ impl PostBuffer {
                     }
 
                     let mut filename: &str = "";
+                    let mut ext_filename: Option<&str> = None;
                     for kv in info.split(";") {
                         if let Some(idx) = kv.find("=") {
                             let (k, v) = kv.split_at(idx);
-                            if k.trim_start() == "filename" {
-                                // 1.. to discard '='
-                                filename = &v[1..].trim();
-                                break;
+                            // 1.. to discard '='
+                            match k.trim() {
+                                "filename" if filename == "" => filename = &v[1..].trim(),
+                                "filename*" => ext_filename = Some(&v[1..].trim()),
+                                _ => {}
                             }
                         }
                     }
 
+                    if filename.starts_with("\"") && filename.len() > 1 {
+                        filename = &filename[1..filename.len() - 1];
+                    }
+
+                    // Browsers send non-ASCII names as filename*, usually alongside a plain
+                    // filename for older servers, and the encoded form is preferred when both
+                    // are given.
+                    let filename = match ext_filename {
+                        Some(value) => match decode_ext_value(value) {
+                            Some(decoded) => decoded,
+                            None => {
+                                return Err(PostBufferError::new(
+                                    HttpStatus::UnprocessableEntity,
+                                    format!("Invalid filename*: {}", value),
+                                ));
+                            }
+                        },
+                        None => filename.to_string(),
+                    };
+
                     if filename == "" {
                         return Err(PostBufferError::new(
                             HttpStatus::UnprocessableEntity,
@@ -388,21 +608,31 @@ impl PostBuffer {
                         ));
                     }
 
-                    if filename.starts_with("\"") {
-                        filename = &filename[1..filename.len() - 1];
-                    }
+                    let real_filename = self.dir.join(&filename);
 
-                    self.new_files.push(filename.to_string());
+                    self.new_files.push(filename.clone());
 
-                    let real_filename = self.dir.join(filename);
+                    let (open_filename, destination) = match &self.temp_dir {
+                        Some(temp_dir) => {
+                            // Check now rather than after the whole file has been received.
+                            if real_filename.exists() {
+                                return Err(already_exists(&filename));
+                            }
+                            (temp_dir.join(staging_name()), Some(real_filename))
+                        }
+                        None => (real_filename, None),
+                    };
 
                     self.current_file = Some(
                         match OpenOptions::new()
                             .write(true)
                             .create_new(true)
-                            .open(&real_filename)
+                            .open(&open_filename)
                         {
                             Ok(f) => f,
+                            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                                return Err(already_exists(&filename));
+                            }
                             _ => {
                                 return Err(PostBufferError::server_error(
                                     "Could not open file for writing. If the file already exists, \
@@ -413,7 +643,9 @@ impl PostBuffer {
                         },
                     );
 
-                    self.current_filename = Some(real_filename);
+                    self.current_filename = Some(open_filename);
+                    self.current_destination = destination;
+                    self.current_size = 0;
 
                     self.state = PostRequestState::AwaitingBody;
 
@@ -423,3 +655,15 @@ impl PostBuffer {
         }
     }
 }
+
+// A file still being staged when the request goes away was cut short, and nothing else would
+// remove it from the staging directory.
+impl Drop for PostBuffer {
+    fn drop(&mut self) {
+        if self.current_destination.is_some() {
+            if let Some(staged) = &self.current_filename {
+                let _ = fs::remove_file(staged);
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    cmp::{max, min},
+    fs,
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time,
+};
+
+use crate::http::content_encoding::GzipBody;
+
+const PUT_BUFFER_SIZE: usize = 256 * 1024;
+
+// Byte range of an upload, as given by the request's Content-Range header.
+#[derive(Clone, Copy)]
+pub struct UploadRange {
+    pub start: usize,
+    pub end: usize, // Inclusive
+    pub total: usize,
+}
+
+// Ranges of a resumable upload that have been fully received so far, and the ones whose bodies
+// are still arriving.
+pub struct ReceivedRanges {
+    total: usize,
+    ranges: Vec<(usize, usize)>, // Inclusive, sorted by start
+    in_flight: Vec<(usize, usize)>,
+    // When a piece last started or finished
+    updated: time::Instant,
+}
+
+impl ReceivedRanges {
+    pub fn new(total: usize) -> ReceivedRanges {
+        ReceivedRanges {
+            total: total,
+            ranges: Vec::new(),
+            in_flight: Vec::new(),
+            updated: time::Instant::now(),
+        }
+    }
+
+    pub fn total(&self) -> usize { self.total }
+
+    // Whether `range` overlaps one that has been received or is being received.
+    pub fn overlaps(&self, range: &UploadRange) -> bool {
+        self.ranges
+            .iter()
+            .chain(self.in_flight.iter())
+            .any(|&(start, end)| range.start <= end && start <= range.end)
+    }
+
+    // Marks `range` as being received, until it is inserted or abandoned.
+    pub fn start(&mut self, range: &UploadRange) {
+        self.in_flight.push((range.start, range.end));
+        self.updated = time::Instant::now();
+    }
+
+    pub fn abandon(&mut self, range: &UploadRange) {
+        if let Some(i) = self
+            .in_flight
+            .iter()
+            .position(|&r| r == (range.start, range.end))
+        {
+            self.in_flight.remove(i);
+        }
+    }
+
+    pub fn insert(&mut self, range: &UploadRange) {
+        self.abandon(range);
+        self.updated = time::Instant::now();
+        self.ranges.push((range.start, range.end));
+        self.ranges.sort();
+
+        // Merge adjacent ranges so the Range header stays short.
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.ranges.len());
+        for &(start, end) in &self.ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 + 1 => {
+                    last.1 = max(last.1, end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    // Whether nothing has been received, or is being received.
+    pub fn is_empty(&self) -> bool { self.ranges.is_empty() && self.in_flight.is_empty() }
+
+    // Whether no piece is being received, and none has been for `max_idle`.
+    pub fn is_stale(&self, max_idle: time::Duration) -> bool {
+        self.in_flight.is_empty() && self.updated.elapsed() > max_idle
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total == 0 || (self.ranges.len() == 1 && self.ranges[0] == (0, self.total - 1))
+    }
+
+    // Value for the Range header telling the client what has been received, e.g.
+    // `bytes=0-99,200-299`.
+    pub fn to_header(&self) -> String {
+        let ranges: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|(start, end)| format!("{}-{}", start, end))
+            .collect();
+        format!("bytes={}", ranges.join(","))
+    }
+}
+
+// Writes the body of a PUT request into a file, starting at the beginning of the upload's
+// range (if any).
+pub struct PutBuffer {
+    path: PathBuf,
+    file: fs::File,
+    buffer: Box<[u8]>,
+    pending: usize,
+    remaining: usize,
+    range: Option<UploadRange>,
+    // Set if the body is compressed; `remaining` counts compressed bytes
+    decoder: Option<GzipBody>,
+    written: usize,
+    size_limit: usize,
+}
+
+impl PutBuffer {
+    pub fn new(
+        path: PathBuf,
+        mut file: fs::File,
+        range: Option<UploadRange>,
+        content_length: usize,
+        slice: &[u8],
+        decoder: Option<GzipBody>,
+        size_limit: usize,
+    ) -> Result<PutBuffer, io::Error> {
+        if let Some(r) = &range {
+            file.seek(SeekFrom::Start(r.start as u64))?;
+        }
+
+        let mut pb = PutBuffer {
+            path: path,
+            file: file,
+            buffer: vec![0; PUT_BUFFER_SIZE].into_boxed_slice(),
+            pending: 0,
+            remaining: content_length,
+            range: range,
+            decoder: decoder,
+            written: 0,
+            size_limit: size_limit,
+        };
+
+        // Anything past the Content-Length is not part of this request. What's left is written
+        // by the first call to `write_buffer_to_file`.
+        let initial = &slice[..min(slice.len(), content_length)];
+        pb.pending = initial.len();
+        pb.buffer[..pb.pending].copy_from_slice(initial);
+
+        Ok(pb)
+    }
+
+    pub fn get_path(&self) -> &Path { &self.path }
+
+    pub fn get_range(&self) -> Option<UploadRange> { self.range }
+
+    pub fn is_done(&self) -> bool { self.remaining == 0 }
+
+    // Bytes written to the file, after any decompression.
+    pub fn written(&self) -> usize { self.written }
+
+    pub fn is_over_limit(&self) -> bool { self.size_limit > 0 && self.written > self.size_limit }
+
+    pub fn read_into_buffer<T>(&mut self, readable: &mut T) -> Result<usize, io::Error>
+    where
+        T: io::Read,
+    {
+        let to_read = min(self.remaining, self.buffer.len());
+        let read = readable.read(&mut self.buffer[..to_read])?;
+        self.pending = read;
+        Ok(read)
+    }
+
+    pub fn write_buffer_to_file(&mut self) -> Result<(), io::Error> {
+        self.remaining -= self.pending;
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => {
+                self.file.write_all(&self.buffer[..self.pending])?;
+                self.written += self.pending;
+                self.pending = 0;
+                return Ok(());
+            }
+        };
+
+        decoder.push(&self.buffer[..self.pending]);
+        self.pending = 0;
+        if self.remaining == 0 {
+            decoder.end_of_input();
+        }
+        loop {
+            let decoded = decoder.decode_into(&mut self.buffer)?;
+            if decoded == 0 {
+                return Ok(());
+            }
+            self.file.write_all(&self.buffer[..decoded])?;
+            self.written += decoded;
+            if self.size_limit > 0 && self.written > self.size_limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Upload size limit of {} bytes exceeded", self.size_limit),
+                ));
+            }
+        }
+    }
+}
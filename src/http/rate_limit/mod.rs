@@ -0,0 +1,56 @@
+use std::{
+    cell::Cell,
+    cmp::min,
+    time::{Duration, Instant},
+};
+
+// Holds every connection together to --global-rate-limit. Each write is limited to the allowance
+// that is left, and nothing is sent once it has run out. At most a tenth of a second's worth of
+// allowance builds up while idle.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    allowance: Cell<i64>,
+    last_refill: Cell<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec: bytes_per_sec,
+            allowance: Cell::new(0),
+            last_refill: Cell::new(Instant::now()),
+        }
+    }
+
+    fn max_allowance(&self) -> i64 { (self.bytes_per_sec / 10).max(1) as i64 }
+
+    // Adds the allowance earned since the last refill.
+    pub fn refill(&self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill.get());
+        let earned = (elapsed.as_secs_f64() * self.bytes_per_sec as f64) as i64;
+        // Only move the refill time on once something has been earned, so that frequent
+        // refills don't each round their share down to nothing.
+        if earned > 0 {
+            self.allowance
+                .set(min(self.allowance.get() + earned, self.max_allowance()));
+            self.last_refill.set(now);
+        }
+    }
+
+    pub fn can_send(&self) -> bool { self.allowance.get() > 0 }
+
+    // How many bytes can be sent right now
+    pub fn available(&self) -> usize { self.allowance.get().max(0) as usize }
+
+    pub fn spend(&self, amt: usize) { self.allowance.set(self.allowance.get() - amt as i64); }
+
+    // How long until something can be sent again
+    pub fn wait(&self) -> Duration {
+        if self.can_send() {
+            return Duration::from_secs(0);
+        }
+        let owed = (1 - self.allowance.get()) as f64;
+        Duration::from_secs_f64(owed / self.bytes_per_sec as f64)
+    }
+}
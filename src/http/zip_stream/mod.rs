@@ -0,0 +1,372 @@
+use std::{
+    cmp::min,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    rendering::is_hidden,
+    timestamp::{civil_from_days, unix_secs},
+};
+
+// Size of a local file header, not including the name.
+const LOCAL_HEADER_SIZE: u64 = 30;
+// Size of the data descriptor that follows each file's data.
+const DATA_DESCRIPTOR_SIZE: u64 = 16;
+// Size of a central directory header, not including the name.
+const CENTRAL_HEADER_SIZE: u64 = 46;
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+
+// Sizes and CRCs are written after the data (bit 3), and names are UTF-8 (bit 11).
+const FLAGS: u16 = 0x0008 | 0x0800;
+const VERSION: u16 = 20;
+
+// Bounds on the walk that collects an archive's entries, which runs before the response starts.
+const ZIP_MAX_DEPTH: usize = 16;
+const ZIP_MAX_ENTRIES: usize = 10000;
+
+lazy_static! {
+    static ref CRC_TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        for i in 0..256 {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    0xedb88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            table[i] = c;
+        }
+        table
+    };
+}
+
+fn update_crc(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for byte in data {
+        c = CRC_TABLE[((c ^ *byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+// Converts a modification time to the MS-DOS (time, date) pair used by ZIP headers.
+fn dos_time(mtime: SystemTime) -> (u16, u16) {
+    let secs = unix_secs(mtime);
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+
+    // DOS dates start in 1980.
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let year = min(year, 1980 + 127);
+
+    let time = ((rem / 3600) << 11) | (((rem % 3600) / 60) << 5) | ((rem % 60) / 2);
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+struct ZipEntry {
+    // Name inside the archive, '/'-separated. Directories end with '/'.
+    name: String,
+    path: PathBuf,
+    size: u32,
+    time: u16,
+    date: u16,
+    crc: u32,
+    offset: u32,
+}
+
+enum Stage {
+    LocalHeader,
+    Data,
+    DataDescriptor,
+    CentralDirectory,
+    EndOfCentralDirectory,
+    Done,
+}
+
+// Streams a stored (uncompressed) ZIP archive of a directory. Entries are produced as they are
+// read, so only one file is open at a time and nothing is buffered beyond a single header.
+pub struct ZipStream {
+    entries: Vec<ZipEntry>,
+    current: usize,
+    stage: Stage,
+    pending: Vec<u8>,
+    pending_pos: usize,
+    file: Option<fs::File>,
+    remaining: u32,
+    crc: u32,
+    offset: u64,
+    central_directory_offset: u64,
+    len: u64,
+}
+
+impl ZipStream {
+    // Collects the files under `root` (skipping symlinks, anything that isn't a regular file or
+    // directory, and entries matching `hide` along with everything under them). Fails if the
+    // archive would need ZIP64 extensions, or if the directory holds more than ZIP_MAX_ENTRIES
+    // entries or is nested deeper than ZIP_MAX_DEPTH.
+    pub fn new(root: &Path, hide: &[String]) -> Result<ZipStream, io::Error> {
+        let mut entries = Vec::new();
+        collect_entries(root, "", 0, hide, &mut entries)?;
+
+        let mut len = END_OF_CENTRAL_DIRECTORY_SIZE;
+        for entry in &entries {
+            let name_len = entry.name.len() as u64;
+            len += LOCAL_HEADER_SIZE + name_len + entry.size as u64 + DATA_DESCRIPTOR_SIZE;
+            len += CENTRAL_HEADER_SIZE + name_len;
+        }
+
+        if len > std::u32::MAX as u64 {
+            return Err(too_large());
+        }
+
+        Ok(ZipStream {
+            entries: entries,
+            current: 0,
+            stage: Stage::LocalHeader,
+            pending: Vec::new(),
+            pending_pos: 0,
+            file: None,
+            remaining: 0,
+            crc: 0,
+            offset: 0,
+            central_directory_offset: 0,
+            len: len,
+        })
+    }
+
+    // Total size of the archive in bytes.
+    pub fn len(&self) -> usize { self.len as usize }
+
+    // Queues up the next header, or moves on to the next file's data.
+    fn advance(&mut self) -> Result<(), io::Error> {
+        let mut buf = Vec::new();
+        match self.stage {
+            Stage::LocalHeader => {
+                if self.current == self.entries.len() {
+                    self.central_directory_offset = self.offset;
+                    self.current = 0;
+                    self.stage = Stage::CentralDirectory;
+                    return Ok(());
+                }
+                let entry = &mut self.entries[self.current];
+                entry.offset = self.offset as u32;
+                put_u32(&mut buf, 0x04034b50);
+                put_u16(&mut buf, VERSION);
+                put_u16(&mut buf, FLAGS);
+                put_u16(&mut buf, 0); // Stored
+                put_u16(&mut buf, entry.time);
+                put_u16(&mut buf, entry.date);
+                put_u32(&mut buf, 0); // CRC, in the data descriptor
+                put_u32(&mut buf, 0); // Compressed size, in the data descriptor
+                put_u32(&mut buf, 0); // Uncompressed size, in the data descriptor
+                put_u16(&mut buf, entry.name.len() as u16);
+                put_u16(&mut buf, 0); // Extra field length
+                buf.extend_from_slice(entry.name.as_bytes());
+
+                self.file = if entry.name.ends_with('/') {
+                    None
+                } else {
+                    fs::File::open(&entry.path).ok()
+                };
+                self.remaining = entry.size;
+                self.crc = 0;
+                self.stage = Stage::Data;
+            }
+            Stage::Data => {
+                self.file = None;
+                self.stage = Stage::DataDescriptor;
+                return Ok(());
+            }
+            Stage::DataDescriptor => {
+                let entry = &mut self.entries[self.current];
+                entry.crc = self.crc;
+                put_u32(&mut buf, 0x08074b50);
+                put_u32(&mut buf, entry.crc);
+                put_u32(&mut buf, entry.size);
+                put_u32(&mut buf, entry.size);
+                self.current += 1;
+                self.stage = Stage::LocalHeader;
+            }
+            Stage::CentralDirectory => {
+                if self.current == self.entries.len() {
+                    self.stage = Stage::EndOfCentralDirectory;
+                    return Ok(());
+                }
+                let entry = &self.entries[self.current];
+                put_u32(&mut buf, 0x02014b50);
+                put_u16(&mut buf, VERSION); // Version made by
+                put_u16(&mut buf, VERSION); // Version needed to extract
+                put_u16(&mut buf, FLAGS);
+                put_u16(&mut buf, 0); // Stored
+                put_u16(&mut buf, entry.time);
+                put_u16(&mut buf, entry.date);
+                put_u32(&mut buf, entry.crc);
+                put_u32(&mut buf, entry.size);
+                put_u32(&mut buf, entry.size);
+                put_u16(&mut buf, entry.name.len() as u16);
+                put_u16(&mut buf, 0); // Extra field length
+                put_u16(&mut buf, 0); // Comment length
+                put_u16(&mut buf, 0); // Disk number
+                put_u16(&mut buf, 0); // Internal attributes
+                put_u32(&mut buf, 0); // External attributes
+                put_u32(&mut buf, entry.offset);
+                buf.extend_from_slice(entry.name.as_bytes());
+                self.current += 1;
+            }
+            Stage::EndOfCentralDirectory => {
+                let count = self.entries.len() as u16;
+                put_u32(&mut buf, 0x06054b50);
+                put_u16(&mut buf, 0); // Disk number
+                put_u16(&mut buf, 0); // Disk with the central directory
+                put_u16(&mut buf, count);
+                put_u16(&mut buf, count);
+                put_u32(
+                    &mut buf,
+                    (self.offset - self.central_directory_offset) as u32,
+                );
+                put_u32(&mut buf, self.central_directory_offset as u32);
+                put_u16(&mut buf, 0); // Comment length
+                self.stage = Stage::Done;
+            }
+            Stage::Done => {}
+        }
+        self.pending = buf;
+        self.pending_pos = 0;
+        Ok(())
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let to_read = min(buf.len(), self.remaining as usize);
+        let mut read = match self.file {
+            Some(ref mut file) => file.read(&mut buf[..to_read])?,
+            None => 0,
+        };
+        if read == 0 {
+            // The file shrank since the archive was laid out. Pad it with zeros so the offsets
+            // we've promised stay correct.
+            for b in &mut buf[..to_read] {
+                *b = 0;
+            }
+            read = to_read;
+        }
+        self.crc = update_crc(self.crc, &buf[..read]);
+        self.remaining -= read as u32;
+        Ok(read)
+    }
+}
+
+impl Read for ZipStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        loop {
+            if self.pending_pos < self.pending.len() {
+                let mut slice = &self.pending[self.pending_pos..];
+                let read = slice.read(buf)?;
+                self.pending_pos += read;
+                self.offset += read as u64;
+                return Ok(read);
+            }
+
+            match self.stage {
+                Stage::Done => return Ok(0),
+                Stage::Data if self.remaining > 0 => {
+                    let read = self.read_data(buf)?;
+                    self.offset += read as u64;
+                    return Ok(read);
+                }
+                _ => self.advance()?,
+            }
+        }
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, val: u16) { buf.extend_from_slice(&val.to_le_bytes()); }
+
+fn put_u32(buf: &mut Vec<u8>, val: u32) { buf.extend_from_slice(&val.to_le_bytes()); }
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "Directory is too large to archive")
+}
+
+fn collect_entries(
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    hide: &[String],
+    entries: &mut Vec<ZipEntry>,
+) -> Result<(), io::Error> {
+    if depth >= ZIP_MAX_DEPTH {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Directory is nested too deeply to archive",
+        ));
+    }
+    let read_dir = match fs::read_dir(dir) {
+        Ok(r) => r,
+        // Leave out subdirectories we can't read rather than failing the whole archive.
+        Err(_) if prefix.len() > 0 => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    let mut paths: Vec<_> = read_dir.filter_map(|e| e.ok()).collect();
+    paths.sort_by_key(|e| e.path());
+    for entry in paths {
+        if entries.len() >= ZIP_MAX_ENTRIES {
+            return Err(too_large());
+        }
+        let fname = entry.file_name();
+        let fname_str = match fname.to_str() {
+            Some(f) => f,
+            _ => {
+                continue;
+            }
+        };
+        // Hidden entries aren't listed, so they aren't archived either.
+        if is_hidden(fname_str, hide) {
+            continue;
+        }
+        // Don't follow symlinks, they may lead outside of the served directory.
+        let meta = match fs::symlink_metadata(entry.path()) {
+            Ok(m) => m,
+            _ => {
+                continue;
+            }
+        };
+        let (time, date) = dos_time(meta.modified().unwrap_or(UNIX_EPOCH));
+        if meta.is_dir() {
+            let name = format!("{}{}/", prefix, fname_str);
+            entries.push(ZipEntry {
+                name: name.clone(),
+                path: entry.path(),
+                size: 0,
+                time: time,
+                date: date,
+                crc: 0,
+                offset: 0,
+            });
+            collect_entries(&entry.path(), &name, depth + 1, hide, entries)?;
+        } else if meta.is_file() {
+            if fs::File::open(entry.path()).is_err() {
+                continue;
+            }
+            if meta.len() > std::u32::MAX as u64 {
+                return Err(too_large());
+            }
+            entries.push(ZipEntry {
+                name: format!("{}{}", prefix, fname_str),
+                path: entry.path(),
+                size: meta.len() as u32,
+                time: time,
+                date: date,
+                crc: 0,
+                offset: 0,
+            });
+        }
+    }
+    Ok(())
+}
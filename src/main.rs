@@ -4,30 +4,114 @@ extern crate lazy_static;
 mod display;
 mod http;
 mod opts;
+mod privileges;
 mod rendering;
+mod timestamp;
 
 use display::{
     display,
-    types::{ConnectionSet, ControlEvent},
+    types::{format_upload, ConnectionSet, ControlEvent},
 };
 use http::HttpTui;
-use opts::types::Opts;
+use opts::types::{LogFormat, Opts};
 
 use clap::Clap;
 use std::{
     fs::canonicalize,
     io,
+    net::SocketAddr,
+    os::unix::io::RawFd,
     path::Path,
+    process,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicI32, Ordering},
         mpsc, Arc, Mutex,
     },
     thread,
 };
 
-use nix::unistd;
+use nix::{
+    libc,
+    sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal},
+    unistd,
+};
 use termion::{event::Key, input::TermRead};
 
+// Write end of the pipe to the server loop, for the signal handler to ask it to shut down
+static SHUTDOWN_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_shutdown_signal(_: libc::c_int) {
+    let _ = unistd::write(SHUTDOWN_PIPE.load(Ordering::Relaxed), b"s");
+}
+
+// Makes SIGINT and SIGTERM stop the server once the requests it is serving have finished.
+fn shut_down_on_signals(write_end: RawFd) -> nix::Result<()> {
+    SHUTDOWN_PIPE.store(write_end, Ordering::Relaxed);
+    let action = SigAction::new(
+        SigHandler::Handler(handle_shutdown_signal),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    unsafe {
+        signal::sigaction(Signal::SIGINT, &action)?;
+        signal::sigaction(Signal::SIGTERM, &action)?;
+    }
+    Ok(())
+}
+
+// Says what is being served and how, when running headless. With --log-format json this is a
+// single JSON object, like the log lines that follow it.
+fn print_startup_banner(opts: &Opts, root: &Path, address: SocketAddr) {
+    let mut features = vec![];
+    if opts.uploading_enabled {
+        features.push("uploads");
+    }
+    if !opts.disable_directory_listings {
+        features.push("listings");
+    }
+    if opts.zip_downloads {
+        features.push("zip");
+    }
+    if opts.gzip_level.is_some() {
+        features.push("gzip");
+    }
+    if opts.brotli_level.is_some() {
+        features.push("brotli");
+    }
+    if opts.mmap {
+        features.push("mmap");
+    }
+    if opts.enable_metrics {
+        features.push("metrics");
+    }
+    if !opts.cors_origins.is_empty() {
+        features.push("cors");
+    }
+    match opts.log_format {
+        LogFormat::Text => {
+            println!("hypershare revision {}", rendering::GIT_HASH);
+            println!("Serving {}", root.display());
+            if features.is_empty() {
+                println!("Features: none");
+            } else {
+                println!("Features: {}", features.join(", "));
+            }
+            println!("Listening on {}", address);
+        }
+        LogFormat::Json => {
+            let features: Vec<String> = features.iter().map(|f| http::json_string(f)).collect();
+            println!(
+                "{{\"event\":\"startup\",\"version\":{},\"root\":{},\"address\":{},\"features\":\
+                 [{}]}}",
+                http::json_string(rendering::GIT_HASH),
+                http::json_string(&root.to_string_lossy()),
+                http::json_string(&address.to_string()),
+                features.join(",")
+            );
+        }
+    }
+}
+
 fn main() -> Result<(), io::Error> {
     let opts: Opts = Opts::parse();
     let path = Path::new(&opts.directory);
@@ -41,15 +125,68 @@ fn main() -> Result<(), io::Error> {
 
     opts::verify_opts(&opts);
 
+    if opts::root_is_symlink(path) {
+        if opts.no_symlink_root {
+            println!(
+                "Error: {} is a symlink to {}. Refusing to serve it because of --no-symlink-root.",
+                opts.directory,
+                canon_path.display()
+            );
+            process::exit(1);
+        }
+        let warning = format!(
+            "Warning: {} is a symlink. Serving its target, {}.",
+            opts.directory,
+            canon_path.display()
+        );
+        // Only JSON goes to stdout in a JSON log.
+        match opts.log_format {
+            LogFormat::Text => println!("{}", warning),
+            LogFormat::Json => eprintln!("{}", warning),
+        }
+    }
+    let virtual_files = opts::read_virtual_files(&opts);
+    let vhosts = opts::read_vhosts(&opts);
+    let overlays = opts::read_overlays(&opts);
+    let error_template = opts::read_error_template(&opts);
+    let listing_css = opts::read_listing_css(&opts);
+    let headers = opts::read_headers(&opts);
+    let denied_ips = opts::read_denied_ips(&opts);
+    let audit_log = opts::open_audit_log(&opts);
+
     let (hist_tx, hist_rx) = mpsc::channel();
+    let (upload_tx, upload_rx) = mpsc::channel();
 
     let mut tui = match HttpTui::new(&canon_path.as_path(), hist_tx, &opts) {
         Ok(tui) => tui,
         Err(e) => {
-            eprintln!("Failed to bind to port {}: {}", opts.port, e);
+            match opts::inherited_listener_fd(&opts) {
+                Some(fd) => eprintln!("Failed to listen on inherited descriptor {}: {}", fd, e),
+                None => eprintln!("Failed to bind to port {}: {}", opts.port, e),
+            }
             return Ok(());
         }
     };
+    let local_addr = tui.local_addr();
+    tui.add_virtual_files(virtual_files);
+    tui.add_vhosts(vhosts);
+    tui.add_overlays(overlays);
+    tui.set_error_template(error_template);
+    tui.set_listing_css(listing_css);
+    tui.set_extra_headers(headers);
+    tui.set_audit_log(audit_log);
+    if !denied_ips.is_empty() {
+        tui.set_accept_filter(Some(Box::new(move |addr: &SocketAddr| {
+            !denied_ips.contains(&addr.ip())
+        })));
+    }
+    tui.set_upload_channel(upload_tx);
+
+    // Now that the port is bound, we don't need to keep any elevated privileges.
+    if let Err(e) = privileges::drop_privileges(opts.user.as_deref(), opts.group.as_deref()) {
+        eprintln!("Failed to drop privileges: {}", e);
+        return Ok(());
+    }
 
     let (read_end, write_end) = match unistd::pipe() {
         Ok(tuple) => tuple,
@@ -60,7 +197,7 @@ fn main() -> Result<(), io::Error> {
     };
 
     if !opts.headless {
-        let connection_set = Arc::new(Mutex::new(ConnectionSet::new()));
+        let connection_set = Arc::new(Mutex::new(ConnectionSet::new(opts.history_size)));
         let connection_set_needs_update = Arc::new(AtomicBool::new(false));
 
         let needs_update_clone = Arc::clone(&connection_set_needs_update);
@@ -69,7 +206,9 @@ fn main() -> Result<(), io::Error> {
 
         let connection_set_ptr = connection_set.clone();
         let canon_path = canon_path.clone();
-        let opts_c = opts.clone();
+        let mut opts_c = opts.clone();
+        opts_c.hostmask = local_addr.ip().to_string();
+        opts_c.port = local_addr.port();
         let thd = thread::spawn(move || {
             match display(
                 canon_path.display(),
@@ -102,9 +241,24 @@ fn main() -> Result<(), io::Error> {
                         Key::Char('k') => {
                             let _ = tx.send(ControlEvent::CloseAll);
                         }
+                        Key::Char('i') => {
+                            let _ = tx.send(ControlEvent::CloseIdle);
+                        }
                         Key::Char(' ') => {
                             let _ = tx.send(ControlEvent::Toggle);
                         }
+                        Key::Char('r') => {
+                            let _ = tx.send(ControlEvent::ToggleReads);
+                        }
+                        Key::Char('w') => {
+                            let _ = tx.send(ControlEvent::ToggleUploads);
+                        }
+                        Key::Char('d') => {
+                            let _ = tx.send(ControlEvent::ToggleDirListings);
+                        }
+                        Key::Char('u') => {
+                            let _ = tx.send(ControlEvent::ToggleUploading);
+                        }
                         _ => {}
                     }
                 }
@@ -128,6 +282,9 @@ fn main() -> Result<(), io::Error> {
                         }
                     }
                 }
+                while let Ok(upload) = upload_rx.try_recv() {
+                    conn_set.add_upload(&upload);
+                }
                 connection_set_needs_update.store(false, Ordering::Release);
             }
         });
@@ -137,14 +294,27 @@ fn main() -> Result<(), io::Error> {
         let _ = thd.join();
         let _ = keys.join();
     } else {
-        println!("Listening on {}:{}", opts.hostmask, opts.port);
-        tui.run(read_end, move |_connections| loop {
-            match hist_rx.try_recv() {
-                Ok(s) => {
-                    println!("{}", s);
+        if let Err(e) = shut_down_on_signals(write_end) {
+            eprintln!("Failed to install signal handlers: {}", e);
+            return Ok(());
+        }
+        print_startup_banner(&opts, &canon_path, local_addr);
+        let log_format = opts.log_format;
+        tui.run(read_end, move |_connections| {
+            loop {
+                match hist_rx.try_recv() {
+                    Ok(s) => {
+                        println!("{}", s);
+                    }
+                    Err(mpsc::TryRecvError::Empty) | Err(mpsc::TryRecvError::Disconnected) => {
+                        break;
+                    }
                 }
-                Err(mpsc::TryRecvError::Empty) | Err(mpsc::TryRecvError::Disconnected) => {
-                    break;
+            }
+            while let Ok(upload) = upload_rx.try_recv() {
+                match log_format {
+                    LogFormat::Text => println!("Upload complete: {}", format_upload(&upload)),
+                    LogFormat::Json => println!("{}", http::upload_json(&upload)),
                 }
             }
         });
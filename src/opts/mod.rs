@@ -1,6 +1,20 @@
 pub mod types;
 
-use std::process;
+use std::{
+    collections::HashMap,
+    env, fs,
+    net::IpAddr,
+    os::unix::io::RawFd,
+    path::{Path, PathBuf},
+    process,
+};
+
+use nix::unistd;
+
+use crate::http::AuditLog;
+
+// The first descriptor passed by systemd socket activation
+const SD_LISTEN_FDS_START: RawFd = 3;
 
 pub fn verify_opts(opts: &types::Opts) {
     if opts.start_disabled && opts.headless {
@@ -10,8 +24,237 @@ pub fn verify_opts(opts: &types::Opts) {
         );
     }
 
+    for (paused, name) in &[
+        (opts.start_reads_paused, "--start-reads-paused"),
+        (opts.start_uploads_paused, "--start-uploads-paused"),
+    ] {
+        if *paused && opts.headless && !opts.start_disabled {
+            println!(
+                "Warning: {} and --headless have both been specified. These requests will remain \
+                 paused, as there is no way to resume them while running headless.",
+                name
+            );
+        }
+    }
+
+    if opts.sort_files_first && opts.sort_mixed {
+        println!("Error: --sort-files-first and --sort-mixed cannot be used together.");
+        process::exit(1);
+    }
+
+    if opts.history_size == 0 {
+        println!("Error: --history-size must be at least 1.");
+        process::exit(1);
+    }
+
+    if let Some(dir) = &opts.upload_temp_dir {
+        if !Path::new(dir).is_dir() {
+            println!("Error: upload temp dir {} is not a directory.", dir);
+            process::exit(1);
+        }
+    }
+
+    if opts.cors_origins.is_empty()
+        && (opts.cors_methods.is_some()
+            || opts.cors_headers.is_some()
+            || opts.cors_expose_headers.is_some()
+            || opts.cors_credentials)
+    {
+        println!("Error: the --cors-* options require at least one --cors-origin.");
+        process::exit(1);
+    }
+
+    if opts.gzip_level.map_or(false, |level| level > 9) {
+        println!("Error: --gzip-level must be between 0 and 9.");
+        process::exit(1);
+    }
+
+    if opts.brotli_level.map_or(false, |level| level > 11) {
+        println!("Error: --brotli-level must be between 0 and 11.");
+        process::exit(1);
+    }
+
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if opts.default_charset.is_empty() || !opts.default_charset.chars().all(is_token_char) {
+        println!("Error: invalid charset {}.", opts.default_charset);
+        process::exit(1);
+    }
+
     if opts.index_file.contains("/") {
         println!("Error: invalid index file.");
         process::exit(1);
     }
 }
+
+// A listening socket to use instead of binding one: the one given with --inherit-fd, or else the
+// first passed by systemd socket activation, if the LISTEN_FDS it set are meant for us.
+pub fn inherited_listener_fd(opts: &types::Opts) -> Option<RawFd> {
+    if opts.inherit_fd.is_some() {
+        return opts.inherit_fd;
+    }
+    let pid: i32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: i32 = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid == unistd::getpid().as_raw() && fds >= 1 {
+        Some(SD_LISTEN_FDS_START)
+    } else {
+        None
+    }
+}
+
+// Whether the root directory was given as a symlink, rather than being one by way of a parent.
+pub fn root_is_symlink(path: &Path) -> bool {
+    // Rebuilding the path drops any trailing '/', which would make lstat follow the link.
+    let path: PathBuf = path.components().collect();
+    match fs::symlink_metadata(&path) {
+        Ok(meta) => meta.file_type().is_symlink(),
+        Err(_) => false,
+    }
+}
+
+// Resolves each --vhost to its host and canonical root directory.
+pub fn read_vhosts(opts: &types::Opts) -> Vec<(String, PathBuf)> {
+    let mut vhosts = Vec::new();
+    for spec in &opts.vhosts {
+        let colon = match spec.find(':') {
+            Some(i) => i,
+            None => {
+                println!("Error: invalid vhost `{}`. Expected <host>:<dir>.", spec);
+                process::exit(1);
+            }
+        };
+        let (host, dir) = (&spec[..colon], &spec[colon + 1..]);
+        let root = match fs::canonicalize(dir) {
+            Ok(root) if root.is_dir() => root,
+            _ => {
+                println!("Error: vhost directory {} is not a directory.", dir);
+                process::exit(1);
+            }
+        };
+        vhosts.push((host.to_string(), root));
+    }
+    vhosts
+}
+
+// Parses each --header. Headers that frame the response are set by the server alone, as a second
+// one would leave the client unable to tell where the response ends.
+pub fn read_headers(opts: &types::Opts) -> Vec<(String, String)> {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    let mut headers = Vec::new();
+    for spec in &opts.headers {
+        let (name, value) = match spec.find(':') {
+            Some(i) => (&spec[..i], spec[i + 1..].trim()),
+            None => ("", ""),
+        };
+        if name.is_empty()
+            || !name.chars().all(is_token_char)
+            || value.chars().any(|c| c.is_control() && c != '\t')
+        {
+            println!(
+                "Error: invalid header `{}`. Expected <name>: <value>.",
+                spec
+            );
+            process::exit(1);
+        }
+        match name.to_lowercase().as_str() {
+            "content-length" | "transfer-encoding" | "connection" => {
+                println!("Error: the {} header can't be set with --header.", name);
+                process::exit(1);
+            }
+            _ => {}
+        }
+        headers.push((name.to_string(), value.to_string()));
+    }
+    headers
+}
+
+pub fn read_denied_ips(opts: &types::Opts) -> Vec<IpAddr> {
+    let mut ips = Vec::new();
+    for ip in &opts.deny_ips {
+        match ip.parse() {
+            Ok(ip) => ips.push(ip),
+            Err(_) => {
+                println!("Error: invalid IP address `{}` given to --deny-ip.", ip);
+                process::exit(1);
+            }
+        }
+    }
+    ips
+}
+
+pub fn open_audit_log(opts: &types::Opts) -> Option<AuditLog> {
+    let path = opts.audit_log.as_ref()?;
+    match AuditLog::open(Path::new(path), opts.audit_log_max_size) {
+        Ok(log) => Some(log),
+        Err(e) => {
+            println!("Error: could not open audit log {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+pub fn read_error_template(opts: &types::Opts) -> Option<String> {
+    let path = opts.error_template.as_ref()?;
+    match fs::read_to_string(path) {
+        Ok(template) => Some(template),
+        Err(e) => {
+            println!("Error: could not read error template {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+pub fn read_listing_css(opts: &types::Opts) -> Option<String> {
+    let path = opts.listing_css.as_ref()?;
+    match fs::read_to_string(path) {
+        Ok(css) => Some(css),
+        Err(e) => {
+            println!("Error: could not read listing stylesheet {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+pub fn read_overlays(opts: &types::Opts) -> Vec<PathBuf> {
+    let mut overlays = Vec::new();
+    for dir in &opts.overlays {
+        match fs::canonicalize(dir) {
+            Ok(path) if path.is_dir() => overlays.push(path),
+            _ => {
+                println!("Error: overlay {} is not a directory.", dir);
+                process::exit(1);
+            }
+        }
+    }
+    overlays
+}
+
+pub fn read_virtual_files(opts: &types::Opts) -> HashMap<String, Vec<u8>> {
+    let mut files = HashMap::new();
+    for spec in &opts.virtual_files {
+        let eq_ind = match spec.find('=') {
+            Some(i) => i,
+            None => {
+                println!(
+                    "Error: invalid virtual file `{}`. Expected <url-path>=<file>.",
+                    spec
+                );
+                process::exit(1);
+            }
+        };
+        let (url_path, file_path) = (&spec[..eq_ind], &spec[eq_ind + 1..]);
+        let data = match fs::read(file_path) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Error: could not read virtual file {}: {}", file_path, e);
+                process::exit(1);
+            }
+        };
+        let url_path = if url_path.starts_with("/") {
+            url_path.to_string()
+        } else {
+            format!("/{}", url_path)
+        };
+        files.insert(url_path, data);
+    }
+    files
+}
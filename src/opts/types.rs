@@ -1,16 +1,135 @@
 use clap::Clap;
+use std::str::FromStr;
+
+// A minimum transfer rate, given on the command line as <bytes>/<seconds>.
+#[derive(Clone, Copy)]
+pub struct RequestRate {
+    pub bytes: usize,
+    pub seconds: u64,
+}
+
+impl FromStr for RequestRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RequestRate, String> {
+        let err = || format!("Expected <bytes>/<seconds>, got `{}`", s);
+        let slash = s.find('/').ok_or_else(err)?;
+        let bytes = s[..slash].trim().parse().map_err(|_| err())?;
+        let seconds = s[slash + 1..].trim().parse().map_err(|_| err())?;
+        if seconds == 0 {
+            return Err(err());
+        }
+        Ok(RequestRate {
+            bytes: bytes,
+            seconds: seconds,
+        })
+    }
+}
+
+// Forces a Content-Type for request paths matching a glob, given on the command line as
+// <glob>:<type>.
+#[derive(Clone)]
+pub struct ContentTypeRule {
+    pub glob: String,
+    pub content_type: String,
+}
+
+impl FromStr for ContentTypeRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ContentTypeRule, String> {
+        // Types can't contain a ':', so split on the last one.
+        let colon = s
+            .rfind(':')
+            .ok_or_else(|| format!("Expected <glob>:<type>, got `{}`", s))?;
+        let (glob, content_type) = (s[..colon].trim(), s[colon + 1..].trim());
+        if glob.is_empty() || content_type.is_empty() {
+            return Err(format!("Expected <glob>:<type>, got `{}`", s));
+        }
+        Ok(ContentTypeRule {
+            glob: glob.to_string(),
+            content_type: content_type.to_string(),
+        })
+    }
+}
+
+impl ContentTypeRule {
+    pub fn matches(&self, path: &str) -> bool { glob_matches(&self.glob, path) }
+}
+
+// Matches `path` against `glob`, where '*' matches any run of characters, including '/'.
+pub fn glob_matches(glob: &str, path: &str) -> bool {
+    let (glob, path) = (glob.as_bytes(), path.as_bytes());
+    let (mut g, mut p) = (0, 0);
+    // Position of the last '*' seen, and of the path when we reached it
+    let mut backtrack: Option<(usize, usize)> = None;
+    while p < path.len() {
+        if g < glob.len() && glob[g] == b'*' {
+            backtrack = Some((g, p));
+            g += 1;
+        } else if g < glob.len() && glob[g] == path[p] {
+            g += 1;
+            p += 1;
+        } else if let Some((star, start)) = backtrack {
+            // Let the last '*' swallow one more character and try again.
+            g = star + 1;
+            p = start + 1;
+            backtrack = Some((star, start + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|&c| c == b'*')
+}
+
+// How requests are written to the log
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Expected `text` or `json`, got `{}`", s)),
+        }
+    }
+}
 
 #[derive(Clap, Clone)]
 #[clap(version = "0.2.1", author = "James Houghton <jamesthoughton@gmail.com")]
 pub struct Opts {
     #[clap(short, long, default_value = ".")]
     pub directory: String,
-    #[clap(short, long, default_value = "80")]
+    #[clap(
+        short,
+        long,
+        default_value = "80",
+        about = "Port to listen on. Use 0 to have one picked automatically."
+    )]
     pub port: u16,
     #[clap(short = 'm', long, default_value = "0.0.0.0")]
     pub hostmask: String,
+    #[clap(
+        long = "inherit-fd",
+        about = "Serve connections from this already listening socket, as passed by a \
+                 superserver, instead of binding one. --port and --hostmask are then ignored. A \
+                 socket passed by systemd socket activation is used without this."
+    )]
+    pub inherit_fd: Option<i32>,
     #[clap(short, long = "upload", about = "Enable uploading capabilities")]
     pub uploading_enabled: bool,
+    #[clap(
+        long = "no-upload-form",
+        about = "Leave the upload form out of directory listings, while still accepting uploads, \
+                 e.g. from scripts."
+    )]
+    pub no_upload_form: bool,
     #[clap(long = "nodirs", about = "Disable directory listings")]
     pub disable_directory_listings: bool,
     #[clap(
@@ -19,6 +138,17 @@ pub struct Opts {
                  enabled."
     )]
     pub start_disabled: bool,
+    #[clap(
+        long = "start-reads-paused",
+        about = "Start with downloads and other non-upload requests paused. Uploads are still \
+                 accepted until paused separately."
+    )]
+    pub start_reads_paused: bool,
+    #[clap(
+        long = "start-uploads-paused",
+        about = "Start with uploads paused. Other requests are still served."
+    )]
+    pub start_uploads_paused: bool,
     #[clap(
         short = 'r',
         long = "ui-refresh-rate",
@@ -34,6 +164,37 @@ pub struct Opts {
         default_value = "0"
     )]
     pub size_limit: usize,
+    #[clap(
+        long = "max-discard-bytes",
+        about = "When a form upload fails, read at most this many more bytes of it before \
+                 responding and closing the connection. Browsers only show the error once the \
+                 whole upload has been read, so they may not show it. Likewise, a connection \
+                 isn't kept alive past a request with more than this much of its body still to \
+                 come, so an unwanted body is never skipped past this limit. Specify 0 for no \
+                 limit.",
+        default_value = "0"
+    )]
+    pub max_discard_bytes: usize,
+    #[clap(
+        long = "strict-boundary",
+        about = "Reject form uploads whose multipart boundary doesn't follow RFC 2046 with 400 \
+                 Bad Request, instead of trying to make sense of it."
+    )]
+    pub strict_boundary: bool,
+    #[clap(
+        long = "max-concurrent-uploads",
+        about = "Form uploads that can be in progress at once. Others are turned away with 429 \
+                 Too Many Requests until one finishes. Specify 0 for no limit.",
+        default_value = "0"
+    )]
+    pub max_concurrent_uploads: usize,
+    #[clap(
+        long = "max-uri-length",
+        about = "Longest request target, in bytes, that will be served. Longer ones get 414 URI \
+                 Too Long. Specify 0 to only be limited by the request header size.",
+        default_value = "0"
+    )]
+    pub max_uri_length: usize,
     #[clap(
         long = "index-file",
         about = "Index page filename. When rendering a directory, render this file instead.",
@@ -51,4 +212,340 @@ pub struct Opts {
                  path."
     )]
     pub no_append_slash: bool,
+    #[clap(
+        long = "virtual-file",
+        about = "Serve the contents of a file from memory at the given path, in the form \
+                 <url-path>=<file>. The file is read once at startup. May be repeated.",
+        number_of_values = 1
+    )]
+    pub virtual_files: Vec<String>,
+    #[clap(
+        long = "deny-dotdot-encoded",
+        about = "Reject requests whose decoded path contains a '..' segment or a percent-encoded \
+                 sequence, such as a double-encoded traversal attempt."
+    )]
+    pub deny_dotdot_encoded: bool,
+    #[clap(
+        long = "sort-files-first",
+        about = "In directory listings, list files before directories."
+    )]
+    pub sort_files_first: bool,
+    #[clap(
+        long = "sort-mixed",
+        about = "In directory listings, sort files and directories together alphabetically."
+    )]
+    pub sort_mixed: bool,
+    #[clap(
+        long = "bind-retry",
+        about = "In seconds, how long to keep retrying if the port is already in use. Useful when \
+                 a previous instance is still shutting down.",
+        default_value = "0"
+    )]
+    pub bind_retry: u64,
+    #[clap(
+        long = "no-keepalive",
+        about = "Close every connection after its first response, whatever the client asks for."
+    )]
+    pub no_keepalive: bool,
+    #[clap(
+        long = "mmap",
+        about = "Serve files from memory mappings rather than reading them. This saves read calls \
+                 when small files are served repeatedly, at the cost of page faults. Unsafe for \
+                 files that may be truncated while being served: the server is killed by SIGBUS \
+                 if that happens at the wrong moment."
+    )]
+    pub mmap: bool,
+    #[clap(
+        long = "backlog",
+        about = "How many connections may wait to be accepted. Raise this if bursts of clients \
+                 are being refused.",
+        default_value = "128"
+    )]
+    pub backlog: usize,
+    #[clap(
+        long = "show-hits",
+        about = "Show how many times each file has been downloaded in directory listings. Counts \
+                 are kept in memory and reset when the server restarts."
+    )]
+    pub show_hits: bool,
+    #[clap(
+        long = "enable-metrics",
+        about = "Serve request, traffic, connection and upload counters at /_metrics, in the \
+                 Prometheus text format."
+    )]
+    pub enable_metrics: bool,
+    #[clap(
+        long = "show-perms",
+        about = "Show each entry's mode, owner and group in directory listings."
+    )]
+    pub show_perms: bool,
+    #[clap(
+        long = "show-dir-counts",
+        about = "Show how many entries each subdirectory holds in directory listings. Left out of \
+                 listings with more than 1000 entries, where counting would slow them down."
+    )]
+    pub show_dir_counts: bool,
+    #[clap(
+        long = "show-readme",
+        about = "Show the README.md or README.txt in a directory as plain text below its listing."
+    )]
+    pub show_readme: bool,
+    #[clap(
+        long = "thumbnails",
+        about = "Show previews of images in directory listings. Images are sent whole and scaled \
+                 down by the browser."
+    )]
+    pub thumbnails: bool,
+    #[clap(
+        long = "listing-css",
+        about = "A stylesheet to include in directory listings after the built-in style, so that \
+                 its rules take precedence. It is included as it is, so it must be trusted."
+    )]
+    pub listing_css: Option<String>,
+    #[clap(
+        long = "dark-mode",
+        about = "Style directory listings with light text on a dark background."
+    )]
+    pub dark_mode: bool,
+    #[clap(
+        long = "listing-md5-limit",
+        about = "How many .md5sum sidecars to read when rendering a directory listing. Digests \
+                 beyond this aren't shown, so that a directory with many sidecars on a slow disk \
+                 can't hold up the server. All are read if this isn't given."
+    )]
+    pub listing_md5_limit: Option<usize>,
+    #[clap(
+        long = "zip",
+        about = "Allow downloading a directory as a ZIP archive by adding ?zip to its URL. A link \
+                 is added to directory listings."
+    )]
+    pub zip_downloads: bool,
+    #[clap(
+        long = "upload-token",
+        about = "Only accept uploads, and only show the upload form, when the request carries \
+                 this token in a `token` query parameter or an X-Upload-Token header."
+    )]
+    pub upload_token: Option<String>,
+    #[clap(
+        long = "redirect-file-slash",
+        about = "When a file is requested with a trailing '/', redirect to the path without it \
+                 instead of responding with 404."
+    )]
+    pub redirect_file_slash: bool,
+    #[clap(
+        long = "allow-fifo",
+        about = "Serve named pipes (FIFOs), streaming whatever is written to them until the \
+                 writer closes them."
+    )]
+    pub allow_fifo: bool,
+    #[clap(
+        long = "min-request-rate",
+        about = "Drop connections that send their request headers slower than this, given as \
+                 <bytes>/<seconds>. For example, 100/10 drops a connection that sends fewer than \
+                 100 bytes in any 10 second period while a request is being read."
+    )]
+    pub min_request_rate: Option<RequestRate>,
+    #[clap(
+        long = "global-rate-limit",
+        about = "Send responses no faster than this many bytes per second, across all connections \
+                 together. Specify 0 for no limit.",
+        default_value = "0"
+    )]
+    pub global_rate_limit: u64,
+    #[clap(
+        long = "history-size",
+        about = "Number of entries kept in the interface's request history",
+        default_value = "50"
+    )]
+    pub history_size: usize,
+    #[clap(
+        long,
+        about = "Switch to this user (name or uid) once the port has been bound. Without --group, \
+                 the user's primary group is used."
+    )]
+    pub user: Option<String>,
+    #[clap(
+        long,
+        about = "Switch to this group (name or gid) once the port has been bound."
+    )]
+    pub group: Option<String>,
+    #[clap(
+        long = "upload-temp-dir",
+        about = "Write form uploads to this directory while they are in progress, and move them \
+                 into place once complete. May be on a different filesystem."
+    )]
+    pub upload_temp_dir: Option<String>,
+    #[clap(
+        long = "upload-mkdir",
+        about = "Create the directory an upload is made to, and any missing parents, if it \
+                 doesn't exist yet. Only directories inside --directory are created."
+    )]
+    pub upload_mkdir: bool,
+    #[clap(
+        long = "log-format",
+        about = "Format of the request log: `text`, or `json` for one JSON object per request \
+                 with the fields ts, ip, method, path, status, bytes_sent, duration_ms and \
+                 request_id. JSON entries are written once the response has been sent. Other \
+                 lines are JSON objects too, with an `event` field saying what they are.",
+        default_value = "text"
+    )]
+    pub log_format: LogFormat,
+    #[clap(
+        long = "slow-request-ms",
+        about = "Flag requests that take longer than this many milliseconds to serve. Text logs \
+                 get a second line for them, starting with SLOW, once the response has been sent; \
+                 JSON entries get a `slow` field."
+    )]
+    pub slow_request_ms: Option<u64>,
+    #[clap(
+        long = "audit-log",
+        about = "Append a line to this file for every download and upload, once it is over, as a \
+                 JSON object with the fields ts, ip, method, path, status, bytes_sent, \
+                 bytes_received and files."
+    )]
+    pub audit_log: Option<String>,
+    #[clap(
+        long = "audit-log-max-size",
+        about = "Once the --audit-log would grow past this many bytes, move it to <path>.1, \
+                 replacing any earlier one, and start a new one. Specify 0 to never do so.",
+        default_value = "0"
+    )]
+    pub audit_log_max_size: u64,
+    #[clap(
+        long = "generate-md5",
+        about = "When a file without a .md5sum sidecar is first downloaded, compute its MD5 in \
+                 the background and write the sidecar next to it, so that listings show it. Files \
+                 in directories that aren't writable are skipped."
+    )]
+    pub generate_md5: bool,
+    #[clap(
+        long = "content-md5",
+        about = "Send a Content-MD5 header with files whose digest is known from an up-to-date \
+                 .md5sum sidecar. Small files without one are digested when they are served."
+    )]
+    pub content_md5: bool,
+    #[clap(
+        long = "gzip-level",
+        about = "Compress responses with gzip at this level, from 0 (fastest) to 9 (smallest), \
+                 for clients that accept it. Responses are sent uncompressed if this isn't given."
+    )]
+    pub gzip_level: Option<u32>,
+    #[clap(
+        long = "brotli-level",
+        about = "Compress responses with Brotli at this level, from 0 (fastest) to 11 (smallest), \
+                 for clients that accept it. When gzip is also enabled, the client's preference \
+                 decides which is used. Levels 10 and 11 are much slower, so responses over 64 \
+                 KiB are compressed at level 9 at most."
+    )]
+    pub brotli_level: Option<u32>,
+    #[clap(
+        long = "gzip-min-size",
+        about = "In bytes, how large a response must be for --gzip-level or --brotli-level to \
+                 compress it. Smaller ones gain too little to be worth it.",
+        default_value = "1024"
+    )]
+    pub gzip_min_size: usize,
+    #[clap(
+        long = "default-charset",
+        about = "Charset to declare in the Content-Type of HTML files that are served, or `none` \
+                 to leave it out, e.g. for files in legacy encodings that declare their own. \
+                 Pages that hypershare generates are always UTF-8.",
+        default_value = "utf-8"
+    )]
+    pub default_charset: String,
+    #[clap(
+        long = "content-type",
+        about = "Serve request paths matching a glob with the given Content-Type, in the form \
+                 <glob>:<type>. '*' matches anything, including '/', e.g. \
+                 `*.wasm:application/wasm` or `/docs/README:text/plain`. May be repeated; the \
+                 first match wins.",
+        number_of_values = 1
+    )]
+    pub content_types: Vec<ContentTypeRule>,
+    #[clap(
+        long = "hide",
+        about = "Leave files and directories whose names match a glob out of directory listings, \
+                 e.g. `*.tmp` or `*~`. '*' matches any run of characters. They can still be \
+                 requested directly unless --hide-hard is given. May be repeated.",
+        number_of_values = 1
+    )]
+    pub hide: Vec<String>,
+    #[clap(
+        long = "hide-hard",
+        about = "Respond to requests for anything hidden with --hide, or inside it, as if it \
+                 didn't exist."
+    )]
+    pub hide_hard: bool,
+    #[clap(
+        long = "no-symlink-root",
+        about = "Refuse to start if --directory is itself a symlink, instead of warning and \
+                 serving whatever it points to."
+    )]
+    pub no_symlink_root: bool,
+    #[clap(
+        long = "vhost",
+        about = "Serve a different directory to requests for a given host, in the form \
+                 <host>:<dir>, e.g. `a.local:/srv/a`. Requests for other hosts, or without a Host \
+                 header, are served from --directory. May be repeated.",
+        number_of_values = 1
+    )]
+    pub vhosts: Vec<String>,
+    #[clap(
+        long = "cors-origin",
+        about = "Allow cross-origin requests from this origin, or from any with `*`. May be \
+                 repeated. The other --cors-* options have no effect without it.",
+        number_of_values = 1
+    )]
+    pub cors_origins: Vec<String>,
+    #[clap(
+        long = "cors-methods",
+        about = "Methods to allow in answers to CORS preflights, e.g. `GET, POST`. Defaults to \
+                 every method the server currently accepts."
+    )]
+    pub cors_methods: Option<String>,
+    #[clap(
+        long = "cors-headers",
+        about = "Request headers to allow in answers to CORS preflights, e.g. `Content-Type, \
+                 X-Upload-Token`."
+    )]
+    pub cors_headers: Option<String>,
+    #[clap(
+        long = "cors-expose-headers",
+        about = "Response headers cross-origin scripts may read, e.g. `X-Request-Id`."
+    )]
+    pub cors_expose_headers: Option<String>,
+    #[clap(
+        long = "cors-credentials",
+        about = "Allow cross-origin requests with credentials. Any `*` origin is answered with \
+                 the requesting origin instead, as browsers require."
+    )]
+    pub cors_credentials: bool,
+    #[clap(
+        long = "header",
+        about = "Add a header to every response, in the form `<name>: <value>`, e.g. \
+                 `X-Frame-Options: DENY`. May be repeated.",
+        number_of_values = 1
+    )]
+    pub headers: Vec<String>,
+    #[clap(
+        long = "deny-ip",
+        about = "Close connections from this IP address as soon as they are accepted. May be \
+                 repeated.",
+        number_of_values = 1
+    )]
+    pub deny_ips: Vec<String>,
+    #[clap(
+        long = "overlay",
+        about = "Look for files in this directory before --directory, and merge its listings over \
+                 those of --directory, with its entries taking precedence. May be repeated, \
+                 highest priority first. Uploads still go to --directory.",
+        number_of_values = 1
+    )]
+    pub overlays: Vec<String>,
+    #[clap(
+        long = "error-template",
+        about = "HTML file to render error pages with. `{{code}}`, `{{message}}` and `{{detail}}` \
+                 are replaced with the status code, its description and any further details."
+    )]
+    pub error_template: Option<String>,
 }
@@ -0,0 +1,57 @@
+use nix::unistd::{self, Gid, Group, Uid, User};
+
+// Looks up a user by name, or accepts a numeric uid as-is.
+pub fn resolve_user(name: &str) -> Result<User, String> {
+    let user = match name.parse::<u32>() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+        Err(_) => User::from_name(name),
+    };
+    match user {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(format!("unknown user `{}`", name)),
+        Err(e) => Err(format!("could not look up user `{}`: {}", name, e)),
+    }
+}
+
+// Looks up a group by name, or accepts a numeric gid as-is.
+pub fn resolve_group(name: &str) -> Result<Gid, String> {
+    let group = match name.parse::<u32>() {
+        Ok(gid) => Group::from_gid(Gid::from_raw(gid)),
+        Err(_) => Group::from_name(name),
+    };
+    match group {
+        Ok(Some(group)) => Ok(group.gid),
+        Ok(None) => Err(format!("unknown group `{}`", name)),
+        Err(e) => Err(format!("could not look up group `{}`: {}", name, e)),
+    }
+}
+
+// Switches to the given user and/or group. Without a group, the user's primary group is used.
+// This must happen after anything that needs the original privileges, like binding to a low
+// port.
+pub fn drop_privileges(user: Option<&str>, group: Option<&str>) -> Result<(), String> {
+    let user = match user {
+        Some(name) => Some(resolve_user(name)?),
+        None => None,
+    };
+    let gid = match (group, &user) {
+        (Some(name), _) => Some(resolve_group(name)?),
+        (None, Some(user)) => Some(user.gid),
+        (None, None) => None,
+    };
+
+    // The group has to be changed first, as we may no longer be allowed to once the user has
+    // been changed.
+    if let Some(gid) = gid {
+        if unistd::geteuid().is_root() {
+            unistd::setgroups(&[gid])
+                .map_err(|e| format!("could not set supplementary groups: {}", e))?;
+        }
+        unistd::setgid(gid).map_err(|e| format!("could not switch to group {}: {}", gid, e))?;
+    }
+    if let Some(user) = user {
+        unistd::setuid(user.uid)
+            .map_err(|e| format!("could not switch to user {}: {}", user.name, e))?;
+    }
+    Ok(())
+}
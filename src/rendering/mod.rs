@@ -1,10 +1,25 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{MetadataExt, OpenOptionsExt},
+    },
+    path::{Path, PathBuf},
+};
 
-use std::{collections::HashMap, io::Read};
+use nix::{
+    libc::O_NOFOLLOW,
+    unistd::{Gid, Group, Uid, User},
+};
 
-use crate::http::http_core;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+};
 
-const GIT_HASH: &'static str = env!("GIT_HASH");
+use crate::{http::http_core, opts::types::glob_matches, timestamp};
+
+pub const GIT_HASH: &'static str = env!("GIT_HASH");
 
 struct HtmlElement {
     tag: &'static str,
@@ -59,7 +74,7 @@ impl HtmlElement {
         let attributes = if self.attributes.len() > 0 {
             let mut s = format!("");
             for (attr, val) in &self.attributes {
-                s.push_str(&format!(" {}='{}'", attr, val));
+                s.push_str(&format!(" {}='{}'", attr, xml_escape(val)));
             }
             s
         } else {
@@ -109,21 +124,66 @@ fn generate_default_footer() -> HtmlElement {
     footer
 }
 
-fn generate_href(relative_path: &str, fname: &str) -> String {
-    if relative_path.ends_with("/") {
-        format!("/{}{}", relative_path, fname)
+// `href_path` is the directory's path from `encode_path`, so it is already percent-encoded.
+fn generate_href(href_path: &str, fname: &str) -> String {
+    if href_path.ends_with("/") {
+        format!("/{}{}", href_path, fname)
     } else {
         format!(
             "/{}{}{}",
-            relative_path,
-            if relative_path.len() > 0 { "/" } else { "" },
+            href_path,
+            if href_path.len() > 0 { "/" } else { "" },
             fname
         )
     }
 }
 
-fn generate_md5_table(paths: &Vec<std::fs::DirEntry>) -> HashMap<String, String> {
-    let mut res = HashMap::<String, String>::new();
+// `path`, relative to the root, as it appears in a URL, with each component percent-encoded.
+pub fn encode_path(path: &Path) -> String {
+    let components: Vec<String> = path
+        .iter()
+        .map(|component| percent_encode(component.as_bytes()))
+        .collect();
+    components.join("/")
+}
+
+fn percent_encode(s: &[u8]) -> String {
+    let mut res = String::with_capacity(s.len());
+    for &b in s {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                res.push(b as char)
+            }
+            _ => res.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    res
+}
+
+// Anything in a .md5sum sidecar longer than this is too long to be a digest
+const MD5_SIDECAR_MAX_LEN: u64 = 34;
+
+// The contents of a .md5sum sidecar. Anything too long to be a digest is ignored.
+pub fn read_md5_sidecar(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    if len > MD5_SIDECAR_MAX_LEN {
+        return None;
+    }
+    let mut contents = String::with_capacity(len as usize);
+    file.read_to_string(&mut contents).ok()?;
+    Some(contents)
+}
+
+// Maps the names of the .md5sum sidecars among `paths` to their contents. Only the first
+// `read_limit` are read, as reading every one could hold up the server on a slow disk; the rest
+// are recognized by their name and size alone, and map to None.
+fn generate_md5_table(
+    paths: &Vec<std::fs::DirEntry>,
+    read_limit: Option<usize>,
+) -> HashMap<String, Option<String>> {
+    let mut res = HashMap::<String, Option<String>>::new();
+    let mut read = 0;
     for entry in paths {
         let metadata = match entry.metadata() {
             Ok(meta) => meta,
@@ -138,42 +198,251 @@ fn generate_md5_table(paths: &Vec<std::fs::DirEntry>) -> HashMap<String, String>
             Some(ext) => ext.to_string_lossy() == "md5sum",
             None => false,
         };
-        if !is_sum {
+        if !is_sum || metadata.len() > MD5_SIDECAR_MAX_LEN {
             continue;
         }
-        if metadata.len() > 34 {
+        let name = match entry.path().file_name().unwrap().to_str() {
+            Some(s) => s.to_string(),
+            None => continue,
+        };
+        if read_limit.map_or(false, |limit| read >= limit) {
+            res.insert(name, None);
             continue;
         }
-        if let Ok(mut file) = fs::File::open(entry.path()) {
-            let mut contents = String::with_capacity(metadata.len() as usize);
-            if file.read_to_string(&mut contents).is_ok() {
-                if let Some(s) = entry.path().file_name().unwrap().to_str() {
-                    res.insert(s.to_string(), contents);
-                }
-            }
+        read += 1;
+        if let Some(contents) = read_md5_sidecar(&entry.path()) {
+            res.insert(name, Some(contents));
         }
     }
     res
 }
 
-fn generate_dir_table(path: &Path, relative_path: &str) -> HtmlElement {
-    if let Ok(paths) = fs::read_dir(path) {
+#[derive(Clone, Copy, PartialEq)]
+pub enum ListingSort {
+    DirsFirst,
+    FilesFirst,
+    Mixed,
+}
+
+pub struct ListingOptions<'a> {
+    pub show_form: bool,
+    pub sort: ListingSort,
+    pub hits: Option<&'a HashMap<PathBuf, u64>>,
+    pub zip_link: bool,
+    // Show each entry's mode, owner and group
+    pub show_perms: bool,
+    // Show how many entries each subdirectory holds
+    pub dir_counts: bool,
+    // Show the directory's README below the listing
+    pub readme: bool,
+    // Show images as small previews, scaled down by the browser
+    pub thumbnails: bool,
+    // Passed along in the upload form's action so the POST is accepted
+    pub upload_token: Option<&'a str>,
+    // How many .md5sum sidecars to read for the listing, if limited
+    pub md5_limit: Option<usize>,
+    pub dark_mode: bool,
+    // From --listing-css, added after the built-in style so that it can override it
+    pub css: Option<&'a str>,
+    // Globs from --hide. Entries whose names match are left out.
+    pub hide: &'a [String],
+}
+
+pub fn is_hidden(name: &str, hide: &[String]) -> bool {
+    hide.iter().any(|glob| glob_matches(glob, name))
+}
+
+// Subdirectories are only counted in listings up to this long, since each count reads a whole
+// directory.
+const DIR_COUNTS_MAX_ENTRIES: usize = 1000;
+
+// Files with these extensions get a preview when --thumbnails is given.
+const THUMBNAIL_EXTENSIONS: [&str; 8] =
+    ["apng", "avif", "bmp", "gif", "jpeg", "jpg", "png", "webp"];
+
+fn is_image(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => THUMBNAIL_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+// Looked for in this order when --show-readme is given. Only the start of a longer README is
+// shown, so a huge one can't hold up the server.
+const README_NAMES: [&str; 2] = ["README.md", "README.txt"];
+const README_MAX_LEN: u64 = 64 * 1024;
+
+// The README among `paths`, merged like a listing, highest priority first. Symlinked READMEs
+// are skipped, since nothing checks where they lead and they could point outside the root.
+fn read_readme(paths: &[PathBuf]) -> Option<String> {
+    for name in &README_NAMES {
+        for dir in paths {
+            let path = dir.join(name);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_file() => {}
+                _ => continue,
+            }
+            let mut contents = Vec::new();
+            // O_NOFOLLOW in case it was swapped for a symlink since the check
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(O_NOFOLLOW)
+                .open(&path)
+                .ok()?;
+            file.take(README_MAX_LEN).read_to_end(&mut contents).ok()?;
+            return Some(String::from_utf8_lossy(&contents).into_owned());
+        }
+    }
+    None
+}
+
+const DARK_MODE_CSS: &str = r#"
+    body { background-color: #1e1e1e; color: #d4d4d4; }
+    a { color: #8ab4f8; }
+    a:visited { color: #c58af9; }
+    hr { border-color: #444444; }
+    "#;
+
+// A mode as `ls -l` shows it, e.g. `drwxr-xr-x`.
+fn symbolic_mode(mode: u32) -> String {
+    let file_type = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o010000 => 'p',
+        0o140000 => 's',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        _ => '-',
+    };
+    let mut s = String::with_capacity(10);
+    s.push(file_type);
+    // Owner, group and others, each with the bit that changes how its execute bit is shown
+    let classes = [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')];
+    for &(shift, special_bit, special) in &classes {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(match (bits & 0o1 != 0, mode & special_bit != 0) {
+            (true, true) => special,
+            (false, true) => special.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    s
+}
+
+// Names for the ids in a listing, looked up once each. Ids without a name are shown as numbers.
+struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl OwnerNames {
+    fn new() -> OwnerNames {
+        OwnerNames {
+            users: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    fn user(&mut self, uid: u32) -> &str {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| match User::from_uid(Uid::from_raw(uid)) {
+                Ok(Some(user)) => user.name,
+                _ => uid.to_string(),
+            })
+    }
+
+    fn group(&mut self, gid: u32) -> &str {
+        self.groups
+            .entry(gid)
+            .or_insert_with(|| match Group::from_gid(Gid::from_raw(gid)) {
+                Ok(Some(group)) => group.name,
+                _ => gid.to_string(),
+            })
+    }
+}
+
+fn sort_entries(paths: &mut Vec<std::fs::DirEntry>, sort: ListingSort) {
+    let is_dir = |entry: &std::fs::DirEntry| match entry.metadata() {
+        Ok(meta) => meta.is_dir(),
+        _ => false,
+    };
+    match sort {
+        ListingSort::DirsFirst => paths.sort_by_key(|p| (!is_dir(p), p.file_name())),
+        ListingSort::FilesFirst => paths.sort_by_key(|p| (is_dir(p), p.file_name())),
+        ListingSort::Mixed => paths.sort_by_key(|p| p.file_name()),
+    }
+}
+
+// Reads the merged entries of `paths`. Where more than one has an entry with the same name, the
+// earliest wins.
+fn read_sorted_entries(paths: &[PathBuf], sort: ListingSort) -> Option<Vec<std::fs::DirEntry>> {
+    let mut paths_vec: Vec<std::fs::DirEntry> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut any_read = false;
+    for path in paths {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        any_read = true;
+        for entry in entries.filter_map(Result::ok) {
+            if seen.insert(entry.file_name()) {
+                paths_vec.push(entry);
+            }
+        }
+    }
+    if !any_read {
+        return None;
+    }
+    sort_entries(&mut paths_vec, sort);
+    Some(paths_vec)
+}
+
+// `entries` without the .md5sum sidecars in `md5_table` and the ones hidden by --hide.
+fn shown_entries(
+    entries: Vec<std::fs::DirEntry>,
+    md5_table: &HashMap<String, Option<String>>,
+    hide: &[String],
+) -> Vec<std::fs::DirEntry> {
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            !md5_table.contains_key(&*name) && !is_hidden(&name, hide)
+        })
+        .collect()
+}
+
+// The entries a listing of `paths` shows, leaving out .md5sum sidecars and hidden entries.
+pub fn listed_entries(
+    paths: &[PathBuf],
+    sort: ListingSort,
+    hide: &[String],
+) -> Option<Vec<std::fs::DirEntry>> {
+    let paths_vec = read_sorted_entries(paths, sort)?;
+    // Only which entries are sidecars matters here, not what they say.
+    let md5_table = generate_md5_table(&paths_vec, Some(0));
+    Some(shown_entries(paths_vec, &md5_table, hide))
+}
+
+fn generate_dir_table(paths: &[PathBuf], href_path: &str, options: &ListingOptions) -> HtmlElement {
+    if let Some(all_entries) = read_sorted_entries(paths, options.sort) {
         let mut table = HtmlElement::new("table", HtmlStyle::CanHaveChildren);
-        let mut paths_vec: Vec<_> = paths.filter_map(Option::Some).map(|r| r.unwrap()).collect();
-        paths_vec.sort_by_key(|p| p.path());
-        let md5_table = generate_md5_table(&paths_vec);
+        let mut rows = 0;
+        let md5_table = generate_md5_table(&all_entries, options.md5_limit);
+        let paths_vec = shown_entries(all_entries, &md5_table, options.hide);
+        let mut owner_names = OwnerNames::new();
+        let dir_counts = options.dir_counts && paths_vec.len() <= DIR_COUNTS_MAX_ENTRIES;
         for entry in paths_vec {
             let fname = entry.file_name();
-            let fname_str = match fname.to_str() {
-                Some(f) => f,
-                _ => {
-                    continue;
-                }
-            };
-
-            if md5_table.contains_key(fname_str) {
-                continue;
-            }
+            // Names that aren't valid UTF-8 are shown approximately, but still linked to exactly.
+            let fname_lossy = fname.to_string_lossy();
+            let fname_str: &str = &fname_lossy;
 
             let mut tr = HtmlElement::new("tr", HtmlStyle::CanHaveChildren);
 
@@ -189,25 +458,49 @@ fn generate_dir_table(path: &Path, relative_path: &str) -> HtmlElement {
             let mut td_size = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
             let mut td_hash = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
 
-            // Add pre
-            let mut pre_type = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
-            pre_type.add_text(if meta.is_dir() {
-                "[DIR]".to_string()
+            let href = generate_href(href_path, &percent_encode(fname.as_bytes()));
+
+            // Images are shown in place of their type, linking to the full image.
+            if options.thumbnails && meta.is_file() && is_image(Path::new(&fname)) {
+                let mut img = HtmlElement::new("img", HtmlStyle::NoChildren);
+                img.add_attribute("src".to_string(), href.clone());
+                img.add_attribute("alt".to_string(), "[IMG]".to_string());
+                img.add_attribute("loading".to_string(), "lazy".to_string());
+                img.add_attribute(
+                    "style".to_string(),
+                    "display: block; margin: auto; max-width: 64px; max-height: 64px;".to_string(),
+                );
+                let mut a_img = HtmlElement::new("a", HtmlStyle::CanHaveChildren);
+                a_img.add_attribute("href".to_string(), href.clone());
+                a_img.add_child(img);
+                td_type.add_child(a_img);
             } else {
-                "[FILE]".to_string()
-            });
-            pre_type.add_attribute(
-                "style".to_string(),
-                "display: block; text-align: center;".to_string(),
-            );
-            td_type.add_child(pre_type);
+                // Add pre
+                let mut pre_type = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
+                pre_type.add_text(if meta.is_dir() {
+                    "[DIR]".to_string()
+                } else {
+                    "[FILE]".to_string()
+                });
+                pre_type.add_attribute(
+                    "style".to_string(),
+                    "display: block; text-align: center;".to_string(),
+                );
+                td_type.add_child(pre_type);
+            }
 
             // Add anchor
-            let href = generate_href(relative_path, fname_str);
             let mut a = HtmlElement::new("a", HtmlStyle::CanHaveChildren);
             a.add_attribute("href".to_string(), href);
-            a.add_text(fname_str.to_string());
+            a.add_text(xml_escape(fname_str));
             td_a.add_child(a);
+            if dir_counts && meta.is_dir() {
+                if let Ok(dir) = fs::read_dir(entry.path()) {
+                    let mut count = HtmlElement::new("span", HtmlStyle::CanHaveChildren);
+                    count.add_text(format!(" ({})", dir.count()));
+                    td_a.add_child(count);
+                }
+            }
 
             // Add size
             let mut pre_size = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
@@ -221,7 +514,7 @@ fn generate_dir_table(path: &Path, relative_path: &str) -> HtmlElement {
             td_size.add_child(pre_size);
 
             match md5_table.get(&format!("{}.md5sum", fname_str)) {
-                Some(data) => {
+                Some(Some(data)) => {
                     let mut pre = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
                     pre.add_text(format!("MD5: {}", data));
                     td_hash.add_child(pre);
@@ -233,7 +526,43 @@ fn generate_dir_table(path: &Path, relative_path: &str) -> HtmlElement {
             tr.add_child(td_size);
             tr.add_child(td_hash);
 
+            if options.show_perms {
+                let mut td_perms = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
+                let mut pre = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
+                let user = owner_names.user(meta.uid()).to_string();
+                pre.add_text(xml_escape(&format!(
+                    "{} {} {}",
+                    symbolic_mode(meta.mode()),
+                    user,
+                    owner_names.group(meta.gid())
+                )));
+                td_perms.add_child(pre);
+                tr.add_child(td_perms);
+            }
+
+            if let Some(hits) = options.hits {
+                let mut td_hits = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
+                if meta.is_file() {
+                    let mut pre = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
+                    pre.add_text(format!(
+                        "Hits: {}",
+                        hits.get(&entry.path()).cloned().unwrap_or(0)
+                    ));
+                    td_hits.add_child(pre);
+                }
+                tr.add_child(td_hits);
+            }
+
             table.add_child(tr);
+            rows += 1;
+        }
+        if rows == 0 {
+            // An empty table looks like the listing failed to load.
+            let mut p = HtmlElement::new("p", HtmlStyle::CanHaveChildren);
+            let mut i = HtmlElement::new("i", HtmlStyle::CanHaveChildren);
+            i.add_text("This folder is empty.".to_string());
+            p.add_child(i);
+            return p;
         }
         table
     } else {
@@ -243,13 +572,19 @@ fn generate_dir_table(path: &Path, relative_path: &str) -> HtmlElement {
     }
 }
 
-pub fn render_directory(relative_path: &str, path: &Path, show_form: bool) -> String {
+// `paths` are the directories to list, merged, highest priority first.
+pub fn render_directory(
+    relative_path: &str,
+    href_path: &str,
+    paths: &[PathBuf],
+    options: &ListingOptions,
+) -> String {
     let mut html = HtmlElement::new("html", HtmlStyle::CanHaveChildren);
     html.add_attribute("lang".to_string(), "en".to_string());
     let mut head = HtmlElement::new("head", HtmlStyle::CanHaveChildren);
 
     let mut title = HtmlElement::new("title", HtmlStyle::CanHaveChildren);
-    title.add_text(format!("hypershare: /{}", relative_path));
+    title.add_text(format!("hypershare: /{}", xml_escape(relative_path)));
     head.add_child(title);
 
     head.add_child(create_viewport_meta());
@@ -260,7 +595,9 @@ pub fn render_directory(relative_path: &str, path: &Path, show_form: bool) -> St
     tr { font-family: monospace; }
     pre { margin-top: 0px; margin-bottom: 0px }
     "#
-        .to_string(),
+        .to_string()
+            + if options.dark_mode { DARK_MODE_CSS } else { "" }
+            + options.css.unwrap_or(""),
     );
     head.add_child(style);
 
@@ -276,13 +613,16 @@ pub fn render_directory(relative_path: &str, path: &Path, show_form: bool) -> St
     head.add_child(link_favi);
     html.add_child(head);
 
-    h1.add_text(format!("Directory listing for /{}", relative_path));
+    h1.add_text(format!(
+        "Directory listing for /{}",
+        xml_escape(relative_path)
+    ));
     body.add_child(h1);
     body.add_child(HtmlElement::new("hr", HtmlStyle::NoChildren));
     let top_level = relative_path.len() == 0;
     if !top_level {
         let mut a = HtmlElement::new("a", HtmlStyle::CanHaveChildren);
-        let href = generate_href(relative_path, "..");
+        let href = generate_href(href_path, "..");
         a.add_attribute("href".to_string(), href);
         let mut i = HtmlElement::new("i", HtmlStyle::CanHaveChildren);
         i.add_text("Up a directory".to_string());
@@ -290,13 +630,38 @@ pub fn render_directory(relative_path: &str, path: &Path, show_form: bool) -> St
         body.add_child(a);
         body.add_child(HtmlElement::new("br", HtmlStyle::NoChildren));
     }
-    let table = generate_dir_table(path, relative_path);
+    if options.zip_link {
+        let mut a = HtmlElement::new("a", HtmlStyle::CanHaveChildren);
+        a.add_attribute("href".to_string(), "?zip".to_string());
+        let mut i = HtmlElement::new("i", HtmlStyle::CanHaveChildren);
+        i.add_text("Download as .zip".to_string());
+        a.add_child(i);
+        body.add_child(a);
+        body.add_child(HtmlElement::new("br", HtmlStyle::NoChildren));
+    }
+    let table = generate_dir_table(paths, href_path, options);
     body.add_child(table);
 
-    if show_form {
+    if options.readme {
+        if let Some(readme) = read_readme(paths) {
+            let mut pre = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
+            pre.add_class("readme");
+            pre.add_text(xml_escape(&readme));
+            body.add_child(HtmlElement::new("hr", HtmlStyle::NoChildren));
+            body.add_child(pre);
+        }
+    }
+
+    if options.show_form {
         let mut upload_form = HtmlElement::new("form", HtmlStyle::CanHaveChildren);
         upload_form.add_attribute("method".to_string(), "post".to_string());
         upload_form.add_attribute("enctype".to_string(), "multipart/form-data".to_string());
+        if let Some(token) = options.upload_token {
+            upload_form.add_attribute(
+                "action".to_string(),
+                format!("?token={}", percent_encode(token.as_bytes())),
+            );
+        }
         let mut file_input = HtmlElement::new("input", HtmlStyle::NoChildren);
         file_input.add_attribute("type".to_string(), "file".to_string());
         file_input.add_attribute("name".to_string(), "data".to_string());
@@ -318,6 +683,291 @@ pub fn render_directory(relative_path: &str, path: &Path, show_form: bool) -> St
     format!("<!DOCTYPE html>{}", html.render())
 }
 
+// One entry name per line, with a `/` after directories.
+pub fn render_directory_text(paths: &[PathBuf], sort: ListingSort, hide: &[String]) -> String {
+    let mut s = String::new();
+    for entry in listed_entries(paths, sort, hide).unwrap_or_default() {
+        s.push_str(&entry.file_name().to_string_lossy());
+        if entry.metadata().map_or(false, |meta| meta.is_dir()) {
+            s.push('/');
+        }
+        s.push('\n');
+    }
+    s
+}
+
+// Bounds on how much of the tree a search looks through, and how much it returns
+const SEARCH_MAX_DEPTH: usize = 16;
+const SEARCH_MAX_RESULTS: usize = 500;
+
+struct SearchMatch {
+    // Path relative to the served root
+    path: PathBuf,
+    meta: fs::Metadata,
+}
+
+// Walks the directories under `roots`, merged like a listing, collecting entries whose names
+// contain `term`, ignoring case. Symlinks are skipped, as they may lead outside of the served
+// directory or back into it. Returns whether the walk was cut short.
+fn find_matches(
+    roots: &[PathBuf],
+    term: &str,
+    sort: ListingSort,
+    hide: &[String],
+) -> (Vec<SearchMatch>, bool) {
+    let term = term.to_lowercase();
+    let mut matches = Vec::new();
+    let mut truncated = false;
+    let mut pending = vec![(PathBuf::new(), 0)];
+    'walk: while let Some((relative, depth)) = pending.pop() {
+        // A directory may only exist in some of the roots, or lead out of them in others.
+        let dirs: Vec<PathBuf> = roots
+            .iter()
+            .filter(|root| match fs::canonicalize(root.join(&relative)) {
+                Ok(dir) => dir.starts_with(root),
+                Err(_) => false,
+            })
+            .map(|root| root.join(&relative))
+            .collect();
+        let entries = match listed_entries(&dirs, sort, hide) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        // Pushed in reverse so subdirectories are searched in listing order.
+        for entry in entries.into_iter().rev() {
+            let meta = match fs::symlink_metadata(entry.path()) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            if meta.file_type().is_symlink() {
+                continue;
+            }
+            let path = relative.join(entry.file_name());
+            if meta.is_dir() {
+                if depth + 1 < SEARCH_MAX_DEPTH {
+                    pending.push((path.clone(), depth + 1));
+                } else {
+                    truncated = true;
+                }
+            }
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(&term)
+            {
+                if matches.len() == SEARCH_MAX_RESULTS {
+                    truncated = true;
+                    break 'walk;
+                }
+                matches.push(SearchMatch {
+                    path: path,
+                    meta: meta,
+                });
+            }
+        }
+    }
+    matches.sort_by(|a, b| a.path.cmp(&b.path));
+    (matches, truncated)
+}
+
+// A listing of everything under `roots` whose name contains `term`, leaving out hidden entries
+// and anything under them.
+pub fn render_search(roots: &[PathBuf], term: &str, sort: ListingSort, hide: &[String]) -> String {
+    let (matches, truncated) = find_matches(roots, term, sort, hide);
+
+    let mut html = HtmlElement::new("html", HtmlStyle::CanHaveChildren);
+    html.add_attribute("lang".to_string(), "en".to_string());
+    let mut head = HtmlElement::new("head", HtmlStyle::CanHaveChildren);
+
+    let mut title = HtmlElement::new("title", HtmlStyle::CanHaveChildren);
+    title.add_text(format!("hypershare: search for {}", xml_escape(term)));
+    head.add_child(title);
+
+    head.add_child(create_viewport_meta());
+
+    let mut style = HtmlElement::new("style", HtmlStyle::CanHaveChildren);
+    style.add_text(
+        r#"
+    tr { font-family: monospace; }
+    pre { margin-top: 0px; margin-bottom: 0px }
+    "#
+        .to_string(),
+    );
+    head.add_child(style);
+    html.add_child(head);
+
+    let mut body = HtmlElement::new("body", HtmlStyle::CanHaveChildren);
+    let mut h1 = HtmlElement::new("h1", HtmlStyle::CanHaveChildren);
+    h1.add_text(format!("Search results for {}", xml_escape(term)));
+    body.add_child(h1);
+    body.add_child(HtmlElement::new("hr", HtmlStyle::NoChildren));
+
+    if matches.is_empty() {
+        let mut p = HtmlElement::new("p", HtmlStyle::CanHaveChildren);
+        let mut i = HtmlElement::new("i", HtmlStyle::CanHaveChildren);
+        i.add_text("Nothing matched.".to_string());
+        p.add_child(i);
+        body.add_child(p);
+    } else {
+        let mut table = HtmlElement::new("table", HtmlStyle::CanHaveChildren);
+        for found in &matches {
+            let mut tr = HtmlElement::new("tr", HtmlStyle::CanHaveChildren);
+
+            let mut td_type = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
+            let mut pre_type = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
+            pre_type.add_text(if found.meta.is_dir() {
+                "[DIR]".to_string()
+            } else {
+                "[FILE]".to_string()
+            });
+            pre_type.add_attribute(
+                "style".to_string(),
+                "display: block; text-align: center;".to_string(),
+            );
+            td_type.add_child(pre_type);
+
+            let mut href = format!("/{}", encode_path(&found.path));
+            if found.meta.is_dir() {
+                href.push('/');
+            }
+            let mut td_a = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
+            let mut a = HtmlElement::new("a", HtmlStyle::CanHaveChildren);
+            a.add_attribute("href".to_string(), href);
+            a.add_text(xml_escape(&found.path.to_string_lossy()));
+            td_a.add_child(a);
+
+            let mut td_size = HtmlElement::new("td", HtmlStyle::CanHaveChildren);
+            let mut pre_size = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
+            if found.meta.is_file() {
+                pre_size.add_text(format!("{}", found.meta.len()));
+            }
+            pre_size.add_attribute(
+                "style".to_string(),
+                "display: block; text-align: right;".to_string(),
+            );
+            td_size.add_child(pre_size);
+
+            tr.add_child(td_type);
+            tr.add_child(td_a);
+            tr.add_child(td_size);
+            table.add_child(tr);
+        }
+        body.add_child(table);
+    }
+    if truncated {
+        let mut p = HtmlElement::new("p", HtmlStyle::CanHaveChildren);
+        let mut i = HtmlElement::new("i", HtmlStyle::CanHaveChildren);
+        i.add_text("The search stopped early. Try a more specific term.".to_string());
+        p.add_child(i);
+        body.add_child(p);
+    }
+
+    body.add_child(generate_default_footer());
+    html.add_child(body);
+
+    format!("<!DOCTYPE html>{}", html.render())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+// A WebDAV <response> describing one file or directory.
+fn generate_dav_response(href: String, name: &str, meta: &fs::Metadata) -> HtmlElement {
+    let mut prop = HtmlElement::new("D:prop", HtmlStyle::CanHaveChildren);
+
+    let mut displayname = HtmlElement::new("D:displayname", HtmlStyle::CanHaveChildren);
+    displayname.add_text(xml_escape(name));
+    prop.add_child(displayname);
+
+    let mut resourcetype = HtmlElement::new("D:resourcetype", HtmlStyle::CanHaveChildren);
+    if meta.is_dir() {
+        resourcetype.add_child(HtmlElement::new("D:collection", HtmlStyle::CanHaveChildren));
+    } else {
+        let mut length = HtmlElement::new("D:getcontentlength", HtmlStyle::CanHaveChildren);
+        length.add_text(meta.len().to_string());
+        prop.add_child(length);
+    }
+    prop.add_child(resourcetype);
+
+    if let Ok(mtime) = meta.modified() {
+        let mut modified = HtmlElement::new("D:getlastmodified", HtmlStyle::CanHaveChildren);
+        modified.add_text(timestamp::http_date(mtime));
+        prop.add_child(modified);
+    }
+
+    let mut status = HtmlElement::new("D:status", HtmlStyle::CanHaveChildren);
+    status.add_text("HTTP/1.1 200 OK".to_string());
+
+    let mut propstat = HtmlElement::new("D:propstat", HtmlStyle::CanHaveChildren);
+    propstat.add_child(prop);
+    propstat.add_child(status);
+
+    let mut href_element = HtmlElement::new("D:href", HtmlStyle::CanHaveChildren);
+    href_element.add_text(xml_escape(&href));
+
+    let mut response = HtmlElement::new("D:response", HtmlStyle::CanHaveChildren);
+    response.add_child(href_element);
+    response.add_child(propstat);
+    response
+}
+
+// Renders a PROPFIND response for `path`, along with the entries of a directory when
+// `include_children` is set. `href_path` is the path from `encode_path`, with a trailing '/' for a
+// directory.
+pub fn render_multistatus(
+    href_path: &str,
+    path: &Path,
+    include_children: bool,
+    sort: ListingSort,
+    hide: &[String],
+) -> Result<String, std::io::Error> {
+    let meta = fs::metadata(path)?;
+    let mut multistatus = HtmlElement::new("D:multistatus", HtmlStyle::CanHaveChildren);
+    multistatus.add_attribute("xmlns:D".to_string(), "DAV:".to_string());
+
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => "/".to_string(),
+    };
+    multistatus.add_child(generate_dav_response(
+        format!("/{}", href_path),
+        &name,
+        &meta,
+    ));
+
+    if meta.is_dir() && include_children {
+        for entry in listed_entries(&[path.to_path_buf()], sort, hide).unwrap_or_default() {
+            let entry_meta = match entry.metadata() {
+                Ok(m) => m,
+                _ => {
+                    continue;
+                }
+            };
+            let fname = entry.file_name();
+            let mut href = generate_href(href_path, &percent_encode(fname.as_bytes()));
+            if entry_meta.is_dir() {
+                href.push('/');
+            }
+            multistatus.add_child(generate_dav_response(
+                href,
+                &fname.to_string_lossy(),
+                &entry_meta,
+            ));
+        }
+    }
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>{}",
+        multistatus.render()
+    ))
+}
+
 fn create_viewport_meta() -> HtmlElement {
     let mut meta = HtmlElement::new("meta", HtmlStyle::NoChildren);
     meta.add_attribute("name".to_string(), "viewport".to_string());
@@ -329,7 +979,19 @@ fn create_viewport_meta() -> HtmlElement {
     meta
 }
 
-pub fn render_error(status: &http_core::HttpStatus, msg: Option<String>) -> String {
+pub fn render_error(
+    status: &http_core::HttpStatus,
+    msg: Option<String>,
+    template: Option<&str>,
+) -> String {
+    if let Some(template) = template {
+        // Details are filled in last, so any placeholders they contain are left alone.
+        return template
+            .replace("{{code}}", &http_core::status_to_code(status).to_string())
+            .replace("{{message}}", http_core::status_to_message(status))
+            .replace("{{detail}}", &xml_escape(msg.as_deref().unwrap_or("")));
+    }
+
     let mut html = HtmlElement::new("html", HtmlStyle::CanHaveChildren);
     html.add_attribute("lang".to_string(), "en".to_string());
 
@@ -363,7 +1025,7 @@ pub fn render_error(status: &http_core::HttpStatus, msg: Option<String>) -> Stri
     match msg {
         Some(msg) => {
             let mut p = HtmlElement::new("pre", HtmlStyle::CanHaveChildren);
-            p.add_text(msg.to_string());
+            p.add_text(xml_escape(&msg));
             p.add_class("error");
             body.add_child(p);
         }